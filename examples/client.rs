@@ -18,14 +18,16 @@ fn main() {
     let mut client = App::new();
     client.add_plugins(DefaultPlugins);
     client.add_plugins(bevy_editor_pls::EditorPlugin::default());
-    client.add_plugins(SyncPlugin);
+    client.add_plugins(SyncPlugin::default());
     client.add_plugins(ClientPlugin {
         parameters: SyncConnectionParameters::Socket {
             ip,
             port,
             web_port,
             max_transfer: 100_000_000,
+            asset_transport: AssetTransport::Http,
         },
+        identity: None,
     });
 
     client.sync_component::<Name>();
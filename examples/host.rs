@@ -18,13 +18,14 @@ fn main() {
     let mut host = App::new();
     host.add_plugins(DefaultPlugins);
     host.add_plugins(bevy_editor_pls::EditorPlugin::default());
-    host.add_plugins(SyncPlugin);
+    host.add_plugins(SyncPlugin::default());
     host.add_plugins(ServerPlugin {
         parameters: SyncConnectionParameters::Socket {
             ip,
             port,
             web_port,
             max_transfer: 100_000_000,
+            asset_transport: AssetTransport::Http,
         },
     });
 
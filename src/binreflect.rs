@@ -1,9 +1,14 @@
-use bevy::reflect::{
-    serde::{ReflectDeserializer, ReflectSerializer},
-    DynamicStruct, Reflect, ReflectFromReflect, TypeRegistry,
+use bevy::{
+    asset::{ReflectHandle, UntypedAssetId, UntypedHandle},
+    reflect::{
+        serde::{ReflectDeserializer, ReflectSerializer},
+        DynamicEnum, DynamicStruct, Reflect, ReflectFromReflect, ReflectMut, ReflectRef,
+        TypeRegistry,
+    },
 };
 use bincode::{DefaultOptions, ErrorKind, Options};
 use serde::de::DeserializeSeed;
+use std::error::Error;
 
 pub(crate) fn reflect_to_bin(
     compo: &dyn Reflect,
@@ -17,22 +22,92 @@ pub(crate) fn reflect_to_bin(
 }
 
 pub(crate) fn bin_to_reflect(data: &[u8], registry: &TypeRegistry) -> Box<dyn Reflect> {
+    try_bin_to_reflect(data, registry).unwrap()
+}
+
+/// Like bin_to_reflect, for data that may not be valid.
+pub(crate) fn try_bin_to_reflect(
+    data: &[u8],
+    registry: &TypeRegistry,
+) -> Result<Box<dyn Reflect>, Box<dyn Error + Send + Sync>> {
     let reflect_deserializer = ReflectDeserializer::new(registry);
     let binoptions = DefaultOptions::new()
         .with_fixint_encoding()
         .allow_trailing_bytes();
     let mut bin_deser = bincode::Deserializer::from_slice(data, binoptions);
-    let data = reflect_deserializer.deserialize(&mut bin_deser).unwrap();
+    let data = reflect_deserializer.deserialize(&mut bin_deser)?;
     if !data.is::<DynamicStruct>() {
-        return data;
+        return Ok(data);
     }
     let data = data.downcast::<DynamicStruct>().unwrap();
-    let type_path = data.get_represented_type_info().unwrap().type_path();
-    let registration = registry.get_with_type_path(type_path).unwrap();
+    let type_path = data
+        .get_represented_type_info()
+        .ok_or("value of an unknown type")?
+        .type_path();
     let rfr = registry
-        .get_type_data::<ReflectFromReflect>(registration.type_id())
-        .unwrap();
-    rfr.from_reflect(&*data).unwrap()
+        .get_with_type_path(type_path)
+        .and_then(|registration| {
+            registry.get_type_data::<ReflectFromReflect>(registration.type_id())
+        })
+        .ok_or_else(|| format!("{} cannot be created from reflection", type_path))?;
+    Ok(rfr
+        .from_reflect(&*data)
+        .ok_or_else(|| format!("invalid {}", type_path))?)
+}
+
+/// Replaces the handles found in value, through the ReflectHandle of their type, with weak
+/// handles to the asset ids returned by map. Strong handles cannot be serialized, and assets
+/// without a uuid are referenced by the one they are given on the network.
+pub(crate) fn map_handles(
+    value: &mut dyn Reflect,
+    registry: &TypeRegistry,
+    map: &mut dyn FnMut(UntypedAssetId) -> UntypedAssetId,
+) {
+    let Some(type_id) = value.get_represented_type_info().map(|info| info.type_id()) else {
+        return;
+    };
+    if let Some(reflect_handle) = registry.get_type_data::<ReflectHandle>(type_id) {
+        // values cloned for sync are dynamic, they are converted back to get their handle
+        let handle = reflect_handle
+            .downcast_handle_untyped(value.as_any())
+            .or_else(|| {
+                let concrete = registry
+                    .get_type_data::<ReflectFromReflect>(type_id)?
+                    .from_reflect(value)?;
+                reflect_handle.downcast_handle_untyped(concrete.as_any())
+            });
+        if let Some(handle) = handle {
+            let handle = reflect_handle.typed(UntypedHandle::Weak(map(handle.id())));
+            // applying a variant to a DynamicEnum keeps the variant index of the previous one
+            match (value.downcast_mut::<DynamicEnum>(), handle.reflect_ref()) {
+                (Some(value), ReflectRef::Enum(handle)) => *value = handle.clone_dynamic(),
+                _ => value.apply(&*handle),
+            }
+        }
+        return;
+    }
+    let mut map_field = |field: &mut dyn Reflect| map_handles(field, registry, map);
+    match value.reflect_mut() {
+        ReflectMut::Struct(value) => {
+            (0..value.field_len()).for_each(|i| value.field_at_mut(i).map_or((), &mut map_field))
+        }
+        ReflectMut::TupleStruct(value) => {
+            (0..value.field_len()).for_each(|i| value.field_mut(i).map_or((), &mut map_field))
+        }
+        ReflectMut::Tuple(value) => {
+            (0..value.field_len()).for_each(|i| value.field_mut(i).map_or((), &mut map_field))
+        }
+        ReflectMut::Enum(value) => {
+            (0..value.field_len()).for_each(|i| value.field_at_mut(i).map_or((), &mut map_field))
+        }
+        ReflectMut::List(value) => {
+            (0..value.len()).for_each(|i| value.get_mut(i).map_or((), &mut map_field))
+        }
+        ReflectMut::Array(value) => {
+            (0..value.len()).for_each(|i| value.get_mut(i).map_or((), &mut map_field))
+        }
+        _ => (),
+    }
 }
 
 #[cfg(test)]
@@ -149,4 +224,55 @@ mod test {
         let result = result.downcast::<StandardMaterial>().unwrap();
         assert_eq!(compo.base_color, result.base_color);
     }
+
+    #[test]
+    fn reflect_material_with_strong_texture_handle() {
+        let mut images = Assets::<Image>::default();
+        let id = AssetId::Uuid {
+            uuid: uuid::Uuid::new_v4(),
+        };
+        images.insert(id, Image::default());
+        let texture = images.get_strong_handle(id).unwrap();
+        let mut material = StandardMaterial {
+            base_color_texture: Some(texture.clone()),
+            ..StandardMaterial::default()
+        };
+
+        let mut registry = TypeRegistry::default();
+        registry.register::<StandardMaterial>();
+        registry.register_type_data::<StandardMaterial, ReflectFromReflect>();
+        registry.register_type_data::<Handle<Image>, ReflectHandle>();
+        assert!(reflect_to_bin(material.as_reflect(), &registry).is_err());
+
+        map_handles(material.as_reflect_mut(), &registry, &mut |id| id);
+        let data = reflect_to_bin(material.as_reflect(), &registry).unwrap();
+
+        let result = bin_to_reflect(&data, &registry);
+        let result = result.downcast::<StandardMaterial>().unwrap();
+        assert_eq!(result.base_color_texture.unwrap().id(), id);
+    }
+
+    #[test]
+    fn map_index_handle_of_dynamic_component() {
+        let mut meshes = Assets::<Mesh>::default();
+        let handle = meshes.add(Mesh::from(Cuboid::default()));
+        let uuid = uuid::Uuid::new_v4();
+
+        let mut registry = TypeRegistry::default();
+        registry.register::<Handle<Mesh>>();
+        registry.register_type_data::<Handle<Mesh>, ReflectHandle>();
+        let mut component = handle.clone_value();
+        map_handles(component.as_mut(), &registry, &mut |id| {
+            assert_eq!(id, handle.id().untyped());
+            UntypedAssetId::Uuid {
+                type_id: id.type_id(),
+                uuid,
+            }
+        });
+
+        let data = reflect_to_bin(component.as_reflect(), &registry).unwrap();
+        let result = bin_to_reflect(&data, &registry);
+        let result = Handle::<Mesh>::from_reflect(&*result).unwrap();
+        assert_eq!(result.id(), AssetId::Uuid { uuid });
+    }
 }
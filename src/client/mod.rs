@@ -1,17 +1,16 @@
 use bevy::prelude::*;
-use bevy_renet::renet::{transport::NetcodeClientTransport, DefaultChannel, RenetClient};
 
 use crate::{
     full_sync,
-    lib_priv::{sync_audio_enabled, sync_material_enabled, sync_mesh_enabled, SyncTrackerRes},
+    lib_priv::{sync_asset_enabled, sync_material_enabled, SyncTrackerRes},
+    networking::transport::{ClientTransport, HOST_PEER},
     proto::Message,
-    ClientState,
+    ClientState, JoinCredentials, SyncPeers,
 };
 
 use self::track::{
     entity_created_on_client, entity_parented_on_client, entity_removed_from_client,
-    react_on_changed_audios, react_on_changed_components, react_on_changed_images,
-    react_on_changed_materials, react_on_changed_meshes,
+    react_on_changed_assets, react_on_changed_components, react_on_changed_materials,
 };
 
 mod receiver;
@@ -29,22 +28,19 @@ impl Plugin for ClientSyncPlugin {
         app.add_systems(
             Update,
             set_client_to_connecting
-                .run_if(resource_exists::<RenetClient>)
-                .run_if(resource_added::<NetcodeClientTransport>)
-                .run_if(in_state(ClientState::Disconnected)),
+                .run_if(resource_exists::<ClientTransport>)
+                .run_if(client_not_connected),
         );
         app.add_systems(
             Update,
             verify_client_connected
-                .run_if(resource_exists::<RenetClient>)
-                .run_if(resource_exists::<NetcodeClientTransport>)
+                .run_if(resource_exists::<ClientTransport>)
                 .run_if(in_state(ClientState::Connecting)),
         );
         app.add_systems(
             Update,
             set_client_to_disconnected
-                .run_if(resource_exists::<RenetClient>)
-                .run_if(resource_removed::<NetcodeClientTransport>())
+                .run_if(resource_removed::<ClientTransport>())
                 .run_if(in_state(ClientState::Connected)),
         );
 
@@ -55,51 +51,104 @@ impl Plugin for ClientSyncPlugin {
                 entity_created_on_client,
                 entity_parented_on_client,
                 react_on_changed_components,
-                react_on_changed_materials.run_if(sync_material_enabled),
-                react_on_changed_images.run_if(sync_material_enabled),
-                react_on_changed_meshes.run_if(sync_mesh_enabled),
-                react_on_changed_audios.run_if(sync_audio_enabled),
                 receiver::poll_for_messages,
+                measure_host_network_info,
             )
                 .chain()
-                .run_if(resource_exists::<RenetClient>)
-                .run_if(resource_exists::<NetcodeClientTransport>)
+                .distributive_run_if(resource_exists::<ClientTransport>)
                 .run_if(in_state(ClientState::Connected)),
         );
     }
 }
 
+/// Sends the changes to the materials of type M once they are synched with sync_material.
+pub(crate) fn track_material<M: Material + Reflect>(app: &mut App) {
+    app.add_systems(
+        Update,
+        react_on_changed_materials::<M>
+            .run_if(sync_material_enabled::<M>)
+            .run_if(resource_exists::<ClientTransport>)
+            .run_if(in_state(ClientState::Connected)),
+    );
+}
+
+/// Sends the changes to the assets of type A once they are synched with sync_asset.
+pub(crate) fn track_asset<A: Asset>(app: &mut App) {
+    app.add_systems(
+        Update,
+        react_on_changed_assets::<A>
+            .run_if(sync_asset_enabled::<A>)
+            .run_if(resource_exists::<ClientTransport>)
+            .run_if(in_state(ClientState::Connected)),
+    );
+}
+
 fn set_client_to_disconnected(mut client_state: ResMut<NextState<ClientState>>) {
     info!("Disconnected from server.");
     client_state.set(ClientState::Disconnected);
 }
 
+fn client_not_connected(state: Res<State<ClientState>>) -> bool {
+    matches!(
+        state.get(),
+        ClientState::Disconnected | ClientState::Failed(_)
+    )
+}
+
 fn set_client_to_connecting(mut client_state: ResMut<NextState<ClientState>>) {
     info!("Connecting to server...");
     client_state.set(ClientState::Connecting);
 }
 
+/// The host shares what it measures of the clients, clients measure the host themselves.
+fn measure_host_network_info(client: Res<ClientTransport>, mut sync_peers: ResMut<SyncPeers>) {
+    if let Some(host) = sync_peers.peers.get_mut(&HOST_PEER) {
+        host.network = client.network_info(HOST_PEER).unwrap_or_default();
+    }
+}
+
 fn verify_client_connected(
     mut cmd: Commands,
     mut client_state: ResMut<NextState<ClientState>>,
-    client: ResMut<RenetClient>,
+    client: Res<ClientTransport>,
     mut tracker: ResMut<SyncTrackerRes>,
 ) {
+    if client.is_disconnected() {
+        let reason = client
+            .disconnect_reason()
+            .unwrap_or_else(|| "connection failed".to_string());
+        warn!("Could not connect to server: {}", reason);
+        client_state.set(ClientState::Failed(reason));
+        cmd.remove_resource::<ClientTransport>();
+        return;
+    }
     if !client.is_connected() {
         return;
     }
     info!("Connected to server.");
     client_state.set(ClientState::Connected);
+    cmd.add(|world: &mut World| {
+        let credentials = world
+            .get_resource::<JoinCredentials>()
+            .cloned()
+            .unwrap_or_default();
+        let identity = world.resource::<SyncPeers>().local.clone();
+        let mut client = world.resource_mut::<ClientTransport>();
+        client.broadcast(
+            bincode::serialize(&Message::Join {
+                credentials,
+                identity,
+            })
+            .unwrap(),
+        );
+    });
     if !tracker.host_promotion_in_progress {
         cmd.add(|world: &mut World| {
             info!("Starting new client session and requesting initial sync.");
             world.resource_mut::<ClientPresendInitialSync>().messages =
                 full_sync::build_full_sync(world).unwrap_or_default();
-            let mut client = world.resource_mut::<RenetClient>();
-            client.send_message(
-                DefaultChannel::ReliableOrdered,
-                bincode::serialize(&Message::RequestInitialSync {}).unwrap(),
-            );
+            let mut client = world.resource_mut::<ClientTransport>();
+            client.broadcast(bincode::serialize(&Message::RequestInitialSync {}).unwrap());
         });
     } else {
         // Since no initial sync is being sent and connection completed,
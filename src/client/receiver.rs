@@ -1,8 +1,12 @@
 use crate::{
+    lib_priv::AssetFallback,
     logging::{log_message_received, Who},
-    networking::{assets::SyncAssetTransfer, create_client, create_server},
-    proto::SyncAssetType,
-    InitialSyncFinished, SyncConnectionParameters, SyncEntity,
+    networking::{
+        assets::SyncAssetTransfer, client_startup_failed, create_client, create_server,
+        server_startup_failed, transport::HOST_PEER, with_own_secrets,
+    },
+    ClientId, InitialSyncFinished, JoinRejected, PeerIdentity, PeerInfo, PeerJoined, PeerLeft,
+    PeerNetworkInfo, SyncConnectionParameters, SyncEntity, SyncNetworkConfig, SyncPeers,
 };
 
 use super::*;
@@ -12,11 +16,17 @@ pub(crate) fn poll_for_messages(
     connection_parameters: Res<SyncConnectionParameters>,
     mut track: ResMut<SyncTrackerRes>,
     mut sync_assets: ResMut<SyncAssetTransfer>,
-    mut client: ResMut<RenetClient>,
+    mut client: ResMut<ClientTransport>,
     mut event_sync_finished: EventWriter<InitialSyncFinished>,
 ) {
-    while let Some(message) = client.receive_message(DefaultChannel::ReliableOrdered) {
-        let deser_message = bincode::deserialize(&message).unwrap();
+    while let Some(message) = client.receive(HOST_PEER) {
+        let deser_message = match bincode::deserialize(&message) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Dropping a malformed message from the host: {}", e);
+                continue;
+            }
+        };
         client_received_a_message(
             deser_message,
             &connection_parameters,
@@ -33,7 +43,7 @@ pub(crate) fn poll_for_messages(
 fn client_received_a_message(
     msg: Message,
     connection_parameters: &Res<SyncConnectionParameters>,
-    client: &mut ResMut<RenetClient>,
+    client: &mut ResMut<ClientTransport>,
     track: &mut ResMut<SyncTrackerRes>,
     sync_assets: &mut ResMut<SyncAssetTransfer>,
     cmd: &mut Commands,
@@ -90,56 +100,160 @@ fn client_received_a_message(
                 SyncTrackerRes::apply_component_change_from_network(world, e_id, name, &data);
             });
         }
-        Message::StandardMaterialUpdated { id, material } => cmd.add(move |world: &mut World| {
+        Message::MaterialUpdated { id, material } => cmd.add(move |world: &mut World| {
             SyncTrackerRes::apply_material_change_from_network(id, &material, world);
         }),
-        Message::MeshUpdated { id, url } => sync_assets.request(SyncAssetType::Mesh, id, url),
-        Message::ImageUpdated { id, url } => sync_assets.request(SyncAssetType::Image, id, url),
-        Message::AudioUpdated { id, url } => sync_assets.request(SyncAssetType::Audio, id, url),
+        Message::AssetUpdated {
+            asset_type,
+            id,
+            url,
+            hash,
+            path,
+        } => {
+            if !track.is_asset_synced(&asset_type) {
+                return;
+            }
+            match path {
+                Some(path) => cmd.add(move |world: &mut World| {
+                    let fallback = AssetFallback {
+                        asset_type,
+                        url,
+                        hash,
+                        peer: HOST_PEER,
+                    };
+                    SyncTrackerRes::apply_asset_path_from_network(world, id, path, fallback);
+                }),
+                None => sync_assets.request(&asset_type, id, url, hash, HOST_PEER),
+            }
+        }
+        Message::AssetRemoved { asset_type, id } => cmd.add(move |world: &mut World| {
+            SyncTrackerRes::apply_asset_removal_from_network(world, &asset_type, id);
+        }),
+        Message::AssetRequest { asset_type, id } => {
+            sync_assets.respond_over_connection(HOST_PEER, &asset_type, id)
+        }
+        Message::AssetChunk {
+            asset_type,
+            id,
+            offset,
+            total,
+            bytes,
+        } => sync_assets.receive_chunk(&asset_type, id, offset, total, bytes),
         Message::PromoteToHost => {
             info!("Promotion: Client is being promoted to host");
-            let sync_connection_parameters = connection_parameters.as_ref();
-            match sync_connection_parameters {
-                SyncConnectionParameters::Socket {
-                    ip,
-                    port,
-                    web_port: _,
-                    max_transfer: _,
-                } => {
-                    let ip = *ip;
-                    let port = *port;
-                    cmd.add(move |world: &mut World| {
-                        info!("Promotion: Starting as host...");
-                        world.insert_resource(create_server(ip, port));
+            let mut params = connection_parameters.as_ref().clone();
+            cmd.add(move |world: &mut World| {
+                info!("Promotion: Starting as host...");
+                let config = world.resource::<SyncNetworkConfig>().clone();
+                match create_server(&mut params, &config) {
+                    Ok(server) => {
+                        world.insert_resource(server);
+                        // the new host announces the port it actually bound
+                        world.insert_resource(params);
                         world
                             .resource_mut::<SyncTrackerRes>()
                             .host_promotion_in_progress = true;
-                    });
+                    }
+                    Err(e) => server_startup_failed(world, e.to_string()),
                 }
-            }
+            });
         }
         Message::NewHost { params } => {
-            match params {
-                SyncConnectionParameters::Socket {
-                    ip,
-                    port,
-                    web_port: _,
-                    max_transfer: _,
-                } => {
-                    info!("Promotion: A new host has been promoted. Reconnecting to new host");
-                    client.disconnect();
-                    cmd.remove_resource::<NetcodeClientTransport>();
-                    cmd.insert_resource(create_client(ip, port));
-                    // even if it was a client before, this connection is not a new session
-                    // and won't need the initial_sync, so it's consider a client to client promotion
-                    track.host_promotion_in_progress = true;
+            info!("Promotion: A new host has been promoted. Reconnecting to new host");
+            client.disconnect(HOST_PEER);
+            cmd.remove_resource::<ClientTransport>();
+            let params = with_own_secrets(&params, connection_parameters);
+            cmd.add(move |world: &mut World| {
+                // the new host will send its own list of peers once joined
+                let mut peers = world.resource_mut::<SyncPeers>();
+                peers.peers.clear();
+                let identity = peers.local.clone();
+                let config = world.resource::<SyncNetworkConfig>().clone();
+                match create_client(&params, &identity, &config) {
+                    Ok(client) => world.insert_resource(client),
+                    Err(e) => client_startup_failed(world, e.to_string()),
                 }
-            }
+            });
+            // even if it was a client before, this connection is not a new session
+            // and won't need the initial_sync, so it's consider a client to client promotion
+            track.host_promotion_in_progress = true;
+        }
+        Message::JoinRejected { reason } => {
+            warn!("Host rejected this client: {}", reason);
+            client.disconnect(HOST_PEER);
+            cmd.remove_resource::<ClientTransport>();
+            cmd.add(move |world: &mut World| {
+                world.send_event(JoinRejected { reason });
+            });
         }
-        // Nothing to do, only servers send initial sync
-        Message::RequestInitialSync => {}
+        Message::PeerList { peers } => cmd.add(move |world: &mut World| {
+            let listed: Vec<ClientId> = peers
+                .iter()
+                .map(|(client_id, _)| ClientId::from_raw(*client_id))
+                .collect();
+            world
+                .resource_mut::<SyncPeers>()
+                .peers
+                .retain(|client_id, _| listed.contains(client_id));
+            for (client_id, identity) in peers {
+                peer_joined(world, ClientId::from_raw(client_id), identity);
+            }
+        }),
+        Message::PeerJoined {
+            client_id,
+            identity,
+        } => cmd.add(move |world: &mut World| {
+            peer_joined(world, ClientId::from_raw(client_id), identity);
+        }),
+        Message::PeerLeft { client_id, reason } => cmd.add(move |world: &mut World| {
+            let client_id = ClientId::from_raw(client_id);
+            let Some(peer) = world.resource_mut::<SyncPeers>().peers.remove(&client_id) else {
+                return;
+            };
+            world.send_event(PeerLeft {
+                client_id,
+                identity: peer.identity,
+                reason,
+            });
+        }),
+        Message::PeerNetworkInfo { peers } => cmd.add(move |world: &mut World| {
+            let mut sync_peers = world.resource_mut::<SyncPeers>();
+            for (client_id, network) in peers {
+                if let Some(peer) = sync_peers.peers.get_mut(&ClientId::from_raw(client_id)) {
+                    peer.network = network;
+                }
+            }
+        }),
+        // Nothing to do, only servers send initial sync or approve joins
+        Message::RequestInitialSync
+        | Message::Join {
+            credentials: _,
+            identity: _,
+        } => {}
         Message::FinishedInitialSync => {
             event_sync_finished.send(InitialSyncFinished);
         }
     }
 }
+
+fn peer_joined(world: &mut World, client_id: ClientId, identity: PeerIdentity) {
+    let mut sync_peers = world.resource_mut::<SyncPeers>();
+    if sync_peers
+        .peers
+        .get(&client_id)
+        .is_some_and(|peer| peer.identity == identity)
+    {
+        return;
+    }
+    sync_peers.peers.insert(
+        client_id,
+        PeerInfo {
+            identity: identity.clone(),
+            network: PeerNetworkInfo::default(),
+        },
+    );
+    world.send_event(PeerJoined {
+        client_id,
+        identity,
+    });
+}
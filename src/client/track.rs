@@ -1,15 +1,17 @@
 use bevy::{prelude::*, utils::HashSet};
-use bevy_renet::renet::{DefaultChannel, RenetClient};
 use uuid::Uuid;
 
 use crate::{
-    binreflect::reflect_to_bin, lib_priv::SyncTrackerRes, networking::assets::SyncAssetTransfer,
-    proto::Message, SyncEntity, SyncMark,
+    binreflect::reflect_to_bin,
+    lib_priv::SyncTrackerRes,
+    networking::{assets::SyncAssetTransfer, transport::ClientTransport},
+    proto::Message,
+    SyncEntity, SyncMark,
 };
 
 pub(crate) fn entity_created_on_client(
     mut track: ResMut<SyncTrackerRes>,
-    mut client: ResMut<RenetClient>,
+    mut client: ResMut<ClientTransport>,
     mut query: Query<Entity, Added<SyncMark>>,
     mut cmd: Commands,
 ) {
@@ -17,10 +19,7 @@ pub(crate) fn entity_created_on_client(
         let uuid = Uuid::new_v4();
         track.uuid_to_entity.insert(uuid, id);
         track.entity_to_uuid.insert(id, uuid);
-        client.send_message(
-            DefaultChannel::ReliableOrdered,
-            bincode::serialize(&Message::EntitySpawn { id: uuid }).unwrap(),
-        );
+        client.broadcast(bincode::serialize(&Message::EntitySpawn { id: uuid }).unwrap());
         cmd.entity(id)
             .remove::<SyncMark>()
             .insert(SyncEntity { uuid });
@@ -29,7 +28,7 @@ pub(crate) fn entity_created_on_client(
 }
 
 pub(crate) fn entity_parented_on_client(
-    mut client: ResMut<RenetClient>,
+    mut client: ResMut<ClientTransport>,
     query: Query<(&Parent, &SyncEntity), Changed<Parent>>,
     query_parent: Query<(Entity, &SyncEntity), With<Children>>,
 ) {
@@ -37,8 +36,7 @@ pub(crate) fn entity_parented_on_client(
         let Ok(parent) = query_parent.get(p.get()) else {
             continue;
         };
-        client.send_message(
-            DefaultChannel::ReliableOrdered,
+        client.broadcast(
             bincode::serialize(&Message::EntityParented {
                 entity_id: sup.uuid,
                 parent_id: parent.1.uuid,
@@ -49,7 +47,7 @@ pub(crate) fn entity_parented_on_client(
 }
 
 pub(crate) fn entity_removed_from_client(
-    mut client: ResMut<RenetClient>,
+    mut client: ResMut<ClientTransport>,
     mut track: ResMut<SyncTrackerRes>,
     query: Query<Entity, With<SyncEntity>>,
 ) {
@@ -63,20 +61,18 @@ pub(crate) fn entity_removed_from_client(
         }
     });
     for &id in despawned_entities.iter() {
-        client.send_message(
-            DefaultChannel::ReliableOrdered,
-            bincode::serialize(&Message::EntityDelete { id }).unwrap(),
-        );
+        client.broadcast(bincode::serialize(&Message::EntityDelete { id }).unwrap());
     }
 }
 
 pub(crate) fn react_on_changed_components(
     registry: Res<AppTypeRegistry>,
-    mut client: ResMut<RenetClient>,
+    mut client: ResMut<ClientTransport>,
     mut track: ResMut<SyncTrackerRes>,
 ) {
     let registry = registry.read();
-    while let Some(change) = track.changed_components_to_send.pop_front() {
+    while let Some(mut change) = track.changed_components_to_send.pop_front() {
+        track.handles_to_network(change.data.as_reflect_mut(), &registry);
         let bin = match reflect_to_bin(change.data.as_reflect(), &registry) {
             Ok(bin) => bin,
             Err(e) => {
@@ -87,8 +83,7 @@ pub(crate) fn react_on_changed_components(
                 continue;
             }
         };
-        client.send_message(
-            DefaultChannel::ReliableOrdered,
+        client.broadcast(
             bincode::serialize(&Message::ComponentUpdated {
                 id: change.change_id.id,
                 name: change.change_id.name,
@@ -99,133 +94,122 @@ pub(crate) fn react_on_changed_components(
     }
 }
 
-pub(crate) fn react_on_changed_materials(
+pub(crate) fn react_on_changed_materials<M: Material + Reflect>(
     mut track: ResMut<SyncTrackerRes>,
     registry: Res<AppTypeRegistry>,
-    mut client: ResMut<RenetClient>,
-    materials: Res<Assets<StandardMaterial>>,
-    mut events: EventReader<AssetEvent<StandardMaterial>>,
+    mut client: ResMut<ClientTransport>,
+    materials: Res<Assets<M>>,
+    mut events: EventReader<AssetEvent<M>>,
 ) {
     let registry = registry.read();
+    let mut changed = track.take_newly_mapped::<M>();
     for event in &mut events.read() {
         match event {
             AssetEvent::Added { id } | AssetEvent::Modified { id } => {
-                let Some(material) = materials.get(*id) else {
-                    continue;
-                };
-                let AssetId::Uuid { uuid: id } = id else {
+                let Some(uuid) = track.known_uuid(id.untyped()) else {
                     continue;
                 };
-                if track.skip_network_handle_change(*id) {
+                if track.skip_network_handle_change(uuid) {
                     continue;
                 }
-                let Ok(bin) = reflect_to_bin(material.as_reflect(), &registry) else {
-                    continue;
-                };
-                client.send_message(
-                    DefaultChannel::ReliableOrdered,
-                    bincode::serialize(&Message::StandardMaterialUpdated {
-                        id: *id,
-                        material: bin,
-                    })
-                    .unwrap(),
-                );
+                if !changed.contains(&(*id, uuid)) {
+                    changed.push((*id, uuid));
+                }
             }
-            AssetEvent::Removed { id: _ } => {}
-            _ => (),
-        }
-    }
-}
-
-pub(crate) fn react_on_changed_audios(
-    mut track: ResMut<SyncTrackerRes>,
-    mut sync_asset: ResMut<SyncAssetTransfer>,
-    mut client: ResMut<RenetClient>,
-    assets: Res<Assets<AudioSource>>,
-    mut events: EventReader<AssetEvent<AudioSource>>,
-) {
-    for event in &mut events.read() {
-        match event {
-            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
-                let Some(asset) = assets.get(*id) else {
+            AssetEvent::Removed { id } => {
+                let Some(uuid) = track.forget_asset_id(id.untyped()) else {
                     continue;
                 };
-                let AssetId::Uuid { uuid: id } = id else {
-                    continue;
-                };
-                if track.skip_network_handle_change(*id) {
+                if track.skip_network_handle_change(uuid) {
                     continue;
                 }
-                let url = sync_asset.serve_audio(id, asset);
-                client.send_message(
-                    DefaultChannel::ReliableOrdered,
-                    bincode::serialize(&Message::AudioUpdated { id: *id, url }).unwrap(),
-                );
+                let msg = &Message::AssetRemoved {
+                    asset_type: M::type_path().to_string(),
+                    id: uuid,
+                };
+                client.broadcast(bincode::serialize(msg).unwrap());
             }
-            AssetEvent::Removed { id: _ } => {}
             _ => (),
         }
     }
+    for (id, uuid) in changed {
+        let Some(material) = materials.get(id) else {
+            continue;
+        };
+        let mut material = material.clone();
+        track.handles_to_network(material.as_reflect_mut(), &registry);
+        let Ok(bin) = reflect_to_bin(material.as_reflect(), &registry) else {
+            continue;
+        };
+        let msg = &Message::MaterialUpdated {
+            id: uuid,
+            material: bin,
+        };
+        client.broadcast(bincode::serialize(msg).unwrap());
+    }
 }
 
-pub(crate) fn react_on_changed_meshes(
+pub(crate) fn react_on_changed_assets<A: Asset>(
     mut track: ResMut<SyncTrackerRes>,
-    mut sync_asset: ResMut<SyncAssetTransfer>,
-    mut client: ResMut<RenetClient>,
-    assets: Res<Assets<Mesh>>,
-    mut events: EventReader<AssetEvent<Mesh>>,
+    mut client: ResMut<ClientTransport>,
+    assets: Res<Assets<A>>,
+    asset_server: Res<AssetServer>,
+    mut events: EventReader<AssetEvent<A>>,
+    mut sync_assets: ResMut<SyncAssetTransfer>,
 ) {
+    let mut changed = track.take_newly_mapped::<A>();
+    let mut modified = HashSet::new();
     for event in &mut events.read() {
         match event {
             AssetEvent::Added { id } | AssetEvent::Modified { id } => {
-                let Some(mesh) = assets.get(*id) else {
-                    continue;
-                };
-                let AssetId::Uuid { uuid: id } = id else {
+                let Some(uuid) = track.known_uuid(id.untyped()) else {
                     continue;
                 };
-                if track.skip_network_handle_change(*id) {
+                if let AssetEvent::Modified { .. } = event {
+                    modified.insert(*id);
+                }
+                if track.skip_network_handle_change(uuid) {
                     continue;
                 }
-                let url = sync_asset.serve_mesh(id, mesh);
-                client.send_message(
-                    DefaultChannel::ReliableOrdered,
-                    bincode::serialize(&Message::MeshUpdated { id: *id, url }).unwrap(),
-                );
+                if !changed.contains(&(*id, uuid)) {
+                    changed.push((*id, uuid));
+                }
             }
-            AssetEvent::Removed { id: _ } => {}
-            _ => (),
-        }
-    }
-}
-
-pub(crate) fn react_on_changed_images(
-    mut track: ResMut<SyncTrackerRes>,
-    mut sync_asset: ResMut<SyncAssetTransfer>,
-    mut client: ResMut<RenetClient>,
-    assets: Res<Assets<Image>>,
-    mut events: EventReader<AssetEvent<Image>>,
-) {
-    for event in &mut events.read() {
-        match event {
-            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
-                let Some(image) = assets.get(*id) else {
+            AssetEvent::Removed { id } => {
+                let Some(uuid) = track.forget_asset_id(id.untyped()) else {
                     continue;
                 };
-                let AssetId::Uuid { uuid: id } = id else {
-                    continue;
-                };
-                if track.skip_network_handle_change(*id) {
+                if track.skip_network_handle_change(uuid) {
                     continue;
                 }
-                let url = sync_asset.serve_image(id, image);
-                client.send_message(
-                    DefaultChannel::ReliableOrdered,
-                    bincode::serialize(&Message::ImageUpdated { id: *id, url }).unwrap(),
-                );
+                sync_assets.forget(A::type_path(), &uuid);
+                let msg = &Message::AssetRemoved {
+                    asset_type: A::type_path().to_string(),
+                    id: uuid,
+                };
+                client.broadcast(bincode::serialize(msg).unwrap());
             }
-            AssetEvent::Removed { id: _ } => {}
             _ => (),
         }
     }
+    for (id, uuid) in changed {
+        let Some(asset) = assets.get(id) else {
+            continue;
+        };
+        let Some((url, hash)) = sync_assets.serve(&mut track, &uuid, asset) else {
+            continue;
+        };
+        let msg = &Message::AssetUpdated {
+            asset_type: A::type_path().to_string(),
+            id: uuid,
+            url,
+            hash,
+            // modified assets may not match their file anymore
+            path: match modified.contains(&id) {
+                true => None,
+                false => track.asset_path_to_network(&asset_server, id),
+            },
+        };
+        client.broadcast(bincode::serialize(msg).unwrap());
+    }
 }
@@ -5,6 +5,7 @@ use crate::{
     lib_priv::{SkinnedMeshSyncMapper, SyncTrackerRes},
     networking::assets::SyncAssetTransfer,
     proto::Message,
+    scene::SceneNode,
     SyncEntity,
 };
 use bevy::{
@@ -13,22 +14,25 @@ use bevy::{
     render::mesh::skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
     utils::HashSet,
 };
-use uuid::Uuid;
 
 pub(crate) fn build_full_sync(world: &mut World) -> Result<Vec<Message>, Box<dyn Error>> {
     let mut result: Vec<Message> = Vec::new();
-    check_entity_components(world, &mut result)?;
+    world.resource_scope(|world, mut track: Mut<SyncTrackerRes>| {
+        check_entity_components(world, &mut track, &mut result)
+    })?;
     check_parents(world, &mut result)?;
-    check_images(world, &mut result)?;
-    check_materials(world, &mut result)?;
-    check_meshes(world, &mut result)?;
-    check_audios(world, &mut result)?;
+    // materials first, the assets referenced by their handles are given a uuid to be sent
+    check_synced_materials(world, &mut result);
+    check_synced_assets(world, &mut result);
     Ok(result)
 }
 
-fn check_entity_components(world: &World, result: &mut Vec<Message>) -> Result<(), Box<dyn Error>> {
+fn check_entity_components(
+    world: &World,
+    track: &mut SyncTrackerRes,
+    result: &mut Vec<Message>,
+) -> Result<(), Box<dyn Error>> {
     let mut entity_ids_sent: HashSet<Entity> = HashSet::new();
-    let track = world.resource::<SyncTrackerRes>();
     let registry = world.resource::<AppTypeRegistry>();
     let registry = registry.read();
     let sync_down_id = world
@@ -50,10 +54,11 @@ fn check_entity_components(world: &World, result: &mut Vec<Message>) -> Result<(
             }
         }
 
-        for c_id in arch
+        let synced_components: Vec<_> = arch
             .components()
-            .filter(|&c_id| track.registered_componets_for_sync.contains(&c_id))
-        {
+            .filter(|c_id| track.registered_componets_for_sync.contains(c_id))
+            .collect();
+        for c_id in synced_components {
             let c_exclude_id = track
                 .sync_exclude_cid_of_component_cid
                 .get(&c_id)
@@ -75,6 +80,15 @@ fn check_entity_components(world: &World, result: &mut Vec<Message>) -> Result<(
             for arch_entity in arch.entities() {
                 let entity = world.entity(arch_entity.id());
                 let e_id = entity.id();
+                if let Some(node) = entity.get::<SceneNode>() {
+                    // peers instance the scene themselves, only the changes to it are sent
+                    let changed = entity.get_change_ticks_by_id(c_id).is_some_and(|ticks| {
+                        ticks.is_changed(node.tracked_at, world.read_change_tick())
+                    });
+                    if !changed {
+                        continue;
+                    }
+                }
                 let component = reflect_component.reflect(entity).ok_or("not registered")?;
                 let type_name = if component.type_id() == TypeId::of::<SkinnedMesh>() {
                     SkinnedMeshSyncMapper::default()
@@ -83,7 +97,7 @@ fn check_entity_components(world: &World, result: &mut Vec<Message>) -> Result<(
                 } else {
                     type_name.to_string()
                 };
-                let component = if component.type_id() == TypeId::of::<SkinnedMesh>() {
+                let mut component = if component.type_id() == TypeId::of::<SkinnedMesh>() {
                     debug!("Initial sync: Converting SkinnedMesh to SkinnedMeshSyncMapper");
                     let compo = track
                         .to_skinned_mapper(
@@ -95,6 +109,7 @@ fn check_entity_components(world: &World, result: &mut Vec<Message>) -> Result<(
                 } else {
                     component.clone_value()
                 };
+                track.handles_to_network(component.as_reflect_mut(), &registry);
                 let compo_bin = match reflect_to_bin(component.as_reflect(), &registry) {
                     Ok(compo_bin) => compo_bin,
                     Err(e) => {
@@ -156,81 +171,71 @@ fn check_parents(world: &World, result: &mut Vec<Message>) -> Result<(), Box<dyn
     Ok(())
 }
 
-fn check_materials(world: &World, result: &mut Vec<Message>) -> Result<(), Box<dyn Error>> {
-    let track = world.resource::<SyncTrackerRes>();
-    let registry = world.resource::<AppTypeRegistry>();
-    let registry = registry.read();
-    if track.sync_materials {
-        let materials = world.resource::<Assets<StandardMaterial>>();
+fn check_synced_materials(world: &mut World, result: &mut Vec<Message>) {
+    let full_syncs: Vec<_> = world
+        .resource::<SyncTrackerRes>()
+        .synced_materials
+        .values()
+        .filter(|synced| synced.enabled)
+        .map(|synced| synced.full_sync)
+        .collect();
+    for full_sync in full_syncs {
+        full_sync(world, result);
+    }
+}
+
+pub(crate) fn check_materials<M: Material + Reflect>(world: &mut World, result: &mut Vec<Message>) {
+    world.resource_scope(|world, mut track: Mut<SyncTrackerRes>| {
+        let registry = world.resource::<AppTypeRegistry>();
+        let registry = registry.read();
+        let materials = world.resource::<Assets<M>>();
         for (id, material) in materials.iter() {
-            let AssetId::Uuid { uuid: id } = id else {
+            let Some(id) = track.known_uuid(id.untyped()) else {
                 continue;
             };
+            let mut material = material.clone();
+            track.handles_to_network(material.as_reflect_mut(), &registry);
             let Ok(bin) = reflect_to_bin(material.as_reflect(), &registry) else {
                 continue;
             };
-            result.push(Message::StandardMaterialUpdated { id, material: bin });
-        }
-    }
-    Ok(())
-}
-
-fn check_audios(world: &mut World, result: &mut Vec<Message>) -> Result<(), Box<dyn Error>> {
-    let track = world.resource_mut::<SyncTrackerRes>();
-    let mut audios_to_add = Vec::<(Uuid, AudioSource)>::new();
-    if track.sync_audios {
-        let audios = world.resource::<Assets<AudioSource>>();
-        for (id, audio) in audios.iter() {
-            let AssetId::Uuid { uuid: id } = id else {
-                continue;
-            };
-            audios_to_add.push((id, audio.clone()));
+            result.push(Message::MaterialUpdated { id, material: bin });
         }
-    }
-    let mut sync_assets = world.resource_mut::<SyncAssetTransfer>();
-    for (id, audio) in audios_to_add.iter() {
-        let url = sync_assets.serve_audio(id, audio);
-        result.push(Message::AudioUpdated { id: *id, url });
-    }
-    Ok(())
+    });
 }
 
-fn check_meshes(world: &mut World, result: &mut Vec<Message>) -> Result<(), Box<dyn Error>> {
-    let track = world.resource_mut::<SyncTrackerRes>();
-    let mut meshes_to_add = Vec::<(Uuid, Mesh)>::new();
-    if track.sync_meshes {
-        let meshes = world.resource::<Assets<Mesh>>();
-        for (id, mesh) in meshes.iter() {
-            let AssetId::Uuid { uuid: id } = id else {
-                continue;
-            };
-            meshes_to_add.push((id, mesh.clone()));
-        }
-    }
-    let mut sync_assets = world.resource_mut::<SyncAssetTransfer>();
-    for (id, mesh) in meshes_to_add.iter() {
-        let url = sync_assets.serve_mesh(id, mesh);
-        result.push(Message::MeshUpdated { id: *id, url });
+fn check_synced_assets(world: &mut World, result: &mut Vec<Message>) {
+    let full_syncs: Vec<_> = world
+        .resource::<SyncTrackerRes>()
+        .synced_assets
+        .values()
+        .filter(|synced| synced.enabled)
+        .map(|synced| synced.full_sync)
+        .collect();
+    for full_sync in full_syncs {
+        full_sync(world, result);
     }
-    Ok(())
 }
 
-fn check_images(world: &mut World, result: &mut Vec<Message>) -> Result<(), Box<dyn Error>> {
-    let track = world.resource_mut::<SyncTrackerRes>();
-    let mut images_to_add = Vec::<(Uuid, Image)>::new();
-    if track.sync_materials {
-        let images = world.resource::<Assets<Image>>();
-        for (id, image) in images.iter() {
-            let AssetId::Uuid { uuid: id } = id else {
-                continue;
-            };
-            images_to_add.push((id, image.clone()));
-        }
-    }
-    let mut sync_assets = world.resource_mut::<SyncAssetTransfer>();
-    for (id, image) in images_to_add.iter() {
-        let url = sync_assets.serve_image(id, image);
-        result.push(Message::ImageUpdated { id: *id, url });
-    }
-    Ok(())
+pub(crate) fn check_assets<A: Asset>(world: &mut World, result: &mut Vec<Message>) {
+    world.resource_scope(|world, mut sync_assets: Mut<SyncAssetTransfer>| {
+        world.resource_scope(|world, mut track: Mut<SyncTrackerRes>| {
+            let asset_server = world.resource::<AssetServer>();
+            for (id, asset) in world.resource::<Assets<A>>().iter() {
+                let path = track.asset_path_to_network(asset_server, id);
+                let Some(id) = track.known_uuid(id.untyped()) else {
+                    continue;
+                };
+                let Some((url, hash)) = sync_assets.serve(&mut track, &id, asset) else {
+                    continue;
+                };
+                result.push(Message::AssetUpdated {
+                    asset_type: A::type_path().to_string(),
+                    id,
+                    url,
+                    hash,
+                    path,
+                });
+            }
+        });
+    });
 }
@@ -3,13 +3,26 @@
 //! Plugin for synchronizing entities and components between server and its clients.
 
 use ::serde::{Deserialize, Serialize};
+pub use bevy_renet::renet::ClientId;
+/// Channel layout used by SyncNetworkConfig
+pub use bevy_renet::renet::{ChannelConfig, SendType};
+/// Network layer abstraction, implement SyncTransport to plug other transports
+pub use networking::transport::{
+    ClientTransport, PeerNetworkInfo, ServerTransport, SyncTransport, TransportEvent, HOST_PEER,
+};
+/// Helpers for the auth service of SecureSocket sessions
+pub use networking::{generate_connect_token, generate_private_key};
 /// Use this event to promote one of the clients as host
 pub use proto::PromoteToHostEvent;
 pub use uuid::Uuid;
 pub mod prelude {
     pub use super::{
-        proto::PromoteToHostEvent, ClientPlugin, ClientState, ServerPlugin, ServerState,
-        SyncComponent, SyncConnectionParameters, SyncEntity, SyncExclude, SyncMark, SyncPlugin,
+        proto::PromoteToHostEvent, AssetCompression, AssetTransferFailed, AssetTransport,
+        ClientPlugin, ClientState, DiscoveredSession, DiscoveredSessions, DiscoveryPlugin,
+        JoinApproval, JoinCredentials, JoinRejected, JoinSession, LeaveSession, PeerIdentity,
+        PeerInfo, PeerJoined, PeerLeft, ServerPlugin, ServerState, StartHosting, SyncAsset,
+        SyncComponent, SyncConnectionParameters, SyncEntity, SyncExclude, SyncMark,
+        SyncNetworkConfig, SyncPeers, SyncPlugin, SyncStartupError,
     };
 }
 
@@ -21,10 +34,19 @@ mod lib_priv;
 mod logging;
 mod networking;
 mod proto;
+mod scene;
 mod server;
 
-use bevy::{prelude::*, reflect::*};
-use std::{marker::PhantomData, net::IpAddr};
+use bevy::{prelude::*, reflect::*, render::render_resource::VertexFormat, utils::HashMap};
+use bevy_renet::renet::DefaultChannel;
+use std::{
+    error::Error,
+    fmt,
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    time::Duration,
+};
 
 /// Use this component to mark which entities to be synched.
 /// This component will be replaced with SyncEntity once the system engages on it.
@@ -51,19 +73,181 @@ pub struct SyncExclude<T: Component> {
 }
 
 /// Specify networking options to create a session. This will also be available as a resource.
-#[derive(Serialize, Deserialize, Debug, Resource, Clone)]
+/// Ports of 0 are bound to any free port, the resource then holds the ports actually bound.
+#[derive(Serialize, Deserialize, Resource, Clone)]
 pub enum SyncConnectionParameters {
     Socket {
         ip: IpAddr,
         port: u16,
         web_port: u16,
         max_transfer: usize,
+        asset_transport: AssetTransport,
     },
+    /// Links apps living in the same process through channels, no sockets are opened.
+    /// Hosts and clients using the same name join the same session.
+    InMemory { name: String },
+    /// Exchanges messages over a WebSocket, such as `ws://127.0.0.1:4000`.
+    /// The host listens on the address of the url, clients connect to it.
+    /// Hosts may use port 0 as well, the url is then updated with the bound port.
+    /// Assets are sent in chunks over the same connection, no other port is opened.
+    /// Browsers join with this transport when built for wasm32, hosting stays native.
+    WebSocket { url: String },
+    /// Same as Socket but only clients holding a valid connect token can join.
+    /// Hosts use the private key shared with the service issuing the tokens, see
+    /// generate_connect_token, while clients use the token they were given.
+    /// Only clients given the private key as well can be promoted to host. They keep their
+    /// token to reconnect to a promoted host, so it must list the address of every client that
+    /// may be promoted.
+    /// Secrets are neither serialized nor printed, so they are never sent to other peers.
+    SecureSocket {
+        ip: IpAddr,
+        port: u16,
+        web_port: u16,
+        max_transfer: usize,
+        asset_transport: AssetTransport,
+        #[serde(skip)]
+        private_key: Option<[u8; 32]>,
+        #[serde(skip)]
+        connect_token: Vec<u8>,
+    },
+}
+
+impl fmt::Debug for SyncConnectionParameters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Socket {
+                ip,
+                port,
+                web_port,
+                max_transfer,
+                asset_transport,
+            } => f
+                .debug_struct("Socket")
+                .field("ip", ip)
+                .field("port", port)
+                .field("web_port", web_port)
+                .field("max_transfer", max_transfer)
+                .field("asset_transport", asset_transport)
+                .finish(),
+            Self::InMemory { name } => f.debug_struct("InMemory").field("name", name).finish(),
+            Self::WebSocket { url } => f.debug_struct("WebSocket").field("url", url).finish(),
+            Self::SecureSocket {
+                ip,
+                port,
+                web_port,
+                max_transfer,
+                asset_transport,
+                ..
+            } => f
+                .debug_struct("SecureSocket")
+                .field("ip", ip)
+                .field("port", port)
+                .field("web_port", web_port)
+                .field("max_transfer", max_transfer)
+                .field("asset_transport", asset_transport)
+                .field("private_key", &"<redacted>")
+                .field("connect_token", &"<redacted>")
+                .finish(),
+        }
+    }
+}
+
+/// How assets are transferred in Socket and SecureSocket sessions.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AssetTransport {
+    /// Every peer serves its assets over http on its web_port.
+    #[default]
+    Http,
+    /// Assets are sent in chunks over the session connection, on the asset channel of
+    /// SyncNetworkConfig. Only the session port needs to be reachable, web_port is unused.
+    Connection,
+}
+
+/// Tuning of the connections, insert it as a resource before adding ServerPlugin or
+/// ClientPlugin, otherwise the default one is used.
+/// Hosts and clients must agree on the protocol id and the channels.
+#[derive(Resource, Debug, Clone)]
+pub struct SyncNetworkConfig {
+    /// Clients accepted at the same time by a host.
+    pub max_clients: usize,
+    /// Identifies the application for netcode, peers with another id are refused.
+    pub protocol_id: u64,
+    /// Bytes each netcode connection may send per tick.
+    pub available_bytes_per_tick: u64,
+    /// Netcode channels of every connection, with their memory budget and resend time.
+    pub channels: Vec<ChannelConfig>,
+    /// Channel carrying bevy_sync messages, it must be a reliable ordered one of channels or
+    /// hosting and joining fail with a SyncStartupError.
+    pub sync_channel: u8,
+    /// Channel carrying the asset chunks of AssetTransport::Connection sessions, it must then
+    /// be a reliable one of channels. Keeping it apart from sync_channel lets the sync messages
+    /// through while large assets are sent.
+    pub asset_channel: u8,
+    /// Threads answering the asset requests of peers.
+    pub asset_server_threads: usize,
+    /// Assets downloaded from peers at the same time.
+    pub asset_download_threads: usize,
+    /// Longest time an asset download attempt may take, and a response to a peer may wait for
+    /// it to read. Transfers still in progress when leaving a session end with it in the
+    /// background. Assets sent over the connection fail once nothing of them was received for
+    /// as long.
+    pub asset_download_timeout: Duration,
+    /// Further attempts after a download fails, each one resuming where the last stopped.
+    pub asset_download_retries: u32,
+    /// Wait before the first retry, doubled on each of the following ones.
+    pub asset_download_backoff: Duration,
+    /// Directory keeping downloaded assets by content hash across sessions, so that assets
+    /// announced again with the same content are not downloaded again. None disables it.
+    pub asset_cache_dir: Option<PathBuf>,
+    /// Size the cache directory is kept under, evicting the least recently used assets.
+    pub asset_cache_max_bytes: u64,
+    /// Time a client has to ask to join once connected, the host disconnects it otherwise.
+    pub join_timeout: Duration,
+}
+
+impl Default for SyncNetworkConfig {
+    fn default() -> Self {
+        Self {
+            max_clients: 64,
+            protocol_id: 1,
+            available_bytes_per_tick: 60_000,
+            channels: DefaultChannel::config(),
+            sync_channel: DefaultChannel::ReliableOrdered.into(),
+            asset_channel: DefaultChannel::ReliableUnordered.into(),
+            asset_server_threads: 4,
+            asset_download_threads: 8,
+            asset_download_timeout: Duration::from_secs(30),
+            asset_download_retries: 3,
+            asset_download_backoff: Duration::from_millis(500),
+            asset_cache_dir: None,
+            asset_cache_max_bytes: 1024 * 1024 * 1024,
+            join_timeout: Duration::from_secs(10),
+        }
+    }
 }
 
 /// Main bevy_sync plugin to setup for sync
 /// Add this to the bevy app minimally, then either ServerPlugin or ClientPlugin.
-pub struct SyncPlugin;
+#[derive(Debug, Clone, Default)]
+pub struct SyncPlugin {
+    /// Compression of the meshes sent to peers.
+    pub mesh_compression: AssetCompression,
+    /// Compression of the images sent to peers.
+    pub image_compression: AssetCompression,
+    /// Sends the positions, normals and uvs of meshes as 16-bit values, smaller but lossy.
+    pub quantize_meshes: bool,
+}
+
+/// Compression of the meshes and images sent to peers. The codec is recorded with the data,
+/// so that peers decode it whatever their own settings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AssetCompression {
+    None,
+    #[default]
+    Lz4,
+    /// Better ratio than Lz4 for vertex data and raw textures, slower to compress.
+    Zstd,
+}
 
 /// Plugin used for hosting mode
 pub struct ServerPlugin {
@@ -73,6 +257,184 @@ pub struct ServerPlugin {
 /// Plugin used for joining a host
 pub struct ClientPlugin {
     pub parameters: SyncConnectionParameters,
+    /// Identity presented to the host, a random one is used for this run when None.
+    pub identity: Option<PeerIdentity>,
+}
+
+/// Send this event to host a session at runtime, instead of adding ServerPlugin.
+/// The current session, if any, is left first.
+#[derive(Event, Debug, Clone)]
+pub struct StartHosting {
+    pub parameters: SyncConnectionParameters,
+}
+
+/// Send this event to join a session at runtime, instead of adding ClientPlugin.
+/// The current session, if any, is left first. The identity used is the one of SyncPeers.
+#[derive(Event, Debug, Clone)]
+pub struct JoinSession {
+    pub parameters: SyncConnectionParameters,
+}
+
+/// Send this event to leave the hosted or joined session, closing its connections and its
+/// asset server. Synched entities stay in the world.
+#[derive(Event, Debug, Clone, Default)]
+pub struct LeaveSession;
+
+/// Optional plugin finding sessions on the local network.
+/// While hosting a Socket or SecureSocket session the app announces it over UDP broadcast,
+/// otherwise it listens for announcements and lists them in DiscoveredSessions.
+/// Does nothing on wasm32, as browsers have no UDP.
+pub struct DiscoveryPlugin {
+    /// Name announced for the hosted session.
+    pub session_name: String,
+    /// UDP port announcements are sent to, the same for hosts and clients.
+    pub port: u16,
+    /// Where announcements are sent, use a loopback address to stay on this machine.
+    pub broadcast_ip: IpAddr,
+    /// Time between two announcements.
+    pub interval: Duration,
+    /// Sessions not announced for this long are dropped from DiscoveredSessions.
+    pub timeout: Duration,
+}
+
+impl Default for DiscoveryPlugin {
+    fn default() -> Self {
+        Self {
+            session_name: "bevy_sync".to_string(),
+            port: 47_800,
+            broadcast_ip: IpAddr::V4(Ipv4Addr::BROADCAST),
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// A session announced on the local network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredSession {
+    pub name: String,
+    /// Address the announcement came from.
+    pub ip: IpAddr,
+    pub port: u16,
+    pub web_port: u16,
+    pub asset_transport: AssetTransport,
+    /// Connected peers, including the host.
+    pub players: usize,
+    /// Joining requires a connect token, see SyncConnectionParameters::SecureSocket.
+    pub secure: bool,
+    /// App elapsed time when the session was last announced.
+    pub last_seen: Duration,
+}
+
+impl DiscoveredSession {
+    /// Parameters for a ClientPlugin joining this session, transferring assets the same way
+    /// as the host and serving its own on any free port when over http.
+    /// Secure sessions are joined with the connect token given by their auth service, None is
+    /// returned when a secure session is given no token.
+    pub fn client_parameters(
+        &self,
+        max_transfer: usize,
+        connect_token: Option<Vec<u8>>,
+    ) -> Option<SyncConnectionParameters> {
+        if !self.secure {
+            return Some(SyncConnectionParameters::Socket {
+                ip: self.ip,
+                port: self.port,
+                web_port: 0,
+                max_transfer,
+                asset_transport: self.asset_transport,
+            });
+        }
+        Some(SyncConnectionParameters::SecureSocket {
+            ip: self.ip,
+            port: self.port,
+            web_port: 0,
+            max_transfer,
+            asset_transport: self.asset_transport,
+            // only the host needs the private key, this client can't be promoted to host
+            private_key: None,
+            connect_token: connect_token?,
+        })
+    }
+}
+
+/// Sessions found by the DiscoveryPlugin, keyed by the address of their host.
+/// Only sessions using the same protocol id as this app are listed.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct DiscoveredSessions {
+    pub sessions: HashMap<SocketAddr, DiscoveredSession>,
+}
+
+/// Identifies a peer across reconnections, regardless of the transport client id.
+/// The host rejects clients joining with an identity already in its session.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PeerIdentity {
+    pub id: Uuid,
+    /// Name to display for this peer.
+    pub name: String,
+}
+
+impl PeerIdentity {
+    /// New identity with a random id. Persist it to be recognized as the same peer later.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: name.into(),
+        }
+    }
+
+    /// Identity whose id is derived from the name, so the same name is always the same peer.
+    pub fn named(name: impl Into<String>) -> Self {
+        let name = name.into();
+        Self {
+            id: Uuid::new_v5(&Uuid::NAMESPACE_OID, name.as_bytes()),
+            name,
+        }
+    }
+}
+
+/// A peer in the session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerInfo {
+    pub identity: PeerIdentity,
+    /// Connection quality between the peer and the host, as measured by the host. Clients
+    /// measure it themselves for the host.
+    pub network: PeerNetworkInfo,
+}
+
+/// Peers in the session, available both on the host and on the clients.
+/// Client ids are the ones given by the host to each connected client. Clients list the host
+/// under HOST_PEER, the host only lists the clients and is its own local identity.
+#[derive(Resource, Debug, Clone)]
+pub struct SyncPeers {
+    /// Identity of this app.
+    pub local: PeerIdentity,
+    pub peers: HashMap<ClientId, PeerInfo>,
+}
+
+impl Default for SyncPeers {
+    fn default() -> Self {
+        Self {
+            local: PeerIdentity::new(""),
+            peers: HashMap::new(),
+        }
+    }
+}
+
+/// Sent on the host and on the clients when a client joined the session. A joining client is
+/// also sent one for the host and for each peer already in the session.
+#[derive(Event, Debug, Clone)]
+pub struct PeerJoined {
+    pub client_id: ClientId,
+    pub identity: PeerIdentity,
+}
+
+/// Sent on the host and on the clients when a client left the session.
+#[derive(Event, Debug, Clone)]
+pub struct PeerLeft {
+    pub client_id: ClientId,
+    pub identity: PeerIdentity,
+    pub reason: String,
 }
 
 /// Published state for server connectivity.
@@ -81,6 +443,8 @@ pub enum ServerState {
     Connected,
     #[default]
     Disconnected,
+    /// Could not start hosting, for example because the port is already in use.
+    Failed(String),
 }
 
 /// Published state for client connectivity.
@@ -91,11 +455,69 @@ pub enum ClientState {
     Connecting,
     #[default]
     Disconnected,
+    /// Could not connect, for example because the connect token was invalid or expired.
+    Failed(String),
 }
 
 #[derive(Event)]
 pub struct InitialSyncFinished;
 
+/// Sent when the networking of a host or a client could not be started, along with the
+/// Failed server or client state.
+#[derive(Event, Debug, Clone)]
+pub struct SyncStartupError {
+    pub reason: String,
+}
+
+/// Sent when an asset announced by a peer could not be fetched, after all retries, or did not
+/// match its content hash. The asset is then not applied.
+#[derive(Event, Debug, Clone)]
+pub struct AssetTransferFailed {
+    pub id: Uuid,
+    pub url: String,
+    pub reason: String,
+}
+
+/// Credentials sent by a client when joining, for the host to approve with JoinApproval.
+/// Insert this resource on the client before it connects, otherwise empty ones are sent.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct JoinCredentials {
+    pub user_name: String,
+    /// Password, invite code or any other secret the host expects.
+    pub secret: String,
+}
+
+type ApprovalCallback = dyn Fn(ClientId, &JoinCredentials) -> Result<(), String> + Send + Sync;
+
+/// Insert this resource on the host to decide which clients may join.
+/// The callback runs before the initial sync is sent, rejected clients are disconnected and
+/// receive the returned reason as a JoinRejected event. Without it every client is accepted.
+/// Clients are sent nothing of the session until they are approved.
+#[derive(Resource)]
+pub struct JoinApproval(Box<ApprovalCallback>);
+
+impl JoinApproval {
+    pub fn new(
+        approve: impl Fn(ClientId, &JoinCredentials) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Box::new(approve))
+    }
+
+    pub(crate) fn approve(
+        &self,
+        client_id: ClientId,
+        credentials: &JoinCredentials,
+    ) -> Result<(), String> {
+        (self.0)(client_id, credentials)
+    }
+}
+
+/// Sent on a client when the host refused to let it join.
+#[derive(Event, Debug, Clone)]
+pub struct JoinRejected {
+    pub reason: String,
+}
+
 /// Use this trait extension to configure sync details for your app.
 /// Every component that needs to be synched must be called with sync_component.
 /// To enable assets synching, use the other sync_* methods.
@@ -106,7 +528,57 @@ pub trait SyncComponent {
     >(
         &mut self,
     ) -> &mut Self;
+    /// Synchs the assets of type A, sending them as their SyncAsset bytes. Assets without a
+    /// uuid AssetId are synched once a synched component or material references them.
+    fn sync_asset<A: SyncAsset>(&mut self) -> &mut Self;
+    /// Synchs the assets of type A like sync_asset, converting them to bytes and back with the
+    /// given functions. For asset types of other crates, such as Font or AnimationClip, which
+    /// cannot implement SyncAsset.
+    fn sync_asset_with<A: Asset>(
+        &mut self,
+        to_bytes: AssetToBytes<A>,
+        from_bytes: AssetFromBytes<A>,
+    ) -> &mut Self;
+    /// Synchs the materials of type M with a uuid AssetId as their reflected data, texture
+    /// handles included. The textures are synched as images, like sync_materials does.
+    fn sync_material<M: Material + Reflect + FromReflect + GetTypeRegistration>(
+        &mut self,
+    ) -> &mut Self;
     fn sync_materials(&mut self, enable: bool);
     fn sync_meshes(&mut self, enable: bool);
     fn sync_audios(&mut self, enable: bool);
+    /// Registers a custom vertex attribute of the synched meshes, with the name, id and format
+    /// it is created with by MeshVertexAttribute::new, so that receivers rebuild it as it is.
+    /// Meshes with custom attributes that are not registered are not synched.
+    fn sync_mesh_attribute(
+        &mut self,
+        name: &'static str,
+        id: usize,
+        format: VertexFormat,
+    ) -> &mut Self;
+    /// Sends the assets loaded with AssetServer::load as their AssetPath, for peers having the
+    /// same asset files. Receivers load the path from their own files, and download the asset
+    /// like any other one when that fails.
+    fn sync_assets_by_path(&mut self, enable: bool);
+    /// Synchs the scenes instanced with a `Handle<Scene>` (SceneBundle) on synched entities as a
+    /// whole. The scene asset is transferred once and instanced by every peer, the entities it
+    /// spawns get the same uuid everywhere and their changes are synched like other entities.
+    /// The assets of the scene, like its meshes and materials, are sent when their type is
+    /// synched. With sync_assets_by_path, peers having the scene file load it instead, and
+    /// download the scene when they cannot. Needs the ScenePlugin on every peer.
+    fn sync_scenes(&mut self, enable: bool);
+}
+
+/// Converts an asset synched with sync_asset_with to the bytes sent to peers.
+pub type AssetToBytes<A> = fn(&A) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+/// Converts the bytes received from a peer back to an asset synched with sync_asset_with.
+pub type AssetFromBytes<A> = fn(&[u8]) -> Result<A, Box<dyn Error + Send + Sync>>;
+
+/// Asset type that can be synched with SyncComponent::sync_asset, transferred as bytes.
+/// Peers tell asset types apart by their type path, so both must register the same type.
+/// Implemented for AudioSource. Meshes and images are synched with sync_meshes and
+/// sync_materials, encoded as set on SyncPlugin.
+pub trait SyncAsset: Asset + Sized {
+    fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error + Send + Sync>>;
 }
@@ -1,17 +1,49 @@
-use std::{any::TypeId, collections::VecDeque};
+use std::{
+    any::{Any, TypeId},
+    collections::VecDeque,
+    sync::Arc,
+};
 
 use bevy::{
+    asset::{ReflectHandle, UntypedAssetId, UntypedHandle},
     ecs::component::ComponentId,
     pbr::OpaqueRendererMethod,
     prelude::*,
-    reflect::{DynamicTypePath, FromReflect, GetTypeRegistration, Reflect, ReflectFromReflect},
-    render::mesh::skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+    reflect::{
+        DynamicTypePath, FromReflect, GetTypeRegistration, Reflect, ReflectFromReflect,
+        TypeRegistry,
+    },
+    render::{
+        mesh::{
+            skinning::{SkinnedMesh, SkinnedMeshInverseBindposes},
+            MeshVertexAttribute,
+        },
+        render_resource::VertexFormat,
+    },
     utils::{HashMap, HashSet},
 };
 use uuid::Uuid;
 
 use crate::{
-    binreflect::bin_to_reflect, bundle_fix::BundleFixPlugin, client::ClientSyncPlugin, proto::AssId, server::ServerSyncPlugin, ClientPlugin, ClientState, InitialSyncFinished, PromoteToHostEvent, ServerPlugin, ServerState, SyncComponent, SyncEntity, SyncExclude, SyncMark, SyncPlugin
+    binreflect::{bin_to_reflect, map_handles},
+    bundle_fix::BundleFixPlugin,
+    client::{self, ClientSyncPlugin},
+    full_sync,
+    networking::{
+        assets::{
+            self, audio_codec, image_codec, mesh_codec, AssetCodec, AssetEncoding,
+            SyncAssetTransfer,
+        },
+        transport::TransportPlugin,
+        SessionPlugin,
+    },
+    proto::{AssId, AssetHash, Message},
+    scene::{scene_codec, SceneSyncPlugin},
+    server::{self, ServerSyncPlugin},
+    AssetFromBytes, AssetToBytes, AssetTransferFailed, ClientId, ClientPlugin, ClientState,
+    InitialSyncFinished, JoinRejected, PeerJoined, PeerLeft, PromoteToHostEvent, ServerPlugin,
+    ServerState, SyncAsset, SyncComponent, SyncEntity, SyncExclude, SyncMark, SyncPeers,
+    SyncPlugin, SyncStartupError,
 };
 
 #[derive(PartialEq, Eq, Hash)]
@@ -41,26 +73,199 @@ pub(crate) struct SyncTrackerRes {
     pub(crate) pushed_component_from_network: HashSet<ComponentChangeId>,
     pub(crate) pushed_handles_from_network: HashSet<AssId>,
 
-    pub(crate) sync_materials: bool,
-    pub(crate) sync_meshes: bool,
-    pub(crate) sync_audios: bool,
+    /// Uuids given to the assets without one once referenced by synched handles, so that
+    /// peers can tell them apart. Receivers map them back to the local asset ids.
+    pub(crate) asset_id_to_uuid: HashMap<UntypedAssetId, AssId>,
+    pub(crate) uuid_to_asset_id: HashMap<AssId, UntypedAssetId>,
+    /// Assets given a uuid since the systems sending their type last ran.
+    pub(crate) newly_mapped_assets: HashSet<UntypedAssetId>,
+
+    /// Material types registered with sync_material, keyed by type path.
+    pub(crate) synced_materials: HashMap<String, SyncedMaterial>,
+    /// Asset types registered with sync_asset, keyed by type path.
+    pub(crate) synced_assets: HashMap<String, SyncedAsset>,
+    /// AssetCodec of each synched asset type.
+    asset_codecs: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+    /// Set by sync_assets_by_path, assets loaded from an AssetPath are sent with it.
+    pub(crate) assets_by_path: bool,
+    /// Assets received by path being loaded from the local files, keyed by network uuid.
+    pub(crate) assets_loaded_by_path: HashMap<AssId, PathLoadedAsset>,
+    /// Path each asset received by path was loaded from, so that it is not loaded twice.
+    pub(crate) paths_of_network_assets: HashMap<AssId, String>,
+    /// Set by sync_scenes, scenes of synched entities are instanced on every peer.
+    pub(crate) sync_scenes: bool,
+    /// Why images are synched: as material textures, set while any material is synched, and as
+    /// the morph targets of meshes, set by sync_meshes. Images stay synched while either needs them.
+    pub(crate) images_for_materials: bool,
+    pub(crate) images_for_meshes: bool,
+    /// Encoding of the meshes and images served, from SyncPlugin.
+    pub(crate) asset_encoding: AssetEncoding,
+    /// Custom vertex attributes registered with sync_mesh_attribute, with their id.
+    pub(crate) mesh_attributes: Vec<(usize, MeshVertexAttribute)>,
 
     pub(crate) host_promotion_in_progress: bool,
 }
 
-pub(crate) fn sync_material_enabled(tracker: Res<SyncTrackerRes>) -> bool {
-    tracker.sync_materials
+pub(crate) fn sync_material_enabled<M: Material>(tracker: Res<SyncTrackerRes>) -> bool {
+    tracker
+        .synced_materials
+        .get(M::type_path())
+        .is_some_and(|synced| synced.enabled)
+}
+
+pub(crate) fn sync_asset_enabled<A: Asset>(tracker: Res<SyncTrackerRes>) -> bool {
+    tracker
+        .synced_assets
+        .get(A::type_path())
+        .is_some_and(|synced| synced.enabled)
+}
+
+/// Asset type known to the sync. Received assets of known types are always applied,
+/// enabled tells whether the local ones are sent too.
+pub(crate) struct SyncedAsset {
+    pub(crate) enabled: bool,
+    /// Serves every asset of the type for an initial sync.
+    pub(crate) full_sync: fn(&mut World, &mut Vec<Message>),
+    /// Starts loading an asset received by path through the AssetServer.
+    pub(crate) load_path: fn(&mut World, String, AssetFallback) -> PathLoadedAsset,
+    pub(crate) remove: fn(&mut World, AssId),
+}
+
+/// Asset being loaded from the path sent by a peer, moved to its network uuid once loaded.
+pub(crate) struct PathLoadedAsset {
+    pub(crate) handle: UntypedHandle,
+    /// The path was already loaded locally, the asset is then copied instead of moved.
+    pub(crate) shared: bool,
+    /// Where to download the asset from if the path cannot be loaded.
+    pub(crate) fallback: AssetFallback,
+}
+
+pub(crate) struct AssetFallback {
+    pub(crate) asset_type: String,
+    pub(crate) url: String,
+    pub(crate) hash: AssetHash,
+    pub(crate) peer: ClientId,
+}
+
+/// Material type known to the sync, like SyncedAsset but sent as reflected data.
+pub(crate) struct SyncedMaterial {
+    pub(crate) enabled: bool,
+    pub(crate) full_sync: fn(&mut World, &mut Vec<Message>),
+    /// Inserts a material received from the network.
+    pub(crate) apply: fn(&mut World, AssId, Box<dyn Reflect>),
+    pub(crate) remove: fn(&mut World, AssId),
+}
+
+/// Registers the reflected types of M and adds the systems sending its materials the first
+/// time it is registered.
+pub(crate) fn register_synced_material<
+    M: Material + Reflect + FromReflect + GetTypeRegistration,
+>(
+    app: &mut App,
+    enabled: bool,
+) {
+    let mut track = app.world_mut().resource_mut::<SyncTrackerRes>();
+    if let Some(synced) = track.synced_materials.get_mut(M::type_path()) {
+        synced.enabled = enabled;
+        return;
+    }
+    track.synced_materials.insert(
+        M::type_path().to_string(),
+        SyncedMaterial {
+            enabled,
+            full_sync: full_sync::check_materials::<M>,
+            apply: insert_material::<M>,
+            remove: remove_asset::<M>,
+        },
+    );
+    app.register_type::<M>();
+    app.register_type_data::<M, ReflectFromReflect>();
+    app.register_type::<Image>();
+    app.register_type::<Handle<Image>>();
+    app.register_type::<Option<Handle<Image>>>();
+    register_handle::<M>(app);
+    server::track_material::<M>(app);
+    client::track_material::<M>(app);
+}
+
+fn insert_material<M: Material + FromReflect>(
+    world: &mut World,
+    id: AssId,
+    material: Box<dyn Reflect>,
+) {
+    let Some(material) = M::from_reflect(material.as_reflect()) else {
+        debug!("Could not apply material {} as {}", id, M::type_path());
+        return;
+    };
+    let mut track = world.resource_mut::<SyncTrackerRes>();
+    track.pushed_handles_from_network.insert(id);
+    let local_id = track.local_asset_id::<M>(id);
+    world.resource_mut::<Assets<M>>().insert(local_id, material);
+}
+
+/// Removes an asset removed by a peer, the removal is not sent back.
+fn remove_asset<A: Asset>(world: &mut World, id: AssId) {
+    let local_id = world.resource::<SyncTrackerRes>().local_asset_id::<A>(id);
+    if world.resource_mut::<Assets<A>>().remove(local_id).is_some() {
+        world
+            .resource_mut::<SyncTrackerRes>()
+            .pushed_handles_from_network
+            .insert(id);
+    }
+}
+
+fn load_asset_path<A: Asset>(
+    world: &mut World,
+    path: String,
+    fallback: AssetFallback,
+) -> PathLoadedAsset {
+    let handle = world.resource::<AssetServer>().load::<A>(path);
+    PathLoadedAsset {
+        shared: world.resource::<Assets<A>>().contains(&handle),
+        handle: handle.untyped(),
+        fallback,
+    }
 }
 
-pub(crate) fn sync_mesh_enabled(tracker: Res<SyncTrackerRes>) -> bool {
-    tracker.sync_meshes
+/// Lets handles to assets of type A be mapped to their network uuid.
+fn register_handle<A: Asset>(app: &mut App) {
+    app.register_type::<Handle<A>>();
+    app.register_type_data::<Handle<A>, ReflectHandle>();
 }
 
-pub(crate) fn sync_audio_enabled(tracker: Res<SyncTrackerRes>) -> bool {
-    tracker.sync_audios
+/// Adds the systems applying and sending assets of type A the first time it is registered.
+pub(crate) fn register_synced_asset<A: Asset>(app: &mut App, enabled: bool, codec: AssetCodec<A>) {
+    let mut track = app.world_mut().resource_mut::<SyncTrackerRes>();
+    track.set_asset_codec(codec);
+    if let Some(synced) = track.synced_assets.get_mut(A::type_path()) {
+        synced.enabled = enabled;
+        return;
+    }
+    track.synced_assets.insert(
+        A::type_path().to_string(),
+        SyncedAsset {
+            enabled,
+            full_sync: full_sync::check_assets::<A>,
+            load_path: load_asset_path::<A>,
+            remove: remove_asset::<A>,
+        },
+    );
+    register_handle::<A>(app);
+    assets::apply_received::<A>(app);
+    server::track_asset::<A>(app);
+    client::track_asset::<A>(app);
 }
 
 impl SyncTrackerRes {
+    /// Forgets what was pending for the previous session. Entity mappings are kept since
+    /// the synched entities stay in the world.
+    pub(crate) fn reset_session(&mut self) {
+        self.changed_components_to_send.clear();
+        self.pushed_component_from_network.clear();
+        self.pushed_handles_from_network.clear();
+        self.host_promotion_in_progress = false;
+    }
+
     pub(crate) fn signal_component_changed(&mut self, id: Uuid, data: Box<dyn Reflect>) {
         let name = data.get_represented_type_info().unwrap().type_path().into();
         let change_id = ComponentChangeId { id, name };
@@ -76,6 +281,107 @@ impl SyncTrackerRes {
             .push_back(ComponentChange { change_id, data });
     }
 
+    pub(crate) fn set_asset_codec<A: Asset>(&mut self, codec: AssetCodec<A>) {
+        self.asset_codecs.insert(TypeId::of::<A>(), Arc::new(codec));
+    }
+
+    /// Codec the assets of type A are sent with, None when the type is not synched.
+    pub(crate) fn asset_codec<A: Asset>(&self) -> Option<Arc<AssetCodec<A>>> {
+        let codec = self.asset_codecs.get(&TypeId::of::<A>())?.clone();
+        codec.downcast().ok()
+    }
+
+    /// Uuid of the asset on the network, given to assets without one the first time they are
+    /// referenced.
+    pub(crate) fn network_uuid(&mut self, id: UntypedAssetId) -> AssId {
+        if let Some(uuid) = self.known_uuid(id) {
+            return uuid;
+        }
+        let uuid = Uuid::new_v4();
+        debug!("Asset {:?} is synched as {}", id, uuid);
+        self.asset_id_to_uuid.insert(id, uuid);
+        self.uuid_to_asset_id.insert(uuid, id);
+        self.newly_mapped_assets.insert(id);
+        uuid
+    }
+
+    /// Uuid of the asset on the network, None for assets without one not referenced yet.
+    pub(crate) fn known_uuid(&self, id: UntypedAssetId) -> Option<AssId> {
+        match id {
+            UntypedAssetId::Uuid { uuid, .. } => Some(uuid),
+            UntypedAssetId::Index { .. } => self.asset_id_to_uuid.get(&id).copied(),
+        }
+    }
+
+    /// Local id of the asset known as uuid on the network.
+    pub(crate) fn local_asset_id<A: Asset>(&self, uuid: AssId) -> AssetId<A> {
+        self.uuid_to_asset_id
+            .get(&uuid)
+            .and_then(|id| id.try_typed::<A>().ok())
+            .unwrap_or(AssetId::Uuid { uuid })
+    }
+
+    /// Forgets the uuid given to a removed asset, returning its uuid on the network.
+    pub(crate) fn forget_asset_id(&mut self, id: UntypedAssetId) -> Option<AssId> {
+        let uuid = self.known_uuid(id)?;
+        if let Some(id) = self.uuid_to_asset_id.remove(&uuid) {
+            self.asset_id_to_uuid.remove(&id);
+            self.newly_mapped_assets.remove(&id);
+        }
+        Some(uuid)
+    }
+
+    /// Assets of type A given a uuid since the last call.
+    pub(crate) fn take_newly_mapped<A: Asset>(&mut self) -> Vec<(AssetId<A>, AssId)> {
+        if self.newly_mapped_assets.is_empty() {
+            return Vec::new();
+        }
+        let mut result = Vec::new();
+        self.newly_mapped_assets
+            .retain(|id| match id.try_typed::<A>() {
+                Ok(typed) => {
+                    result.push((typed, self.asset_id_to_uuid[id]));
+                    false
+                }
+                Err(_) => true,
+            });
+        result
+    }
+
+    /// Maps the handles of a value to send to the uuids of their assets.
+    pub(crate) fn handles_to_network(&mut self, value: &mut dyn Reflect, registry: &TypeRegistry) {
+        map_handles(value, registry, &mut |id| UntypedAssetId::Uuid {
+            type_id: id.type_id(),
+            uuid: self.network_uuid(id),
+        });
+    }
+
+    /// Maps the handles of a received value back to the local asset ids.
+    pub(crate) fn handles_from_network(&self, value: &mut dyn Reflect, registry: &TypeRegistry) {
+        map_handles(value, registry, &mut |id| match id {
+            UntypedAssetId::Uuid { uuid, .. } => {
+                self.uuid_to_asset_id.get(&uuid).copied().unwrap_or(id)
+            }
+            id => id,
+        });
+    }
+
+    /// Path to send along an asset, when synching by path and the asset was loaded from one.
+    pub(crate) fn asset_path_to_network(
+        &self,
+        asset_server: &AssetServer,
+        id: impl Into<UntypedAssetId>,
+    ) -> Option<String> {
+        if !self.assets_by_path {
+            return None;
+        }
+        asset_server.get_path(id).map(|path| path.to_string())
+    }
+
+    pub(crate) fn is_asset_synced(&self, asset_type: &str) -> bool {
+        self.synced_assets.contains_key(asset_type)
+    }
+
     pub(crate) fn skip_network_handle_change(&mut self, id: AssId) -> bool {
         if self.pushed_handles_from_network.contains(&id) {
             debug!(
@@ -102,15 +408,18 @@ impl SyncTrackerRes {
         } else {
             name
         };
-        let component_data = if (*component_data).type_id() == TypeId::of::<SkinnedMeshSyncMapper>()
-        {
-            let component = component_data
-                .downcast_ref::<SkinnedMeshSyncMapper>()
-                .unwrap();
-            SyncTrackerRes::to_skinned_mesh(world, component.clone()).clone_value()
-        } else {
-            component_data
-        };
+        let mut component_data =
+            if (*component_data).type_id() == TypeId::of::<SkinnedMeshSyncMapper>() {
+                let component = component_data
+                    .downcast_ref::<SkinnedMeshSyncMapper>()
+                    .unwrap();
+                SyncTrackerRes::to_skinned_mesh(world, component.clone()).clone_value()
+            } else {
+                component_data
+            };
+        world
+            .resource::<SyncTrackerRes>()
+            .handles_from_network(component_data.as_reflect_mut(), &registry);
         let Some(registration) = registry.get_with_type_path(name.as_str()) else {
             debug!("Could not obtain registration for {:?}", name);
             return false;
@@ -176,16 +485,73 @@ impl SyncTrackerRes {
         material: &[u8],
         world: &mut World,
     ) {
-        world
-            .resource_mut::<SyncTrackerRes>()
-            .pushed_handles_from_network
-            .insert(id);
         let registry = world.resource::<AppTypeRegistry>().clone();
         let registry = registry.read();
-        let component_data = bin_to_reflect(material, &registry);
-        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
-        let mat = *component_data.downcast::<StandardMaterial>().unwrap();
-        materials.insert(id, mat);
+        let mut material = bin_to_reflect(material, &registry);
+        let track = world.resource::<SyncTrackerRes>();
+        track.handles_from_network(material.as_reflect_mut(), &registry);
+        drop(registry);
+        let type_path = material.reflect_type_path();
+        let Some(synced) = world
+            .resource::<SyncTrackerRes>()
+            .synced_materials
+            .get(type_path)
+        else {
+            debug!("Ignoring material {} of unknown type {}", id, type_path);
+            return;
+        };
+        (synced.apply)(world, id, material);
+    }
+
+    pub(crate) fn apply_asset_removal_from_network(world: &mut World, asset_type: &str, id: AssId) {
+        let track = world.resource::<SyncTrackerRes>();
+        let remove = track
+            .synced_assets
+            .get(asset_type)
+            .map(|synced| synced.remove)
+            .or_else(|| {
+                track
+                    .synced_materials
+                    .get(asset_type)
+                    .map(|synced| synced.remove)
+            });
+        let Some(remove) = remove else {
+            debug!("Ignoring removal of {} of unknown type {}", id, asset_type);
+            return;
+        };
+        if let Some(mut sync_assets) = world.get_resource_mut::<SyncAssetTransfer>() {
+            sync_assets.forget(asset_type, &id);
+        }
+        remove(world, id);
+        let mut track = world.resource_mut::<SyncTrackerRes>();
+        track.assets_loaded_by_path.remove(&id);
+        track.paths_of_network_assets.remove(&id);
+    }
+
+    /// Loads an asset received by path, falling back to downloading it from url if the path
+    /// cannot be loaded.
+    pub(crate) fn apply_asset_path_from_network(
+        world: &mut World,
+        id: AssId,
+        path: String,
+        fallback: AssetFallback,
+    ) {
+        let track = world.resource::<SyncTrackerRes>();
+        if track.paths_of_network_assets.get(&id) == Some(&path) {
+            return;
+        }
+        let Some(load_path) = track
+            .synced_assets
+            .get(&fallback.asset_type)
+            .map(|synced| synced.load_path)
+        else {
+            return;
+        };
+        debug!("Loading {} {} from {}", fallback.asset_type, id, path);
+        let loading = load_path(world, path.clone(), fallback);
+        let mut track = world.resource_mut::<SyncTrackerRes>();
+        track.assets_loaded_by_path.insert(id, loading);
+        track.paths_of_network_assets.insert(id, path);
     }
 
     pub(crate) fn to_skinned_mapper(
@@ -270,19 +636,90 @@ impl SyncComponent for App {
         self
     }
 
+    fn sync_asset<A: SyncAsset>(&mut self) -> &mut Self {
+        self.sync_asset_with(A::to_bytes, A::from_bytes)
+    }
+
+    fn sync_asset_with<A: Asset>(
+        &mut self,
+        to_bytes: AssetToBytes<A>,
+        from_bytes: AssetFromBytes<A>,
+    ) -> &mut Self {
+        if self.world().get_resource::<SyncTrackerRes>().is_none() {
+            warn!("Trying to register sync_asset in bevy_sync, but bevy_sync is not enabled.");
+            return self;
+        }
+        register_synced_asset(self, true, AssetCodec::new(to_bytes, from_bytes));
+        self
+    }
+
+    fn sync_material<M: Material + Reflect + FromReflect + GetTypeRegistration>(
+        &mut self,
+    ) -> &mut Self {
+        if self.world().get_resource::<SyncTrackerRes>().is_none() {
+            warn!("Trying to register sync_material in bevy_sync, but bevy_sync is not enabled.");
+            return self;
+        }
+        register_synced_material::<M>(self, true);
+        self.world_mut()
+            .resource_mut::<SyncTrackerRes>()
+            .images_for_materials = true;
+        register_synced_asset(self, true, image_codec());
+        self
+    }
+
     fn sync_materials(&mut self, enable: bool) {
-        let mut tracker = self.world_mut().resource_mut::<SyncTrackerRes>();
-        tracker.sync_materials = enable;
+        register_synced_material::<StandardMaterial>(self, enable);
+        let mut track = self.world_mut().resource_mut::<SyncTrackerRes>();
+        // custom materials registered with sync_material keep needing their textures
+        track.images_for_materials = track.synced_materials.values().any(|m| m.enabled);
+        let images = track.images_for_meshes || track.images_for_materials;
+        register_synced_asset(self, images, image_codec());
     }
 
     fn sync_meshes(&mut self, enable: bool) {
-        let mut tracker = self.world_mut().resource_mut::<SyncTrackerRes>();
-        tracker.sync_meshes = enable;
+        register_synced_asset(self, enable, mesh_codec());
+        // the morph targets of the meshes are sent as images
+        let mut track = self.world_mut().resource_mut::<SyncTrackerRes>();
+        track.images_for_meshes = enable;
+        let images = track.images_for_materials || enable;
+        register_synced_asset(self, images, image_codec());
     }
 
     fn sync_audios(&mut self, enable: bool) {
-        let mut tracker = self.world_mut().resource_mut::<SyncTrackerRes>();
-        tracker.sync_audios = enable;
+        register_synced_asset(self, enable, audio_codec());
+    }
+
+    fn sync_mesh_attribute(
+        &mut self,
+        name: &'static str,
+        id: usize,
+        format: VertexFormat,
+    ) -> &mut Self {
+        let mut track = self.world_mut().resource_mut::<SyncTrackerRes>();
+        track.mesh_attributes.retain(|(known, _)| *known != id);
+        track
+            .mesh_attributes
+            .push((id, MeshVertexAttribute::new(name, id, format)));
+        self
+    }
+
+    fn sync_assets_by_path(&mut self, enable: bool) {
+        self.world_mut()
+            .resource_mut::<SyncTrackerRes>()
+            .assets_by_path = enable;
+    }
+
+    fn sync_scenes(&mut self, enable: bool) {
+        if !self.world().contains_resource::<Assets<Scene>>() {
+            warn!("Trying to sync scenes in bevy_sync, but the ScenePlugin was not added.");
+            return;
+        }
+        self.world_mut()
+            .resource_mut::<SyncTrackerRes>()
+            .sync_scenes = enable;
+        let registry = self.world().resource::<AppTypeRegistry>().clone();
+        register_synced_asset(self, enable, scene_codec(registry));
     }
 }
 
@@ -359,9 +796,30 @@ fn setup_cascade_registrations<T: Component + Reflect + FromReflect + GetTypeReg
 impl Plugin for SyncPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<InitialSyncFinished>();
+        app.add_event::<JoinRejected>();
+        app.add_event::<PeerJoined>();
+        app.add_event::<PeerLeft>();
+        app.add_event::<SyncStartupError>();
+        app.add_event::<AssetTransferFailed>();
+        app.init_resource::<SyncPeers>();
         app.register_type::<SyncMark>();
         app.init_resource::<SyncTrackerRes>();
+        app.world_mut()
+            .resource_mut::<SyncTrackerRes>()
+            .asset_encoding = AssetEncoding {
+            mesh_compression: self.mesh_compression,
+            image_compression: self.image_compression,
+            quantize_meshes: self.quantize_meshes,
+        };
+        // built-in asset types are always received, the sync_* methods enable sending them
+        register_synced_asset(app, false, mesh_codec());
+        register_synced_asset(app, false, image_codec());
+        register_synced_asset(app, false, audio_codec());
+        register_synced_material::<StandardMaterial>(app, false);
         app.add_plugins(BundleFixPlugin);
+        app.add_plugins(SceneSyncPlugin);
+        app.add_plugins(TransportPlugin);
+        app.add_plugins(SessionPlugin);
         app.add_plugins(ServerSyncPlugin);
         app.add_plugins(ClientSyncPlugin);
         app.init_state::<ServerState>();
@@ -372,14 +830,17 @@ impl Plugin for SyncPlugin {
 
 impl Plugin for ServerPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(self.parameters.clone());
-        crate::networking::setup_server(app, self.parameters.clone());
+        crate::networking::start_hosting(app.world_mut(), self.parameters.clone());
     }
 }
 
 impl Plugin for ClientPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(self.parameters.clone());
-        crate::networking::setup_client(app, self.parameters.clone());
+        if let Some(identity) = &self.identity {
+            app.world_mut()
+                .get_resource_or_insert_with(SyncPeers::default)
+                .local = identity.clone();
+        }
+        crate::networking::join_session(app.world_mut(), self.parameters.clone());
     }
 }
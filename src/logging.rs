@@ -27,28 +27,25 @@ pub(crate) fn log_message_received(from: Who, message: &Message) {
                 from, id, name
             )
         }
-        Message::StandardMaterialUpdated { id, material: _ } => {
-            debug!(
-                "{:?} received StandardMaterialUpdated {{ uuid: {} }}",
-                from, id
-            )
-        }
-        Message::MeshUpdated { id, url } => {
-            debug!(
-                "{:?} received MeshUpdated {{ uuid: {} }} {{ url: {} }}",
-                from, id, url
-            )
+        Message::MaterialUpdated { id, material: _ } => {
+            debug!("{:?} received MaterialUpdated {{ uuid: {} }}", from, id)
         }
-        Message::ImageUpdated { id, url } => {
+        Message::AssetUpdated {
+            asset_type,
+            id,
+            url,
+            path,
+            ..
+        } => {
             debug!(
-                "{:?} received ImageUpdated {{ uuid: {} }} {{ url: {} }}",
-                from, id, url
+                "{:?} received AssetUpdated {{ type: {} }} {{ uuid: {} }} {{ url: {} }} {{ path: {:?} }}",
+                from, asset_type, id, url, path
             )
         }
-        Message::AudioUpdated { id, url } => {
+        Message::AssetRemoved { asset_type, id } => {
             debug!(
-                "{:?} received AudioUpdated {{ uuid: {} }} {{ url: {} }}",
-                from, id, url
+                "{:?} received AssetRemoved {{ type: {} }} {{ uuid: {} }}",
+                from, asset_type, id
             )
         }
         Message::PromoteToHost => debug!("{:?} received PromoteToHost", from),
@@ -58,11 +55,29 @@ pub(crate) fn log_message_received(from: Who, message: &Message) {
                 port,
                 web_port,
                 max_transfer,
+                asset_transport,
             } => {
                 debug!(
-                "{:?} received NewHost {{ ip: {} }} {{ port: {} }} {{ web_port: {} }} {{ max_transfer: {} }}",
+                "{:?} received NewHost {{ ip: {} }} {{ port: {} }} {{ web_port: {} }} {{ max_transfer: {} }} {{ asset_transport: {:?} }}",
+                from, ip, port, web_port, max_transfer, asset_transport);
+            }
+            crate::SyncConnectionParameters::InMemory { name } => {
+                debug!("{:?} received NewHost {{ name: {} }}", from, name);
+            }
+            crate::SyncConnectionParameters::SecureSocket {
+                ip,
+                port,
+                web_port,
+                max_transfer,
+                ..
+            } => {
+                debug!(
+                "{:?} received NewHost (secure) {{ ip: {} }} {{ port: {} }} {{ web_port: {} }} {{ max_transfer: {} }}",
                 from, ip, port, web_port, max_transfer);
             }
+            crate::SyncConnectionParameters::WebSocket { url } => {
+                debug!("{:?} received NewHost {{ url: {} }}", from, url);
+            }
         },
 
         Message::RequestInitialSync => debug!(
@@ -72,5 +87,54 @@ pub(crate) fn log_message_received(from: Who, message: &Message) {
         Message::FinishedInitialSync => {
             debug!("Received FinishedInitialSync from client_id: {:?}", from)
         }
+        Message::Join {
+            credentials,
+            identity,
+        } => debug!(
+            "{:?} received Join {{ user_name: {} }} {{ identity: {} }}",
+            from, credentials.user_name, identity.id
+        ),
+        Message::JoinRejected { reason } => {
+            debug!("{:?} received JoinRejected {{ reason: {} }}", from, reason)
+        }
+        Message::PeerList { peers } => {
+            debug!("{:?} received PeerList {{ count: {} }}", from, peers.len())
+        }
+        Message::PeerJoined {
+            client_id,
+            identity,
+        } => debug!(
+            "{:?} received PeerJoined {{ client_id: {} }} {{ identity: {} }}",
+            from, client_id, identity.id
+        ),
+        Message::PeerLeft { client_id, reason } => debug!(
+            "{:?} received PeerLeft {{ client_id: {} }} {{ reason: {} }}",
+            from, client_id, reason
+        ),
+        Message::PeerNetworkInfo { peers } => {
+            debug!(
+                "{:?} received PeerNetworkInfo {{ count: {} }}",
+                from,
+                peers.len()
+            )
+        }
+        Message::AssetRequest { asset_type, id } => {
+            debug!(
+                "{:?} received AssetRequest {{ type: {} }} {{ uuid: {} }}",
+                from, asset_type, id
+            )
+        }
+        Message::AssetChunk {
+            asset_type,
+            id,
+            offset,
+            total,
+            bytes,
+        } => {
+            debug!(
+                "{:?} received AssetChunk {{ type: {} }} {{ uuid: {} }} {{ offset: {} }} {{ size: {} }} {{ total: {} }}",
+                from, asset_type, id, offset, bytes.len(), total
+            )
+        }
     }
 }
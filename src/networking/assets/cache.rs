@@ -0,0 +1,169 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use bevy::prelude::*;
+
+use crate::proto::AssetHash;
+
+use super::content_hash;
+
+/// Assets kept on disk by content hash, so that they are not downloaded again when the same
+/// content is announced, even in later sessions. The least recently used files are evicted
+/// once the directory grows over max_bytes.
+#[derive(Clone)]
+pub(crate) struct DiskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Serializes writes and evictions of the threads sharing the cache.
+    writing: Arc<Mutex<()>>,
+}
+
+impl DiskCache {
+    pub(crate) fn new(dir: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            max_bytes,
+            writing: Arc::default(),
+        })
+    }
+
+    fn path_of(&self, hash: &AssetHash) -> PathBuf {
+        self.dir
+            .join(blake3::Hash::from_bytes(*hash).to_hex().as_str())
+    }
+
+    /// Content stored for hash, files that do not match it anymore are discarded.
+    pub(crate) fn get(&self, hash: &AssetHash) -> Option<Vec<u8>> {
+        let path = self.path_of(hash);
+        let bytes = fs::read(&path).ok()?;
+        if content_hash(&bytes) != *hash {
+            debug!("Discarding corrupted cache file {}", path.display());
+            fs::remove_file(&path).unwrap_or(());
+            return None;
+        }
+        // refreshed so that eviction keeps the assets in use
+        if let Ok(file) = File::options().append(true).open(&path) {
+            file.set_modified(SystemTime::now()).unwrap_or(());
+        }
+        Some(bytes)
+    }
+
+    pub(crate) fn put(&self, hash: &AssetHash, bytes: &[u8]) {
+        if bytes.len() as u64 > self.max_bytes {
+            return;
+        }
+        let Ok(_writing) = self.writing.lock() else {
+            return;
+        };
+        let path = self.path_of(hash);
+        if path.exists() {
+            return;
+        }
+        if let Err(e) = write_atomically(&path, bytes) {
+            debug!("Could not cache asset at {}: {}", path.display(), e);
+            return;
+        }
+        if let Err(e) = self.evict() {
+            debug!("Could not evict assets from {}: {}", self.dir.display(), e);
+        }
+    }
+
+    /// Removes the least recently used files until the cache fits max_bytes.
+    fn evict(&self) -> io::Result<()> {
+        let mut files = Vec::new();
+        let mut total = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            total += metadata.len();
+            files.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+        files.sort();
+        for (_, len, path) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(&path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+/// Writes through a temporary file, so that readers never see a partial asset.
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temporary = path.with_extension("part");
+    let mut file = File::create(&temporary)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn temporary_cache(max_bytes: u64) -> DiskCache {
+        let dir = std::env::temp_dir().join(format!("bevy_sync_cache_{}", Uuid::new_v4()));
+        DiskCache::new(dir, max_bytes).unwrap()
+    }
+
+    #[test]
+    fn returns_stored_content_by_hash() {
+        let cache = temporary_cache(1_000);
+        let bytes = vec![1, 2, 3];
+        let hash = content_hash(&bytes);
+        assert_eq!(cache.get(&hash), None);
+
+        cache.put(&hash, &bytes);
+        assert_eq!(cache.get(&hash), Some(bytes));
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn discards_corrupted_files() {
+        let cache = temporary_cache(1_000);
+        let hash = content_hash(&[1, 2, 3]);
+        fs::write(cache.path_of(&hash), [3, 2, 1]).unwrap();
+
+        assert_eq!(cache.get(&hash), None);
+        assert!(!cache.path_of(&hash).exists());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_used_assets() {
+        let cache = temporary_cache(250);
+        let assets: Vec<Vec<u8>> = (0..3).map(|i| vec![i; 100]).collect();
+        let hashes: Vec<AssetHash> = assets.iter().map(|bytes| content_hash(bytes)).collect();
+        cache.put(&hashes[0], &assets[0]);
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put(&hashes[1], &assets[1]);
+        std::thread::sleep(Duration::from_millis(20));
+        // using the oldest one keeps it over the second
+        cache.get(&hashes[0]).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        cache.put(&hashes[2], &assets[2]);
+
+        assert!(cache.get(&hashes[0]).is_some());
+        assert!(cache.get(&hashes[1]).is_none());
+        assert!(cache.get(&hashes[2]).is_some());
+        fs::remove_dir_all(&cache.dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use std::io::Read;
+
+use lz4_compression::{compress, decompress};
+
+use crate::AssetCompression;
+
+/// How the meshes and images served to peers are encoded, set from SyncPlugin.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct AssetEncoding {
+    pub(crate) mesh_compression: AssetCompression,
+    pub(crate) image_compression: AssetCompression,
+    pub(crate) quantize_meshes: bool,
+}
+
+/// Compresses bytes with codec, the codec being recorded in the first byte of the result.
+pub(crate) fn compress(codec: AssetCompression, bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let (tag, compressed) = match codec {
+        AssetCompression::None => (0, bytes.to_vec()),
+        AssetCompression::Lz4 => (1, compress::compress(bytes)),
+        AssetCompression::Zstd => (
+            2,
+            zstd::encode_all(bytes, 0).map_err(|e| format!("zstd compression failed: {}", e))?,
+        ),
+    };
+    let mut payload = Vec::with_capacity(compressed.len() + 1);
+    payload.push(tag);
+    payload.extend(compressed);
+    Ok(payload)
+}
+
+/// Decompresses a payload of compress, with the codec it records. Payloads decompressing to more
+/// than max_size bytes are rejected without being decompressed further.
+pub(crate) fn decompress(payload: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    let (tag, bytes) = payload.split_first().ok_or("empty payload")?;
+    let too_large = || format!("payload decompresses to more than {} bytes", max_size);
+    match tag {
+        0 if bytes.len() > max_size => Err(too_large()),
+        0 => Ok(bytes.to_vec()),
+        1 => {
+            if lz4_decompressed_len(bytes)? > max_size {
+                return Err(too_large());
+            }
+            decompress::decompress(bytes).map_err(|e| format!("invalid lz4 data: {:?}", e))
+        }
+        2 => {
+            let decoder = zstd::stream::Decoder::new(bytes)
+                .map_err(|e| format!("invalid zstd data: {}", e))?;
+            let mut decompressed = Vec::new();
+            decoder
+                .take((max_size as u64).saturating_add(1))
+                .read_to_end(&mut decompressed)
+                .map_err(|e| format!("invalid zstd data: {}", e))?;
+            match decompressed.len() > max_size {
+                true => Err(too_large()),
+                false => Ok(decompressed),
+            }
+        }
+        tag => Err(format!("unknown compression {}", tag)),
+    }
+}
+
+/// Length of the data an lz4 block decompresses to, read from the lengths of its sequences.
+fn lz4_decompressed_len(block: &[u8]) -> Result<usize, String> {
+    let truncated = || "invalid lz4 data: UnexpectedEnd".to_string();
+    // lengths of 15 continue in the next bytes, until one is not 0xFF
+    let read_length = |mut length: usize, pos: &mut usize| {
+        if length == 15 {
+            loop {
+                let extra = *block.get(*pos).ok_or_else(truncated)?;
+                *pos += 1;
+                length = length.saturating_add(extra as usize);
+                if extra != 0xFF {
+                    break;
+                }
+            }
+        }
+        Ok::<_, String>(length)
+    };
+    let (mut pos, mut len) = (0, 0usize);
+    while pos < block.len() {
+        let token = block[pos];
+        pos += 1;
+        let literal = read_length((token >> 4) as usize, &mut pos)?;
+        pos = pos.saturating_add(literal);
+        len = len.saturating_add(literal);
+        if pos >= block.len() {
+            return match pos > block.len() {
+                true => Err(truncated()),
+                false => Ok(len),
+            };
+        }
+        // offset of the match, then its length
+        pos += 2;
+        let duplicate = read_length((token & 0xF) as usize, &mut pos)?;
+        len = len.saturating_add(duplicate.saturating_add(4));
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compress_decompress_with_every_codec() {
+        let bytes: Vec<u8> = (0..10_000).map(|i| (i % 7) as u8).collect();
+        for codec in [
+            AssetCompression::None,
+            AssetCompression::Lz4,
+            AssetCompression::Zstd,
+        ] {
+            let payload = compress(codec, &bytes).unwrap();
+            assert_eq!(
+                decompress(&payload, bytes.len()).unwrap(),
+                bytes,
+                "{:?}",
+                codec
+            );
+        }
+    }
+
+    #[test]
+    fn decompress_reports_unknown_codec() {
+        assert!(decompress(&[], 10).is_err());
+        assert!(decompress(&[9, 1, 2], 10).is_err());
+        assert!(decompress(&[2, 1, 2], 10).is_err());
+        assert!(decompress(&[1, 0x20, b'a'], 10).is_err());
+    }
+
+    #[test]
+    fn decompress_rejects_oversized_payloads() {
+        let bytes = vec![0; 1_000_000];
+        for codec in [
+            AssetCompression::None,
+            AssetCompression::Lz4,
+            AssetCompression::Zstd,
+        ] {
+            let payload = compress(codec, &bytes).unwrap();
+            assert!(payload.len() < 10_000 || codec == AssetCompression::None);
+            let error = decompress(&payload, bytes.len() - 1).unwrap_err();
+            assert!(error.contains("more than"), "{:?}: {}", codec, error);
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::{
+    error::Error,
+    io::Read,
+    net::{SocketAddr, TcpListener, TcpStream},
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use ascii::AsciiString;
+use bevy::prelude::*;
+use socket2::{Domain, Protocol, Socket, Type};
+use threadpool::ThreadPool;
+use tiny_http::{Header, Request, Response, Server};
+use ureq::{Agent, AgentBuilder, Response as DownloadResponse};
+use uuid::Uuid;
+
+use crate::{proto::AssetHash, SyncNetworkConfig};
+
+use super::{
+    content_hash, insert_to_apply, read, report_failure, split_asset_url, ServedAssets,
+    SyncAssetTransfer,
+};
+
+/// How http downloads are retried, resuming from the bytes already received.
+#[derive(Clone, Copy)]
+pub(super) struct RetryPolicy {
+    pub(super) retries: u32,
+    pub(super) backoff: Duration,
+}
+
+/// Serving of the assets over http and their downloads, each on a pool of threads.
+pub(super) struct HttpTransfer {
+    /// Http server, unblocked when the transfer is dropped so that its port is released.
+    server: Option<Arc<Server>>,
+    /// Thread handing the accepted requests over to the server pool.
+    accept_thread: Option<JoinHandle<()>>,
+    download_pool: ThreadPool,
+    pub(super) agent: Agent,
+    retry: RetryPolicy,
+}
+
+impl HttpTransfer {
+    /// Downloads only, for the transfers that do not serve their assets over http.
+    pub(super) fn downloads_only() -> Self {
+        Self {
+            server: None,
+            accept_thread: None,
+            download_pool: ThreadPool::new(1),
+            agent: Agent::new(),
+            retry: RetryPolicy {
+                retries: 0,
+                backoff: Duration::ZERO,
+            },
+        }
+    }
+
+    /// Starts the asset server on addr, a port of 0 binds to any free one.
+    pub(super) fn start(
+        addr: SocketAddr,
+        served: ServedAssets,
+        max_transfer: usize,
+        config: &SyncNetworkConfig,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let listener = listen(addr, config.asset_download_timeout)?;
+        let server = Arc::new(Server::from_listener(listener, None)?);
+        let server_pool = ThreadPool::with_name(
+            "bevy_sync asset server".to_string(),
+            config.asset_server_threads,
+        );
+        let accepting = server.clone();
+        let accept_thread = std::thread::Builder::new()
+            .name("bevy_sync asset accept".to_string())
+            .spawn(move || {
+                // ends once the server is unblocked
+                for request in accepting.incoming_requests() {
+                    debug!("Queuing response to {}", request.url());
+                    let served = served.clone();
+                    server_pool.execute(move || respond(request, &served, max_transfer));
+                }
+            })?;
+        Ok(Self {
+            server: Some(server),
+            accept_thread: Some(accept_thread),
+            download_pool: ThreadPool::with_name(
+                "bevy_sync asset download".to_string(),
+                config.asset_download_threads,
+            ),
+            agent: AgentBuilder::new()
+                .timeout(config.asset_download_timeout)
+                .build(),
+            retry: RetryPolicy {
+                retries: config.asset_download_retries,
+                backoff: config.asset_download_backoff,
+            },
+        })
+    }
+
+    /// Port the asset server is bound to, None when not serving.
+    pub(super) fn port(&self) -> Option<u16> {
+        let server = self.server.as_ref()?;
+        server.server_addr().to_ip().map(|addr| addr.port())
+    }
+
+    /// Stops serving and releases the port. The pools are not joined: responses in progress
+    /// end with the write timeout and downloads with the agent timeout on their own threads,
+    /// while downloads not started yet see closing and give up.
+    pub(super) fn close(&mut self) {
+        let Some(server) = self.server.take() else {
+            return;
+        };
+        server.unblock();
+        if let Some(accept_thread) = self.accept_thread.take() {
+            accept_thread.join().unwrap_or(());
+        }
+        let addr = server.server_addr().to_ip();
+        drop(server);
+        if let Some(addr) = addr {
+            wait_until_closed(addr);
+        }
+    }
+}
+
+/// Downloads an asset announced at url on the download pool of transfer, applying it once
+/// received.
+pub(super) fn request(
+    transfer: &SyncAssetTransfer,
+    key: (String, Uuid),
+    url: String,
+    hash: AssetHash,
+) {
+    debug!("Queuing request for {}:{} at {}", key.0, key.1, url);
+    let http = &transfer.http;
+    let max_transfer = transfer.max_transfer;
+    let agent = http.agent.clone();
+    let retry = http.retry;
+    let closing = transfer.closing.clone();
+    let failures = transfer.failures.clone();
+    let disk_cache = transfer.disk_cache.clone();
+    let to_apply = transfer.to_apply.clone();
+    http.download_pool.execute(move || {
+        match download(&agent, &url, &hash, max_transfer, retry, &closing) {
+            Ok(bytes) => {
+                debug!("Received {} {} with size {}", key.0, key.1, bytes.len());
+                if let Some(cache) = disk_cache {
+                    cache.put(&hash, &bytes);
+                }
+                insert_to_apply(&to_apply, key, url, bytes);
+            }
+            Err(reason) => report_failure(&failures, key.1, url, reason),
+        }
+    });
+}
+
+fn respond(request: Request, served: &ServedAssets, max_size: usize) {
+    let url = request.url();
+    let Some((_, asset_type, id)) = split_asset_url(url) else {
+        return;
+    };
+    let map = read(served);
+    let Some(bytes) = map.get(&(asset_type, id)) else {
+        request
+            .respond(Response::from_string("").with_status_code(404))
+            .unwrap_or(());
+        return;
+    };
+    let range = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Range"))
+        .map(|header| requested_range(header.value.as_str(), bytes.len()));
+    let (range, status) = match range {
+        None => (0..bytes.len(), 200),
+        Some(Some(range)) => (range, 206),
+        Some(None) => {
+            let response = Response::from_string("")
+                .with_status_code(416)
+                .with_header(header("Content-Range", format!("bytes */{}", bytes.len())));
+            drop(map);
+            request.respond(response).unwrap_or(());
+            return;
+        }
+    };
+    debug!(
+        "Responding to {} with {} of size {}",
+        url,
+        format_range(&range),
+        bytes.len()
+    );
+    let mut response = Response::from_data(bytes[range.clone()].to_vec())
+        .with_status_code(status)
+        .with_header(header("Content-Length", range.len().to_string()))
+        .with_chunked_threshold(max_size);
+    if status == 206 {
+        response = response.with_header(header(
+            "Content-Range",
+            format!("bytes {}/{}", format_range(&range), bytes.len()),
+        ));
+    }
+    drop(map);
+    request.respond(response).unwrap_or(());
+}
+
+/// Listener of the asset server. The write timeout is inherited by the accepted connections, so
+/// that a peer no longer reading a response does not hold a server thread forever.
+fn listen(addr: SocketAddr, write_timeout: Duration) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // as std does, so that the port can be bound again right after the server is dropped
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_write_timeout(Some(write_timeout))?;
+    socket.bind(&addr.into())?;
+    socket.listen(128)?;
+    Ok(socket.into())
+}
+
+/// tiny_http closes its listener on a thread of its own, wait for it so that the port is free.
+fn wait_until_closed(addr: SocketAddr) {
+    for _ in 0..100 {
+        if TcpStream::connect_timeout(&addr, Duration::from_millis(10)).is_err() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn header(field: &str, value: String) -> Header {
+    Header {
+        field: field.parse().unwrap(),
+        value: AsciiString::from_ascii(value).unwrap(),
+    }
+}
+
+/// Bytes asked by a `bytes=start-end` range header, None when they cannot be served.
+/// Only single ranges are supported, as that is all that resuming a download needs.
+fn requested_range(value: &str, len: usize) -> Option<Range<usize>> {
+    let (start, end) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let range = match (start.trim(), end.trim()) {
+        ("", suffix) => len.saturating_sub(suffix.parse().ok()?)..len,
+        (start, "") => start.parse().ok()?..len,
+        (start, end) => start.parse().ok()?..end.parse::<usize>().ok()?.saturating_add(1).min(len),
+    };
+    (range.start < range.end || (len == 0 && range.start == 0)).then_some(range)
+}
+
+fn format_range(range: &Range<usize>) -> String {
+    format!("{}-{}", range.start, range.end.saturating_sub(1))
+}
+
+/// Downloads url, resuming with range requests after failures, until its content matches
+/// hash or the retries are exhausted.
+pub(super) fn download(
+    agent: &Agent,
+    url: &str,
+    hash: &AssetHash,
+    max_transfer: usize,
+    retry: RetryPolicy,
+    closing: &AtomicBool,
+) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut attempt = 0;
+    loop {
+        if closing.load(Ordering::SeqCst) {
+            return Err("transfer closed".to_string());
+        }
+        let error = match fetch(agent, url, &mut bytes, max_transfer) {
+            Ok(()) if content_hash(&bytes) == *hash => return Ok(bytes),
+            Ok(()) => {
+                bytes.clear();
+                "content does not match its hash".to_string()
+            }
+            Err(e) => e,
+        };
+        if attempt >= retry.retries {
+            return Err(error);
+        }
+        let backoff = retry.backoff.saturating_mul(1 << attempt.min(16));
+        debug!("Retrying {} in {:?} after: {}", url, backoff, error);
+        std::thread::sleep(backoff);
+        attempt += 1;
+    }
+}
+
+/// Fetches the rest of url after the bytes already received, restarting if the server does
+/// not support ranges.
+pub(super) fn fetch(
+    agent: &Agent,
+    url: &str,
+    bytes: &mut Vec<u8>,
+    max_transfer: usize,
+) -> Result<(), String> {
+    let mut request = agent.get(url);
+    if !bytes.is_empty() {
+        request = request.set("Range", &format!("bytes={}-", bytes.len()));
+    }
+    let response: DownloadResponse = request.call().map_err(|e| e.to_string())?;
+    if response.status() != 206 {
+        bytes.clear();
+    }
+    let expected = response
+        .header("Content-Length")
+        .and_then(|s| s.parse::<usize>().ok())
+        .map(|len| bytes.len() + len);
+    if expected.is_some_and(|expected| expected > max_transfer) {
+        return Err(format!("asset is larger than {} bytes", max_transfer));
+    }
+    let limit = max_transfer.saturating_sub(bytes.len()) as u64;
+    // bytes read before an error are kept, the next attempt resumes after them
+    response
+        .into_reader()
+        .take(limit)
+        .read_to_end(bytes)
+        .map_err(|e| e.to_string())?;
+    match expected {
+        Some(expected) if bytes.len() < expected => Err(format!(
+            "connection closed after {} of {} bytes",
+            bytes.len(),
+            expected
+        )),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,48 @@
+use std::{error::Error, net::SocketAddr};
+
+use uuid::Uuid;
+
+use crate::{proto::AssetHash, SyncNetworkConfig};
+
+use super::{report_failure, ServedAssets, SyncAssetTransfer};
+
+/// Browsers can neither serve http nor block on a download, the assets go over the connection.
+pub(super) struct HttpTransfer;
+
+impl HttpTransfer {
+    pub(super) fn downloads_only() -> Self {
+        Self
+    }
+
+    pub(super) fn start(
+        _addr: SocketAddr,
+        _served: ServedAssets,
+        _max_transfer: usize,
+        _config: &SyncNetworkConfig,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Err(
+            "the asset http server is not available on wasm32, send the assets over the connection"
+                .into(),
+        )
+    }
+
+    pub(super) fn port(&self) -> Option<u16> {
+        None
+    }
+
+    pub(super) fn close(&mut self) {}
+}
+
+pub(super) fn request(
+    transfer: &SyncAssetTransfer,
+    key: (String, Uuid),
+    url: String,
+    _hash: AssetHash,
+) {
+    report_failure(
+        &transfer.failures,
+        key.1,
+        url,
+        "http downloads are not available on wasm32".to_string(),
+    );
+}
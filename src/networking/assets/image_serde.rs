@@ -2,324 +2,327 @@ use bevy::{
     prelude::*,
     render::{
         render_asset::RenderAssetUsages,
-        render_resource::{Extent3d, TextureDimension, TextureFormat},
+        render_resource::{
+            Extent3d, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat,
+            TextureUsages, TextureViewDescriptor, TextureViewDimension,
+        },
+        texture::ImageSampler,
     },
 };
-use lz4_compression::{compress, decompress};
 use serde::{Deserialize, Serialize};
-use wgpu_types::{AstcBlock, AstcChannel};
 
-pub(crate) fn bin_to_image(bin: &[u8]) -> Option<Image> {
-    let bin = decompress::decompress(bin).unwrap();
+use super::codec::{compress, decompress};
+use crate::AssetCompression;
+
+pub(crate) fn bin_to_image(bin: &[u8], max_size: usize) -> Option<Image> {
+    let bin = decompress(bin, max_size).ok()?;
     let img = bincode::deserialize::<ImageData>(&bin).ok()?;
-    let dimension = match img.dimensions {
-        1 => TextureDimension::D1,
-        2 => TextureDimension::D2,
-        3 => TextureDimension::D3,
-        _ => TextureDimension::D2,
-    };
-    Some(Image::new(
-        Extent3d {
-            width: img.width,
-            height: img.height,
-            depth_or_array_layers: img.depth_or_array_layers,
+    Some(Image {
+        data: img.data,
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size: img.size,
+            mip_level_count: img.mip_level_count,
+            sample_count: img.sample_count,
+            dimension: img.dimension,
+            format: img.format,
+            usage: img.usage,
+            view_formats: &[],
         },
-        dimension,
-        img.data,
-        img.format.0,
-        RenderAssetUsages::RENDER_WORLD | RenderAssetUsages::MAIN_WORLD,
-    ))
+        sampler: img.sampler,
+        texture_view_descriptor: img.view.map(|view| TextureViewDescriptor {
+            label: None,
+            format: view.format,
+            dimension: view.dimension,
+            aspect: view.aspect,
+            base_mip_level: view.base_mip_level,
+            mip_level_count: view.mip_level_count,
+            base_array_layer: view.base_array_layer,
+            array_layer_count: view.array_layer_count,
+        }),
+        asset_usage: RenderAssetUsages::RENDER_WORLD | RenderAssetUsages::MAIN_WORLD,
+    })
 }
 
-pub(crate) fn image_to_bin(image: &Image) -> Option<Vec<u8>> {
-    let dimensions = match image.texture_descriptor.dimension {
-        TextureDimension::D1 => 1,
-        TextureDimension::D2 => 2,
-        TextureDimension::D3 => 3,
-    };
+pub(crate) fn image_to_bin(image: &Image, compression: AssetCompression) -> Option<Vec<u8>> {
+    let descriptor = &image.texture_descriptor;
     let img = ImageData {
-        width: image.texture_descriptor.size.width,
-        height: image.texture_descriptor.size.height,
-        depth_or_array_layers: image.texture_descriptor.size.depth_or_array_layers,
-        dimensions,
-        format: TextureFormatWrapper(image.texture_descriptor.format),
+        size: descriptor.size,
+        mip_level_count: descriptor.mip_level_count,
+        sample_count: descriptor.sample_count,
+        dimension: descriptor.dimension,
+        format: descriptor.format,
+        usage: descriptor.usage,
+        sampler: image.sampler.clone(),
+        view: image
+            .texture_view_descriptor
+            .as_ref()
+            .map(|view| TextureViewData {
+                format: view.format,
+                dimension: view.dimension,
+                aspect: view.aspect,
+                base_mip_level: view.base_mip_level,
+                mip_level_count: view.mip_level_count,
+                base_array_layer: view.base_array_layer,
+                array_layer_count: view.array_layer_count,
+            }),
         data: image.data.clone(),
     };
-    Some(compress::compress(&bincode::serialize(&img).ok()?))
+    compress(compression, &bincode::serialize(&img).ok()?).ok()
 }
 
+/// Image with its texture descriptor, without the labels and view formats which are static.
+/// The data holds every mip level of every layer, compressed formats included.
 #[derive(Serialize, Deserialize)]
 struct ImageData {
-    width: u32,
-    height: u32,
-    depth_or_array_layers: u32,
-    dimensions: u8,
-    format: TextureFormatWrapper,
+    size: Extent3d,
+    mip_level_count: u32,
+    sample_count: u32,
+    dimension: TextureDimension,
+    format: TextureFormat,
+    usage: TextureUsages,
+    sampler: ImageSampler,
+    view: Option<TextureViewData>,
     data: Vec<u8>,
 }
 
-struct TextureFormatWrapper(TextureFormat);
+/// TextureViewDescriptor of an image, cubemaps and texture arrays being told apart by it.
+#[derive(Serialize, Deserialize)]
+struct TextureViewData {
+    format: Option<TextureFormat>,
+    dimension: Option<TextureViewDimension>,
+    aspect: TextureAspect,
+    base_mip_level: u32,
+    mip_level_count: Option<u32>,
+    base_array_layer: u32,
+    array_layer_count: Option<u32>,
+}
 
-impl<'de> Deserialize<'de> for TextureFormatWrapper {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        use serde::de::{self, Error, Unexpected};
+#[cfg(test)]
+mod test {
+    use bevy::render::texture::{ImageAddressMode, ImageFilterMode, ImageSamplerDescriptor};
+    use wgpu_types::{AstcBlock, AstcChannel};
 
-        struct TextureFormatVisitor;
+    use super::*;
 
-        impl de::Visitor<'_> for TextureFormatVisitor {
-            type Value = TextureFormat;
+    #[test]
+    fn test_image() {
+        let img = Image::default();
+        let bin = image_to_bin(&img, AssetCompression::Lz4).unwrap();
+        let img2 = bin_to_image(&bin, usize::MAX).unwrap();
+        assert_eq!(img.data, img2.data);
+    }
 
-            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                formatter.write_str("a valid texture format")
-            }
+    #[test]
+    fn test_image_with_every_compression() {
+        let img = Image::default();
+        for compression in [AssetCompression::None, AssetCompression::Zstd] {
+            let bin = image_to_bin(&img, compression).unwrap();
+            let img2 = bin_to_image(&bin, usize::MAX).unwrap();
+            assert_eq!(img.data, img2.data);
+        }
+    }
 
-            fn visit_str<E: Error>(self, s: &str) -> Result<Self::Value, E> {
-                let format = match s {
-                    "r8unorm" => TextureFormat::R8Unorm,
-                    "r8snorm" => TextureFormat::R8Snorm,
-                    "r8uint" => TextureFormat::R8Uint,
-                    "r8sint" => TextureFormat::R8Sint,
-                    "r16uint" => TextureFormat::R16Uint,
-                    "r16sint" => TextureFormat::R16Sint,
-                    "r16unorm" => TextureFormat::R16Unorm,
-                    "r16snorm" => TextureFormat::R16Snorm,
-                    "r16float" => TextureFormat::R16Float,
-                    "rg8unorm" => TextureFormat::Rg8Unorm,
-                    "rg8snorm" => TextureFormat::Rg8Snorm,
-                    "rg8uint" => TextureFormat::Rg8Uint,
-                    "rg8sint" => TextureFormat::Rg8Sint,
-                    "r32uint" => TextureFormat::R32Uint,
-                    "r32sint" => TextureFormat::R32Sint,
-                    "r32float" => TextureFormat::R32Float,
-                    "rg16uint" => TextureFormat::Rg16Uint,
-                    "rg16sint" => TextureFormat::Rg16Sint,
-                    "rg16unorm" => TextureFormat::Rg16Unorm,
-                    "rg16snorm" => TextureFormat::Rg16Snorm,
-                    "rg16float" => TextureFormat::Rg16Float,
-                    "rgba8unorm" => TextureFormat::Rgba8Unorm,
-                    "rgba8unorm-srgb" => TextureFormat::Rgba8UnormSrgb,
-                    "rgba8snorm" => TextureFormat::Rgba8Snorm,
-                    "rgba8uint" => TextureFormat::Rgba8Uint,
-                    "rgba8sint" => TextureFormat::Rgba8Sint,
-                    "bgra8unorm" => TextureFormat::Bgra8Unorm,
-                    "bgra8unorm-srgb" => TextureFormat::Bgra8UnormSrgb,
-                    "rgb10a2uint" => TextureFormat::Rgb10a2Uint,
-                    "rgb10a2unorm" => TextureFormat::Rgb10a2Unorm,
-                    "rg11b10ufloat" => TextureFormat::Rg11b10Float,
-                    "rg32uint" => TextureFormat::Rg32Uint,
-                    "rg32sint" => TextureFormat::Rg32Sint,
-                    "rg32float" => TextureFormat::Rg32Float,
-                    "rgba16uint" => TextureFormat::Rgba16Uint,
-                    "rgba16sint" => TextureFormat::Rgba16Sint,
-                    "rgba16unorm" => TextureFormat::Rgba16Unorm,
-                    "rgba16snorm" => TextureFormat::Rgba16Snorm,
-                    "rgba16float" => TextureFormat::Rgba16Float,
-                    "rgba32uint" => TextureFormat::Rgba32Uint,
-                    "rgba32sint" => TextureFormat::Rgba32Sint,
-                    "rgba32float" => TextureFormat::Rgba32Float,
-                    "stencil8" => TextureFormat::Stencil8,
-                    "depth32float" => TextureFormat::Depth32Float,
-                    "depth32float-stencil8" => TextureFormat::Depth32FloatStencil8,
-                    "depth16unorm" => TextureFormat::Depth16Unorm,
-                    "depth24plus" => TextureFormat::Depth24Plus,
-                    "depth24plus-stencil8" => TextureFormat::Depth24PlusStencil8,
-                    "nv12" => TextureFormat::NV12,
-                    "rgb9e5ufloat" => TextureFormat::Rgb9e5Ufloat,
-                    "bc1-rgba-unorm" => TextureFormat::Bc1RgbaUnorm,
-                    "bc1-rgba-unorm-srgb" => TextureFormat::Bc1RgbaUnormSrgb,
-                    "bc2-rgba-unorm" => TextureFormat::Bc2RgbaUnorm,
-                    "bc2-rgba-unorm-srgb" => TextureFormat::Bc2RgbaUnormSrgb,
-                    "bc3-rgba-unorm" => TextureFormat::Bc3RgbaUnorm,
-                    "bc3-rgba-unorm-srgb" => TextureFormat::Bc3RgbaUnormSrgb,
-                    "bc4-r-unorm" => TextureFormat::Bc4RUnorm,
-                    "bc4-r-snorm" => TextureFormat::Bc4RSnorm,
-                    "bc5-rg-unorm" => TextureFormat::Bc5RgUnorm,
-                    "bc5-rg-snorm" => TextureFormat::Bc5RgSnorm,
-                    "bc6h-rgb-ufloat" => TextureFormat::Bc6hRgbUfloat,
-                    "bc6h-rgb-float" => TextureFormat::Bc6hRgbFloat,
-                    "bc7-rgba-unorm" => TextureFormat::Bc7RgbaUnorm,
-                    "bc7-rgba-unorm-srgb" => TextureFormat::Bc7RgbaUnormSrgb,
-                    "etc2-rgb8unorm" => TextureFormat::Etc2Rgb8Unorm,
-                    "etc2-rgb8unorm-srgb" => TextureFormat::Etc2Rgb8UnormSrgb,
-                    "etc2-rgb8a1unorm" => TextureFormat::Etc2Rgb8A1Unorm,
-                    "etc2-rgb8a1unorm-srgb" => TextureFormat::Etc2Rgb8A1UnormSrgb,
-                    "etc2-rgba8unorm" => TextureFormat::Etc2Rgba8Unorm,
-                    "etc2-rgba8unorm-srgb" => TextureFormat::Etc2Rgba8UnormSrgb,
-                    "eac-r11unorm" => TextureFormat::EacR11Unorm,
-                    "eac-r11snorm" => TextureFormat::EacR11Snorm,
-                    "eac-rg11unorm" => TextureFormat::EacRg11Unorm,
-                    "eac-rg11snorm" => TextureFormat::EacRg11Snorm,
-                    other => {
-                        if let Some(parts) = other.strip_prefix("astc-") {
-                            let (block, channel) = parts
-                                .split_once('-')
-                                .ok_or_else(|| E::invalid_value(Unexpected::Str(s), &self))?;
+    #[test]
+    fn test_uncompressed_formats() {
+        for format in [
+            TextureFormat::R8Unorm,
+            TextureFormat::R8Snorm,
+            TextureFormat::R8Uint,
+            TextureFormat::R8Sint,
+            TextureFormat::R16Uint,
+            TextureFormat::R16Sint,
+            TextureFormat::R16Unorm,
+            TextureFormat::R16Snorm,
+            TextureFormat::R16Float,
+            TextureFormat::Rg8Unorm,
+            TextureFormat::Rg8Snorm,
+            TextureFormat::Rg8Uint,
+            TextureFormat::Rg8Sint,
+            TextureFormat::R32Uint,
+            TextureFormat::R32Sint,
+            TextureFormat::R32Float,
+            TextureFormat::Rg16Uint,
+            TextureFormat::Rg16Sint,
+            TextureFormat::Rg16Unorm,
+            TextureFormat::Rg16Snorm,
+            TextureFormat::Rg16Float,
+            TextureFormat::Rgba8Unorm,
+            TextureFormat::Rgba8UnormSrgb,
+            TextureFormat::Rgba8Snorm,
+            TextureFormat::Rgba8Uint,
+            TextureFormat::Rgba8Sint,
+            TextureFormat::Bgra8Unorm,
+            TextureFormat::Bgra8UnormSrgb,
+            TextureFormat::Rgb9e5Ufloat,
+            TextureFormat::Rgb10a2Uint,
+            TextureFormat::Rgb10a2Unorm,
+            TextureFormat::Rg11b10Float,
+            TextureFormat::Rg32Uint,
+            TextureFormat::Rg32Sint,
+            TextureFormat::Rg32Float,
+            TextureFormat::Rgba16Uint,
+            TextureFormat::Rgba16Sint,
+            TextureFormat::Rgba16Unorm,
+            TextureFormat::Rgba16Snorm,
+            TextureFormat::Rgba16Float,
+            TextureFormat::Rgba32Uint,
+            TextureFormat::Rgba32Sint,
+            TextureFormat::Rgba32Float,
+            TextureFormat::NV12,
+        ] {
+            assert_format_kept(format);
+        }
+    }
 
-                            let block = match block {
-                                "4x4" => AstcBlock::B4x4,
-                                "5x4" => AstcBlock::B5x4,
-                                "5x5" => AstcBlock::B5x5,
-                                "6x5" => AstcBlock::B6x5,
-                                "6x6" => AstcBlock::B6x6,
-                                "8x5" => AstcBlock::B8x5,
-                                "8x6" => AstcBlock::B8x6,
-                                "8x8" => AstcBlock::B8x8,
-                                "10x5" => AstcBlock::B10x5,
-                                "10x6" => AstcBlock::B10x6,
-                                "10x8" => AstcBlock::B10x8,
-                                "10x10" => AstcBlock::B10x10,
-                                "12x10" => AstcBlock::B12x10,
-                                "12x12" => AstcBlock::B12x12,
-                                _ => return Err(E::invalid_value(Unexpected::Str(s), &self)),
-                            };
+    #[test]
+    fn test_depth_stencil_formats() {
+        for format in [
+            TextureFormat::Stencil8,
+            TextureFormat::Depth16Unorm,
+            TextureFormat::Depth24Plus,
+            TextureFormat::Depth24PlusStencil8,
+            TextureFormat::Depth32Float,
+            TextureFormat::Depth32FloatStencil8,
+        ] {
+            assert_format_kept(format);
+        }
+    }
 
-                            let channel = match channel {
-                                "unorm" => AstcChannel::Unorm,
-                                "unorm-srgb" => AstcChannel::UnormSrgb,
-                                "hdr" => AstcChannel::Hdr,
-                                _ => return Err(E::invalid_value(Unexpected::Str(s), &self)),
-                            };
+    #[test]
+    fn test_bc_formats() {
+        for format in [
+            TextureFormat::Bc1RgbaUnorm,
+            TextureFormat::Bc1RgbaUnormSrgb,
+            TextureFormat::Bc2RgbaUnorm,
+            TextureFormat::Bc2RgbaUnormSrgb,
+            TextureFormat::Bc3RgbaUnorm,
+            TextureFormat::Bc3RgbaUnormSrgb,
+            TextureFormat::Bc4RUnorm,
+            TextureFormat::Bc4RSnorm,
+            TextureFormat::Bc5RgUnorm,
+            TextureFormat::Bc5RgSnorm,
+            TextureFormat::Bc6hRgbUfloat,
+            TextureFormat::Bc6hRgbFloat,
+            TextureFormat::Bc7RgbaUnorm,
+            TextureFormat::Bc7RgbaUnormSrgb,
+        ] {
+            assert_format_kept(format);
+        }
+    }
 
-                            TextureFormat::Astc { block, channel }
-                        } else {
-                            return Err(E::invalid_value(Unexpected::Str(s), &self));
-                        }
-                    }
-                };
+    #[test]
+    fn test_etc_formats() {
+        for format in [
+            TextureFormat::Etc2Rgb8Unorm,
+            TextureFormat::Etc2Rgb8UnormSrgb,
+            TextureFormat::Etc2Rgb8A1Unorm,
+            TextureFormat::Etc2Rgb8A1UnormSrgb,
+            TextureFormat::Etc2Rgba8Unorm,
+            TextureFormat::Etc2Rgba8UnormSrgb,
+            TextureFormat::EacR11Unorm,
+            TextureFormat::EacR11Snorm,
+            TextureFormat::EacRg11Unorm,
+            TextureFormat::EacRg11Snorm,
+        ] {
+            assert_format_kept(format);
+        }
+    }
 
-                Ok(format)
+    #[test]
+    fn test_astc_formats() {
+        for block in [
+            AstcBlock::B4x4,
+            AstcBlock::B5x4,
+            AstcBlock::B5x5,
+            AstcBlock::B6x5,
+            AstcBlock::B6x6,
+            AstcBlock::B8x5,
+            AstcBlock::B8x6,
+            AstcBlock::B8x8,
+            AstcBlock::B10x5,
+            AstcBlock::B10x6,
+            AstcBlock::B10x8,
+            AstcBlock::B10x10,
+            AstcBlock::B12x10,
+            AstcBlock::B12x12,
+        ] {
+            for channel in [AstcChannel::Unorm, AstcChannel::UnormSrgb, AstcChannel::Hdr] {
+                assert_format_kept(TextureFormat::Astc { block, channel });
             }
         }
-
-        deserializer.deserialize_str(TextureFormatVisitor).map(TextureFormatWrapper)
     }
-}
 
-impl Serialize for TextureFormatWrapper {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        let s: String;
-        let name = match self.0 {
-            TextureFormat::R8Unorm => "r8unorm",
-            TextureFormat::R8Snorm => "r8snorm",
-            TextureFormat::R8Uint => "r8uint",
-            TextureFormat::R8Sint => "r8sint",
-            TextureFormat::R16Uint => "r16uint",
-            TextureFormat::R16Sint => "r16sint",
-            TextureFormat::R16Unorm => "r16unorm",
-            TextureFormat::R16Snorm => "r16snorm",
-            TextureFormat::R16Float => "r16float",
-            TextureFormat::Rg8Unorm => "rg8unorm",
-            TextureFormat::Rg8Snorm => "rg8snorm",
-            TextureFormat::Rg8Uint => "rg8uint",
-            TextureFormat::Rg8Sint => "rg8sint",
-            TextureFormat::R32Uint => "r32uint",
-            TextureFormat::R32Sint => "r32sint",
-            TextureFormat::R32Float => "r32float",
-            TextureFormat::Rg16Uint => "rg16uint",
-            TextureFormat::Rg16Sint => "rg16sint",
-            TextureFormat::Rg16Unorm => "rg16unorm",
-            TextureFormat::Rg16Snorm => "rg16snorm",
-            TextureFormat::Rg16Float => "rg16float",
-            TextureFormat::Rgba8Unorm => "rgba8unorm",
-            TextureFormat::Rgba8UnormSrgb => "rgba8unorm-srgb",
-            TextureFormat::Rgba8Snorm => "rgba8snorm",
-            TextureFormat::Rgba8Uint => "rgba8uint",
-            TextureFormat::Rgba8Sint => "rgba8sint",
-            TextureFormat::Bgra8Unorm => "bgra8unorm",
-            TextureFormat::Bgra8UnormSrgb => "bgra8unorm-srgb",
-            TextureFormat::Rgb10a2Uint => "rgb10a2uint",
-            TextureFormat::Rgb10a2Unorm => "rgb10a2unorm",
-            TextureFormat::Rg11b10Float => "rg11b10ufloat",
-            TextureFormat::Rg32Uint => "rg32uint",
-            TextureFormat::Rg32Sint => "rg32sint",
-            TextureFormat::Rg32Float => "rg32float",
-            TextureFormat::Rgba16Uint => "rgba16uint",
-            TextureFormat::Rgba16Sint => "rgba16sint",
-            TextureFormat::Rgba16Unorm => "rgba16unorm",
-            TextureFormat::Rgba16Snorm => "rgba16snorm",
-            TextureFormat::Rgba16Float => "rgba16float",
-            TextureFormat::Rgba32Uint => "rgba32uint",
-            TextureFormat::Rgba32Sint => "rgba32sint",
-            TextureFormat::Rgba32Float => "rgba32float",
-            TextureFormat::Stencil8 => "stencil8",
-            TextureFormat::Depth32Float => "depth32float",
-            TextureFormat::Depth16Unorm => "depth16unorm",
-            TextureFormat::Depth32FloatStencil8 => "depth32float-stencil8",
-            TextureFormat::Depth24Plus => "depth24plus",
-            TextureFormat::Depth24PlusStencil8 => "depth24plus-stencil8",
-            TextureFormat::NV12 => "nv12",
-            TextureFormat::Rgb9e5Ufloat => "rgb9e5ufloat",
-            TextureFormat::Bc1RgbaUnorm => "bc1-rgba-unorm",
-            TextureFormat::Bc1RgbaUnormSrgb => "bc1-rgba-unorm-srgb",
-            TextureFormat::Bc2RgbaUnorm => "bc2-rgba-unorm",
-            TextureFormat::Bc2RgbaUnormSrgb => "bc2-rgba-unorm-srgb",
-            TextureFormat::Bc3RgbaUnorm => "bc3-rgba-unorm",
-            TextureFormat::Bc3RgbaUnormSrgb => "bc3-rgba-unorm-srgb",
-            TextureFormat::Bc4RUnorm => "bc4-r-unorm",
-            TextureFormat::Bc4RSnorm => "bc4-r-snorm",
-            TextureFormat::Bc5RgUnorm => "bc5-rg-unorm",
-            TextureFormat::Bc5RgSnorm => "bc5-rg-snorm",
-            TextureFormat::Bc6hRgbUfloat => "bc6h-rgb-ufloat",
-            TextureFormat::Bc6hRgbFloat => "bc6h-rgb-float",
-            TextureFormat::Bc7RgbaUnorm => "bc7-rgba-unorm",
-            TextureFormat::Bc7RgbaUnormSrgb => "bc7-rgba-unorm-srgb",
-            TextureFormat::Etc2Rgb8Unorm => "etc2-rgb8unorm",
-            TextureFormat::Etc2Rgb8UnormSrgb => "etc2-rgb8unorm-srgb",
-            TextureFormat::Etc2Rgb8A1Unorm => "etc2-rgb8a1unorm",
-            TextureFormat::Etc2Rgb8A1UnormSrgb => "etc2-rgb8a1unorm-srgb",
-            TextureFormat::Etc2Rgba8Unorm => "etc2-rgba8unorm",
-            TextureFormat::Etc2Rgba8UnormSrgb => "etc2-rgba8unorm-srgb",
-            TextureFormat::EacR11Unorm => "eac-r11unorm",
-            TextureFormat::EacR11Snorm => "eac-r11snorm",
-            TextureFormat::EacRg11Unorm => "eac-rg11unorm",
-            TextureFormat::EacRg11Snorm => "eac-rg11snorm",
-            TextureFormat::Astc { block, channel } => {
-                let block = match block {
-                    AstcBlock::B4x4 => "4x4",
-                    AstcBlock::B5x4 => "5x4",
-                    AstcBlock::B5x5 => "5x5",
-                    AstcBlock::B6x5 => "6x5",
-                    AstcBlock::B6x6 => "6x6",
-                    AstcBlock::B8x5 => "8x5",
-                    AstcBlock::B8x6 => "8x6",
-                    AstcBlock::B8x8 => "8x8",
-                    AstcBlock::B10x5 => "10x5",
-                    AstcBlock::B10x6 => "10x6",
-                    AstcBlock::B10x8 => "10x8",
-                    AstcBlock::B10x10 => "10x10",
-                    AstcBlock::B12x10 => "12x10",
-                    AstcBlock::B12x12 => "12x12",
-                };
+    #[test]
+    fn test_cubemap_with_mips_and_sampler() {
+        let mut img = image_of_format(TextureFormat::Bc7RgbaUnormSrgb);
+        img.texture_descriptor.size = Extent3d {
+            width: 8,
+            height: 8,
+            depth_or_array_layers: 6,
+        };
+        img.texture_descriptor.mip_level_count = 4;
+        img.data = (0..6 * (64 + 16 + 16 + 16)).map(|i| i as u8).collect();
+        img.texture_view_descriptor = Some(TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::Cube),
+            mip_level_count: Some(3),
+            base_mip_level: 1,
+            ..default()
+        });
+        img.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+            address_mode_u: ImageAddressMode::Repeat,
+            mag_filter: ImageFilterMode::Linear,
+            anisotropy_clamp: 4,
+            ..default()
+        });
 
-                let channel = match channel {
-                    AstcChannel::Unorm => "unorm",
-                    AstcChannel::UnormSrgb => "unorm-srgb",
-                    AstcChannel::Hdr => "hdr",
-                };
+        let bin = image_to_bin(&img, AssetCompression::Zstd).unwrap();
+        let img2 = bin_to_image(&bin, usize::MAX).unwrap();
 
-                s = format!("astc-{block}-{channel}");
-                &s
-            }
+        assert_eq!(img.texture_descriptor.size, img2.texture_descriptor.size);
+        assert_eq!(img2.texture_descriptor.mip_level_count, 4);
+        assert_eq!(img.texture_descriptor.usage, img2.texture_descriptor.usage);
+        assert_eq!(img.data, img2.data);
+        let view = img2.texture_view_descriptor.unwrap();
+        assert_eq!(view.dimension, Some(TextureViewDimension::Cube));
+        assert_eq!(view.mip_level_count, Some(3));
+        assert_eq!(view.base_mip_level, 1);
+        let ImageSampler::Descriptor(sampler) = img2.sampler else {
+            panic!("sampler descriptor lost");
         };
-        serializer.serialize_str(name)
+        assert!(matches!(sampler.address_mode_u, ImageAddressMode::Repeat));
+        assert!(matches!(sampler.mag_filter, ImageFilterMode::Linear));
+        assert_eq!(sampler.anisotropy_clamp, 4);
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
 
     #[test]
-    fn test_image() {
-        let img = Image::default();
-        let bin = image_to_bin(&img).unwrap();
-        let img2 = bin_to_image(&bin).unwrap();
-        assert_eq!(img.data, img2.data);
+    fn test_invalid_image_data() {
+        assert!(bin_to_image(&[1, 2, 3], usize::MAX).is_none());
+        let mut bin = image_to_bin(&Image::default(), AssetCompression::None).unwrap();
+        bin.truncate(bin.len() / 2);
+        assert!(bin_to_image(&bin, usize::MAX).is_none());
+    }
+
+    fn image_of_format(format: TextureFormat) -> Image {
+        let mut img = Image::default();
+        img.texture_descriptor.format = format;
+        let (width, height) = format.block_dimensions();
+        img.texture_descriptor.size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let block_size = format.block_copy_size(None).unwrap_or(8);
+        img.data = (0..block_size).map(|i| i as u8).collect();
+        img
+    }
+
+    fn assert_format_kept(format: TextureFormat) {
+        let img = image_of_format(format);
+        let bin = image_to_bin(&img, AssetCompression::Lz4).unwrap();
+        let img2 = bin_to_image(&bin, usize::MAX).unwrap();
+        assert_eq!(img2.texture_descriptor.format, format);
+        assert_eq!(img.texture_descriptor.size, img2.texture_descriptor.size);
+        assert_eq!(img.data, img2.data, "{:?}", format);
     }
 }
@@ -1,75 +1,72 @@
-use bevy::render::mesh::Indices;
-use bevy::render::mesh::VertexAttributeValues::{Float32x2, Float32x3, Float32x4, Uint16x4};
+use std::error::Error;
+
+use bevy::render::mesh::{
+    Indices, MeshVertexAttribute, MeshVertexAttributeId, VertexAttributeValues,
+};
 use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::VertexFormat;
 use bevy::{prelude::*, render::render_resource::PrimitiveTopology};
-use lz4_compression::{compress, decompress};
 use serde::{Deserialize, Serialize};
 
+use super::codec::{compress, decompress};
+use crate::AssetCompression;
+
+type MeshSerdeError = Box<dyn Error + Send + Sync>;
+
+/// Vertex attributes registered with sync_mesh_attribute, with the id they were created with.
+pub(crate) type MeshAttributes = [(usize, MeshVertexAttribute)];
+
 #[derive(Serialize, Deserialize)]
 struct MeshData {
     mesh_type: u8,
-    positions: Option<Vec<[f32; 3]>>,
-    normals: Option<Vec<[f32; 3]>>,
-    uvs0: Option<Vec<[f32; 2]>>,
-    uvs1: Option<Vec<[f32; 2]>>,
-    tangents: Option<Vec<[f32; 4]>>,
-    colors: Option<Vec<[f32; 4]>>,
-    joint_weights: Option<Vec<[f32; 4]>>,
-    joint_indices: Option<Vec<[u16; 4]>>,
+    attributes: Vec<AttributeData>,
     indices32: Option<Vec<u32>>,
     indices16: Option<Vec<u16>>,
     morph_targets: Option<AssetId<Image>>,
     morph_target_names: Option<Vec<String>>,
 }
 
-pub(crate) fn mesh_to_bin(mesh: &Mesh) -> Vec<u8> {
-    let positions = if let Some(Float32x3(t)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
-        Some(t.clone())
-    } else {
-        None
-    };
-
-    let tangents = if let Some(Float32x4(t)) = mesh.attribute(Mesh::ATTRIBUTE_TANGENT) {
-        Some(t.clone())
-    } else {
-        None
-    };
-
-    let normals = if let Some(Float32x3(t)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
-        Some(t.clone())
-    } else {
-        None
-    };
-
-    let uvs0 = if let Some(Float32x2(t)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) {
-        Some(t.clone())
-    } else {
-        None
-    };
-
-    let uvs1 = if let Some(Float32x2(t)) = mesh.attribute(Mesh::ATTRIBUTE_UV_1) {
-        Some(t.clone())
-    } else {
-        None
-    };
-
-    let colors = if let Some(Float32x4(t)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR) {
-        Some(t.clone())
-    } else {
-        None
-    };
+#[derive(Serialize, Deserialize)]
+struct AttributeData {
+    id: usize,
+    format: u8,
+    values: AttributeValues,
+}
 
-    let joint_weights = if let Some(Float32x4(t)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT) {
-        Some(t.clone())
-    } else {
-        None
-    };
+#[derive(Serialize, Deserialize)]
+enum AttributeValues {
+    /// The bytes of the values in their VertexFormat.
+    Raw(Vec<u8>),
+    /// Float values mapped to 16 bits over the range from min to max.
+    Quantized {
+        min: f32,
+        max: f32,
+        values: Vec<u16>,
+    },
+}
 
-    let joint_indices = if let Some(Uint16x4(t)) = mesh.attribute(Mesh::ATTRIBUTE_JOINT_INDEX) {
-        Some(t.clone())
-    } else {
-        None
-    };
+pub(crate) fn mesh_to_bin(
+    mesh: &Mesh,
+    registered: &MeshAttributes,
+    compression: AssetCompression,
+    quantize: bool,
+) -> Result<Vec<u8>, MeshSerdeError> {
+    let attributes = mesh
+        .attributes()
+        .map(|(id, values)| {
+            let quantized = QUANTIZED_ATTRIBUTES
+                .iter()
+                .any(|attribute| attribute.id == id);
+            Ok(AttributeData {
+                id: attribute_id(id, registered)?,
+                format: format_to_u8(VertexFormat::from(values))?,
+                values: match quantize && quantized {
+                    true => quantize_values(values),
+                    false => AttributeValues::Raw(values.get_bytes().to_vec()),
+                },
+            })
+        })
+        .collect::<Result<_, MeshSerdeError>>()?;
 
     let indices32 = if let Some(Indices::U32(t)) = mesh.indices() {
         Some(t.clone())
@@ -83,10 +80,8 @@ pub(crate) fn mesh_to_bin(mesh: &Mesh) -> Vec<u8> {
         None
     };
 
-    let morph_targets = extract_morph_targets(mesh).as_ref().and_then(|m| match m {
-        Handle::Strong(_) => None,
-        Handle::Weak(id) => Some(*id),
-    });
+    // mapped to the uuid of the image before serving, the image is synched on its own
+    let morph_targets = extract_morph_targets(mesh).as_ref().map(|m| m.id());
     let morph_target_names = mesh.morph_target_names().map(|v| v.to_vec());
     let mesh_type_num = match mesh.primitive_topology() {
         PrimitiveTopology::PointList => 0,
@@ -98,24 +93,17 @@ pub(crate) fn mesh_to_bin(mesh: &Mesh) -> Vec<u8> {
 
     let data = MeshData {
         mesh_type: mesh_type_num,
-        positions,
-        normals,
-        uvs0,
-        uvs1,
-        tangents,
-        colors,
-        joint_weights,
-        joint_indices,
+        attributes,
         indices32,
         indices16,
         morph_targets,
         morph_target_names,
     };
 
-    compress::compress(&bincode::serialize(&data).unwrap())
+    Ok(compress(compression, &bincode::serialize(&data)?)?)
 }
 
-fn extract_morph_targets(mesh: &Mesh) -> &Option<Handle<Image>> {
+pub(crate) fn extract_morph_targets(mesh: &Mesh) -> &Option<Handle<Image>> {
     let refmesh = mesh as &dyn Struct;
     let morph_targets = refmesh
         .field("morph_targets")
@@ -125,14 +113,13 @@ fn extract_morph_targets(mesh: &Mesh) -> &Option<Handle<Image>> {
     morph_targets
 }
 
-pub(crate) fn bin_to_mesh(binary: &[u8]) -> Mesh {
-    let binary = decompress::decompress(binary).unwrap();
-    let Ok(data) = bincode::deserialize::<MeshData>(&binary) else {
-        return Mesh::new(
-            PrimitiveTopology::TriangleList,
-            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
-        );
-    };
+pub(crate) fn bin_to_mesh(
+    binary: &[u8],
+    registered: &MeshAttributes,
+    max_size: usize,
+) -> Result<Mesh, MeshSerdeError> {
+    let binary = decompress(binary, max_size)?;
+    let data = bincode::deserialize::<MeshData>(&binary)?;
 
     let mesh_type_enum = match data.mesh_type {
         0 => PrimitiveTopology::PointList,
@@ -148,36 +135,32 @@ pub(crate) fn bin_to_mesh(binary: &[u8]) -> Mesh {
         RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
     );
 
-    if let Some(positions) = data.positions {
-        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    }
-
-    if let Some(normals) = data.normals {
-        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    }
-
-    if let Some(uvs0) = data.uvs0 {
-        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs0);
-    }
-
-    if let Some(uvs1) = data.uvs1 {
-        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_1, uvs1);
-    }
-
-    if let Some(tangents) = data.tangents {
-        mesh.insert_attribute(Mesh::ATTRIBUTE_TANGENT, tangents);
-    }
-
-    if let Some(colors) = data.colors {
-        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
-    }
-
-    if let Some(joint_weights) = data.joint_weights {
-        mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, joint_weights);
-    }
-
-    if let Some(joint_indices) = data.joint_indices {
-        mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_INDEX, Uint16x4(joint_indices));
+    for attribute in data.attributes {
+        let format = format_from_u8(attribute.format)
+            .ok_or_else(|| format!("unknown vertex format {}", attribute.format))?;
+        let bytes = match attribute.values {
+            AttributeValues::Raw(bytes) => bytes,
+            AttributeValues::Quantized { min, max, values } => dequantize(min, max, &values),
+        };
+        let values = bytes_to_values(format, &bytes).ok_or_else(|| {
+            format!(
+                "invalid values for vertex attribute {} of format {:?}",
+                attribute.id, format
+            )
+        })?;
+        let vertex_attribute =
+            match known_attributes(registered).find(|(id, _)| *id == attribute.id) {
+                Some((_, known)) if known.format != format => {
+                    return Err(format!(
+                        "vertex attribute {} is {:?}, received as {:?}",
+                        known.name, known.format, format
+                    )
+                    .into())
+                }
+                Some((_, known)) => known.clone(),
+                None => return Err(format!("unknown vertex attribute {}", attribute.id).into()),
+            };
+        mesh.insert_attribute(vertex_attribute, values);
     }
 
     if let Some(indices) = data.indices32 {
@@ -195,7 +178,179 @@ pub(crate) fn bin_to_mesh(binary: &[u8]) -> Mesh {
         mesh.set_morph_target_names(morph_target_names);
     }
 
-    mesh
+    Ok(mesh)
+}
+
+/// The built in attributes, with the id they are created with.
+static BUILT_IN_ATTRIBUTES: [(usize, MeshVertexAttribute); 8] = [
+    (0, Mesh::ATTRIBUTE_POSITION),
+    (1, Mesh::ATTRIBUTE_NORMAL),
+    (2, Mesh::ATTRIBUTE_UV_0),
+    (3, Mesh::ATTRIBUTE_UV_1),
+    (4, Mesh::ATTRIBUTE_TANGENT),
+    (5, Mesh::ATTRIBUTE_COLOR),
+    (6, Mesh::ATTRIBUTE_JOINT_WEIGHT),
+    (7, Mesh::ATTRIBUTE_JOINT_INDEX),
+];
+
+fn known_attributes(
+    registered: &MeshAttributes,
+) -> impl Iterator<Item = &(usize, MeshVertexAttribute)> {
+    BUILT_IN_ATTRIBUTES.iter().chain(registered)
+}
+
+/// Attributes quantized when enabled, their float values lose some precision.
+const QUANTIZED_ATTRIBUTES: [MeshVertexAttribute; 4] = [
+    Mesh::ATTRIBUTE_POSITION,
+    Mesh::ATTRIBUTE_NORMAL,
+    Mesh::ATTRIBUTE_UV_0,
+    Mesh::ATTRIBUTE_UV_1,
+];
+
+fn quantize_values(values: &VertexAttributeValues) -> AttributeValues {
+    let floats: &[f32] = match values {
+        VertexAttributeValues::Float32x2(v) => v.as_flattened(),
+        VertexAttributeValues::Float32x3(v) => v.as_flattened(),
+        _ => return AttributeValues::Raw(values.get_bytes().to_vec()),
+    };
+    let min = floats.iter().copied().fold(f32::INFINITY, f32::min);
+    let max = floats.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    if !min.is_finite() || !max.is_finite() {
+        return AttributeValues::Raw(values.get_bytes().to_vec());
+    }
+    let scale = match max > min {
+        true => u16::MAX as f32 / (max - min),
+        false => 0.,
+    };
+    let values = floats
+        .iter()
+        .map(|v| ((v - min) * scale).round() as u16)
+        .collect();
+    AttributeValues::Quantized { min, max, values }
+}
+
+/// Bytes of the f32 values quantized by quantize_values.
+fn dequantize(min: f32, max: f32, values: &[u16]) -> Vec<u8> {
+    let step = (max - min) / u16::MAX as f32;
+    values
+        .iter()
+        .flat_map(|v| (min + *v as f32 * step).to_le_bytes())
+        .collect()
+}
+
+/// The id a vertex attribute was created with, MeshVertexAttributeId does not expose it so only
+/// the built in and registered attributes are known.
+fn attribute_id(
+    id: MeshVertexAttributeId,
+    registered: &MeshAttributes,
+) -> Result<usize, MeshSerdeError> {
+    known_attributes(registered)
+        .find(|(_, known)| known.id == id)
+        .map(|(known, _)| *known)
+        .ok_or_else(|| format!("vertex attribute {:?} is not registered", id).into())
+}
+
+/// The formats a VertexAttributeValues can hold.
+const VERTEX_FORMATS: [VertexFormat; 28] = [
+    VertexFormat::Float32,
+    VertexFormat::Sint32,
+    VertexFormat::Uint32,
+    VertexFormat::Float32x2,
+    VertexFormat::Sint32x2,
+    VertexFormat::Uint32x2,
+    VertexFormat::Float32x3,
+    VertexFormat::Sint32x3,
+    VertexFormat::Uint32x3,
+    VertexFormat::Float32x4,
+    VertexFormat::Sint32x4,
+    VertexFormat::Uint32x4,
+    VertexFormat::Sint16x2,
+    VertexFormat::Snorm16x2,
+    VertexFormat::Uint16x2,
+    VertexFormat::Unorm16x2,
+    VertexFormat::Sint16x4,
+    VertexFormat::Snorm16x4,
+    VertexFormat::Uint16x4,
+    VertexFormat::Unorm16x4,
+    VertexFormat::Sint8x2,
+    VertexFormat::Snorm8x2,
+    VertexFormat::Uint8x2,
+    VertexFormat::Unorm8x2,
+    VertexFormat::Sint8x4,
+    VertexFormat::Snorm8x4,
+    VertexFormat::Uint8x4,
+    VertexFormat::Unorm8x4,
+];
+
+fn format_to_u8(format: VertexFormat) -> Result<u8, MeshSerdeError> {
+    VERTEX_FORMATS
+        .iter()
+        .position(|known| *known == format)
+        .map(|position| position as u8)
+        .ok_or_else(|| format!("unsupported vertex format {:?}", format).into())
+}
+
+fn format_from_u8(format: u8) -> Option<VertexFormat> {
+    VERTEX_FORMATS.get(format as usize).copied()
+}
+
+fn bytes_to_values(format: VertexFormat, bytes: &[u8]) -> Option<VertexAttributeValues> {
+    use VertexAttributeValues as V;
+    Some(match format {
+        VertexFormat::Float32 => V::Float32(scalars(bytes, f32::from_le_bytes)?),
+        VertexFormat::Sint32 => V::Sint32(scalars(bytes, i32::from_le_bytes)?),
+        VertexFormat::Uint32 => V::Uint32(scalars(bytes, u32::from_le_bytes)?),
+        VertexFormat::Float32x2 => V::Float32x2(vectors(bytes, f32::from_le_bytes)?),
+        VertexFormat::Sint32x2 => V::Sint32x2(vectors(bytes, i32::from_le_bytes)?),
+        VertexFormat::Uint32x2 => V::Uint32x2(vectors(bytes, u32::from_le_bytes)?),
+        VertexFormat::Float32x3 => V::Float32x3(vectors(bytes, f32::from_le_bytes)?),
+        VertexFormat::Sint32x3 => V::Sint32x3(vectors(bytes, i32::from_le_bytes)?),
+        VertexFormat::Uint32x3 => V::Uint32x3(vectors(bytes, u32::from_le_bytes)?),
+        VertexFormat::Float32x4 => V::Float32x4(vectors(bytes, f32::from_le_bytes)?),
+        VertexFormat::Sint32x4 => V::Sint32x4(vectors(bytes, i32::from_le_bytes)?),
+        VertexFormat::Uint32x4 => V::Uint32x4(vectors(bytes, u32::from_le_bytes)?),
+        VertexFormat::Sint16x2 => V::Sint16x2(vectors(bytes, i16::from_le_bytes)?),
+        VertexFormat::Snorm16x2 => V::Snorm16x2(vectors(bytes, i16::from_le_bytes)?),
+        VertexFormat::Uint16x2 => V::Uint16x2(vectors(bytes, u16::from_le_bytes)?),
+        VertexFormat::Unorm16x2 => V::Unorm16x2(vectors(bytes, u16::from_le_bytes)?),
+        VertexFormat::Sint16x4 => V::Sint16x4(vectors(bytes, i16::from_le_bytes)?),
+        VertexFormat::Snorm16x4 => V::Snorm16x4(vectors(bytes, i16::from_le_bytes)?),
+        VertexFormat::Uint16x4 => V::Uint16x4(vectors(bytes, u16::from_le_bytes)?),
+        VertexFormat::Unorm16x4 => V::Unorm16x4(vectors(bytes, u16::from_le_bytes)?),
+        VertexFormat::Sint8x2 => V::Sint8x2(vectors(bytes, i8::from_le_bytes)?),
+        VertexFormat::Snorm8x2 => V::Snorm8x2(vectors(bytes, i8::from_le_bytes)?),
+        VertexFormat::Uint8x2 => V::Uint8x2(vectors(bytes, u8::from_le_bytes)?),
+        VertexFormat::Unorm8x2 => V::Unorm8x2(vectors(bytes, u8::from_le_bytes)?),
+        VertexFormat::Sint8x4 => V::Sint8x4(vectors(bytes, i8::from_le_bytes)?),
+        VertexFormat::Snorm8x4 => V::Snorm8x4(vectors(bytes, i8::from_le_bytes)?),
+        VertexFormat::Uint8x4 => V::Uint8x4(vectors(bytes, u8::from_le_bytes)?),
+        VertexFormat::Unorm8x4 => V::Unorm8x4(vectors(bytes, u8::from_le_bytes)?),
+        _ => return None,
+    })
+}
+
+fn scalars<T, const S: usize>(bytes: &[u8], from: fn([u8; S]) -> T) -> Option<Vec<T>> {
+    let chunks = bytes.chunks_exact(S);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    Some(
+        chunks
+            .map(|chunk| from(chunk.try_into().unwrap()))
+            .collect(),
+    )
+}
+
+fn vectors<T: Copy, const S: usize, const N: usize>(
+    bytes: &[u8],
+    from: fn([u8; S]) -> T,
+) -> Option<Vec<[T; N]>> {
+    let scalars = scalars(bytes, from)?;
+    let chunks = scalars.chunks_exact(N);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+    Some(chunks.map(|chunk| chunk.try_into().unwrap()).collect())
 }
 
 #[cfg(test)]
@@ -206,8 +361,8 @@ mod test {
     fn mesh_to_bin_to_mesh_compare() {
         let mesh = sample_mesh();
 
-        let binary = mesh_to_bin(&mesh);
-        let mesh2 = bin_to_mesh(&binary[..]);
+        let binary = mesh_to_bin(&mesh, &[], AssetCompression::Lz4, false).unwrap();
+        let mesh2 = bin_to_mesh(&binary[..], &[], usize::MAX).unwrap();
 
         assert_eq!(mesh.primitive_topology(), mesh2.primitive_topology());
         assert_eq!(
@@ -275,8 +430,8 @@ mod test {
     fn mesh_to_bin_to_mesh_idx16_compare() {
         let mesh = sample_mesh_idx16();
 
-        let binary = mesh_to_bin(&mesh);
-        let mesh2 = bin_to_mesh(&binary[..]);
+        let binary = mesh_to_bin(&mesh, &[], AssetCompression::Lz4, false).unwrap();
+        let mesh2 = bin_to_mesh(&binary[..], &[], usize::MAX).unwrap();
 
         assert_eq!(mesh.primitive_topology(), mesh2.primitive_topology());
         assert_eq!(
@@ -343,8 +498,8 @@ mod test {
     fn mesh_to_bin_to_mesh_compare_no_tangents() {
         let mesh = sample_mesh_no_tangents();
 
-        let binary = mesh_to_bin(&mesh);
-        let mesh2 = bin_to_mesh(&binary[..]);
+        let binary = mesh_to_bin(&mesh, &[], AssetCompression::Lz4, false).unwrap();
+        let mesh2 = bin_to_mesh(&binary[..], &[], usize::MAX).unwrap();
 
         assert_eq!(mesh.primitive_topology(), mesh2.primitive_topology());
         assert_eq!(
@@ -379,6 +534,122 @@ mod test {
         assert_eq!(v1, v2);
     }
 
+    #[test]
+    fn mesh_to_bin_to_mesh_keeps_custom_attributes() {
+        const ATTRIBUTE_BLEND: MeshVertexAttribute =
+            MeshVertexAttribute::new("Vertex_Blend", 988540917, VertexFormat::Float32);
+        const ATTRIBUTE_PACKED: MeshVertexAttribute =
+            MeshVertexAttribute::new("Vertex_Packed", 988540918, VertexFormat::Unorm8x4);
+        let mut mesh = sample_mesh_no_tangents();
+        mesh.insert_attribute(ATTRIBUTE_BLEND, vec![0.5, 1., 2.]);
+        mesh.insert_attribute(
+            ATTRIBUTE_PACKED,
+            VertexAttributeValues::Unorm8x4(vec![[1, 2, 3, 4], [5, 6, 7, 8], [9, 10, 11, 12]]),
+        );
+
+        let registered = [(988540917, ATTRIBUTE_BLEND), (988540918, ATTRIBUTE_PACKED)];
+        let binary = mesh_to_bin(&mesh, &registered, AssetCompression::Lz4, false).unwrap();
+        let mesh2 = bin_to_mesh(&binary[..], &registered, usize::MAX).unwrap();
+
+        assert_eq!(mesh.attributes().count(), mesh2.attributes().count());
+        for (id, values) in mesh.attributes() {
+            let values2 = mesh2.attribute(id).unwrap();
+            assert_eq!(VertexFormat::from(values), VertexFormat::from(values2));
+            assert_eq!(values.get_bytes(), values2.get_bytes());
+        }
+        let debug = format!("{:?}", mesh2);
+        assert!(debug.contains("Vertex_Blend"));
+        assert!(debug.contains("Vertex_Packed"));
+    }
+
+    #[test]
+    fn unregistered_attributes_are_rejected() {
+        const ATTRIBUTE_BLEND: MeshVertexAttribute =
+            MeshVertexAttribute::new("Vertex_Blend", 988540917, VertexFormat::Float32);
+        let registered = [(988540917, ATTRIBUTE_BLEND)];
+        let mut mesh = sample_mesh_no_tangents();
+        mesh.insert_attribute(ATTRIBUTE_BLEND, vec![0.5, 1., 2.]);
+
+        assert!(mesh_to_bin(&mesh, &[], AssetCompression::Lz4, false).is_err());
+        let binary = mesh_to_bin(&mesh, &registered, AssetCompression::Lz4, false).unwrap();
+        assert!(bin_to_mesh(&binary[..], &[], usize::MAX).is_err());
+    }
+
+    #[test]
+    fn unsupported_formats_are_rejected() {
+        assert!(format_to_u8(VertexFormat::Float64).is_err());
+        for format in VERTEX_FORMATS {
+            assert_eq!(format_from_u8(format_to_u8(format).unwrap()), Some(format));
+        }
+    }
+
+    #[test]
+    fn bin_to_mesh_reports_registered_attributes_of_another_format() {
+        const ATTRIBUTE_BLEND: MeshVertexAttribute =
+            MeshVertexAttribute::new("Vertex_Blend", 988540917, VertexFormat::Float32);
+        let mut mesh = sample_mesh_no_tangents();
+        mesh.insert_attribute(ATTRIBUTE_BLEND, vec![0.5, 1., 2.]);
+        let binary = mesh_to_bin(
+            &mesh,
+            &[(988540917, ATTRIBUTE_BLEND)],
+            AssetCompression::Lz4,
+            false,
+        )
+        .unwrap();
+
+        let registered = [(
+            988540917,
+            MeshVertexAttribute::new("Vertex_Blend", 988540917, VertexFormat::Uint32),
+        )];
+        assert!(bin_to_mesh(&binary[..], &registered, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn built_in_attributes_have_their_id() {
+        for (id, attribute) in BUILT_IN_ATTRIBUTES.iter() {
+            let rebuilt = MeshVertexAttribute::new(attribute.name, *id, attribute.format);
+            assert_eq!(rebuilt.id, attribute.id, "{}", attribute.name);
+        }
+    }
+
+    #[test]
+    fn mesh_to_bin_to_mesh_quantized() {
+        let mesh = sample_mesh();
+
+        let binary = mesh_to_bin(&mesh, &[], AssetCompression::Zstd, true).unwrap();
+        let mesh2 = bin_to_mesh(&binary[..], &[], usize::MAX).unwrap();
+
+        for attribute in QUANTIZED_ATTRIBUTES {
+            let (Some(values), Some(values2)) =
+                (mesh.attribute(attribute.id), mesh2.attribute(attribute.id))
+            else {
+                panic!("missing {}", attribute.name);
+            };
+            assert_eq!(VertexFormat::from(values), VertexFormat::from(values2));
+            let floats = scalars(values.get_bytes(), f32::from_le_bytes).unwrap();
+            let floats2 = scalars(values2.get_bytes(), f32::from_le_bytes).unwrap();
+            for (v, v2) in floats.iter().zip(floats2) {
+                assert!((v - v2).abs() < 0.001, "{} {} {}", attribute.name, v, v2);
+            }
+        }
+        // the other attributes are kept as they are
+        assert_eq!(
+            mesh.attribute(Mesh::ATTRIBUTE_TANGENT).unwrap().get_bytes(),
+            mesh2
+                .attribute(Mesh::ATTRIBUTE_TANGENT)
+                .unwrap()
+                .get_bytes()
+        );
+    }
+
+    #[test]
+    fn bin_to_mesh_reports_invalid_data() {
+        assert!(bin_to_mesh(&[1, 2, 3], &[], usize::MAX).is_err());
+        let mut binary = mesh_to_bin(&sample_mesh(), &[], AssetCompression::Lz4, false).unwrap();
+        binary.truncate(binary.len() / 2);
+        assert!(bin_to_mesh(&binary[..], &[], usize::MAX).is_err());
+    }
+
     fn sample_mesh() -> Mesh {
         let mut mesh = Mesh::new(
             PrimitiveTopology::TriangleList,
@@ -396,7 +667,7 @@ mod test {
         mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, vec![[0., 1., 0., 0.]; 4]);
         mesh.insert_attribute(
             Mesh::ATTRIBUTE_JOINT_INDEX,
-            Uint16x4(vec![[1u16, 2, 3, 4]; 4]),
+            VertexAttributeValues::Uint16x4(vec![[1u16, 2, 3, 4]; 4]),
         );
         mesh.insert_indices(Indices::U32(vec![0, 2, 1]));
         mesh.set_morph_target_names(vec!["name1".into(), "name2".into()]);
@@ -421,7 +692,7 @@ mod test {
         mesh.insert_attribute(Mesh::ATTRIBUTE_JOINT_WEIGHT, vec![[0., 1., 0., 0.]; 4]);
         mesh.insert_attribute(
             Mesh::ATTRIBUTE_JOINT_INDEX,
-            Uint16x4(vec![[1u16, 2, 3, 4]; 4]),
+            VertexAttributeValues::Uint16x4(vec![[1u16, 2, 3, 4]; 4]),
         );
         mesh.insert_indices(Indices::U16(vec![0, 2, 1]));
         mesh.set_morph_target_names(vec!["name1".into(), "name2".into()]);
@@ -1,404 +1,1363 @@
+mod cache;
+mod codec;
+#[cfg(not(target_arch = "wasm32"))]
+mod http;
+#[cfg(target_arch = "wasm32")]
+mod http_wasm;
 mod image_serde;
 mod mesh_serde;
 
+#[cfg(target_arch = "wasm32")]
+use http_wasm as http;
+
 use std::{
+    any::TypeId,
+    collections::VecDeque,
+    error::Error,
     net::{IpAddr, SocketAddr},
     sync::{
-        mpsc::{channel, Receiver},
-        Arc, RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
     },
     time::Duration,
 };
 
 use crate::{
-    lib_priv::SyncTrackerRes, networking::assets::image_serde::image_to_bin, proto::SyncAssetType,
+    lib_priv::SyncTrackerRes,
+    networking::transport::{ClientTransport, ServerTransport, SyncTransport},
+    proto::{AssetHash, Message},
+    AssetFromBytes, AssetToBytes, AssetTransferFailed, SyncAsset, SyncNetworkConfig,
+};
+use bevy::{
+    asset::LoadState,
+    prelude::*,
+    utils::{HashMap, Instant},
 };
-use ascii::AsciiString;
-use bevy::{prelude::*, utils::HashMap};
-use mesh_serde::{bin_to_mesh, mesh_to_bin};
-use std::io::Read;
-use threadpool::ThreadPool;
-use tiny_http::{Header, Request, Response, Server};
+use bevy_renet::renet::ClientId;
+use mesh_serde::{bin_to_mesh, extract_morph_targets, mesh_to_bin};
 use uuid::Uuid;
 
-use self::image_serde::bin_to_image;
+pub(crate) use self::codec::AssetEncoding;
+use self::{
+    cache::DiskCache,
+    http::HttpTransfer,
+    image_serde::{bin_to_image, image_to_bin},
+};
 
-pub(crate) fn init(app: &mut App, addr: IpAddr, port: u16, max_transfer: usize) {
-    debug!(
-        "Initializing asset sync on {:?}:{}",
-        addr.clone(),
-        port.clone()
-    );
-    let http_transfer = SyncAssetTransfer::new(addr, port, max_transfer);
-    app.insert_resource(http_transfer);
-    app.add_systems(
-        Update,
-        process_mesh_assets.run_if(resource_exists::<SyncAssetTransfer>),
-    );
+const MEMORY_SCHEME: &str = "memory://";
+/// Base url of assets sent over the session connection, requested from the peer announcing them.
+const CONNECTION_SCHEME: &str = "sync:";
+/// Largest asset data carried by a single AssetChunk message.
+const ASSET_CHUNK_SIZE: usize = 16 * 1024;
+
+/// Assets served by in-process asset transfers, keyed by their base url.
+static MEMORY_SERVED: OnceLock<Mutex<HashMap<String, ServedAssets>>> = OnceLock::new();
+static NEXT_MEMORY_INSTANCE: AtomicU64 = AtomicU64::new(1);
+
+fn memory_served() -> &'static Mutex<HashMap<String, ServedAssets>> {
+    MEMORY_SERVED.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub(crate) fn init(app: &mut App) {
     app.add_systems(
         Update,
-        process_image_assets.run_if(resource_exists::<SyncAssetTransfer>),
+        report_failed_assets.run_if(resource_exists::<SyncAssetTransfer>),
     );
     app.add_systems(
         Update,
-        process_audio_assets.run_if(resource_exists::<SyncAssetTransfer>),
+        (
+            send_server_asset_messages.run_if(resource_exists::<ServerTransport>),
+            send_client_asset_messages.run_if(resource_exists::<ClientTransport>),
+        )
+            .distributive_run_if(resource_exists::<SyncAssetTransfer>),
     );
 }
 
-fn process_mesh_assets(
-    mut meshes: ResMut<Assets<Mesh>>,
-    sync: ResMut<SyncAssetTransfer>,
-    mut sync_tracker: ResMut<SyncTrackerRes>,
+fn report_failed_assets(
+    sync: Res<SyncAssetTransfer>,
+    mut events: EventWriter<AssetTransferFailed>,
 ) {
-    let Ok(mut map) = sync.meshes_to_apply.write() else {
+    let Ok(mut failures) = sync.failures.lock() else {
         return;
     };
-    for (id, mesh) in map.drain() {
-        sync_tracker.pushed_handles_from_network.insert(id);
-        let id: AssetId<Mesh> = AssetId::Uuid { uuid: id };
-        meshes.insert(id, bin_to_mesh(&mesh));
+    for failure in failures.drain(..) {
+        warn!(
+            "Could not transfer asset {} from {}: {}",
+            failure.id, failure.url, failure.reason
+        );
+        events.send(failure);
     }
 }
 
-fn process_image_assets(
-    mut images: ResMut<Assets<Image>>,
-    sync: ResMut<SyncAssetTransfer>,
+fn send_server_asset_messages(
+    mut sync: ResMut<SyncAssetTransfer>,
+    mut server: ResMut<ServerTransport>,
+) {
+    sync.send_pending(&mut **server);
+}
+
+fn send_client_asset_messages(
+    mut sync: ResMut<SyncAssetTransfer>,
+    mut client: ResMut<ClientTransport>,
+) {
+    sync.send_pending(&mut **client);
+}
+
+/// Whether the asset at url is sent over the session connection rather than over http.
+pub(crate) fn is_connection_url(url: &str) -> bool {
+    url.starts_with(CONNECTION_SCHEME)
+}
+
+/// Applies the received assets of type A, once the type is synched.
+pub(crate) fn apply_received<A: Asset>(app: &mut App) {
+    app.add_systems(
+        Update,
+        (apply_received_assets::<A>, apply_assets_loaded_by_path::<A>)
+            .distributive_run_if(resource_exists::<SyncAssetTransfer>),
+    );
+}
+
+/// Moves the assets of type A loaded from a path sent by a peer to their network uuid, and
+/// downloads the ones that could not be loaded from the local files.
+fn apply_assets_loaded_by_path<A: Asset>(
+    mut assets: ResMut<Assets<A>>,
+    mut sync: ResMut<SyncAssetTransfer>,
     mut sync_tracker: ResMut<SyncTrackerRes>,
+    asset_server: Res<AssetServer>,
 ) {
-    let Ok(mut map) = sync.images_to_apply.write() else {
+    if sync_tracker.assets_loaded_by_path.is_empty() {
         return;
-    };
-    for (id, image) in map.drain() {
-        sync_tracker.pushed_handles_from_network.insert(id);
-        let id: AssetId<Image> = AssetId::Uuid { uuid: id };
-        let Some(img) = bin_to_image(&image) else {
+    }
+    let settled: Vec<Uuid> = sync_tracker
+        .assets_loaded_by_path
+        .iter()
+        .filter(|(_, loading)| loading.handle.type_id() == TypeId::of::<A>())
+        .filter(|(_, loading)| {
+            let state = asset_server.load_state(loading.handle.id());
+            matches!(state, LoadState::Loaded | LoadState::Failed(_))
+        })
+        .map(|(id, _)| *id)
+        .collect();
+    for id in settled {
+        let Some(loading) = sync_tracker.assets_loaded_by_path.remove(&id) else {
             continue;
         };
-        images.insert(id, img);
+        let local_id = loading.handle.id().typed::<A>();
+        let asset = if loading.shared {
+            // other users of the path keep their asset, a copy is made through its bytes
+            assets.get(local_id).and_then(|asset| {
+                let bytes = asset_to_bytes(&mut sync_tracker, asset).ok()?;
+                asset_from_bytes(&sync_tracker, &bytes, usize::MAX).ok()
+            })
+        } else {
+            assets.remove(local_id)
+        };
+        match asset {
+            Some(asset) => {
+                sync_tracker.pushed_handles_from_network.insert(id);
+                assets.insert(AssetId::Uuid { uuid: id }, asset);
+            }
+            None => {
+                let fallback = loading.fallback;
+                debug!(
+                    "Could not load {} {} by path, downloading it",
+                    fallback.asset_type, id
+                );
+                sync.request(
+                    &fallback.asset_type,
+                    id,
+                    fallback.url,
+                    fallback.hash,
+                    fallback.peer,
+                );
+            }
+        }
     }
 }
 
-fn process_audio_assets(
-    mut audios: ResMut<Assets<AudioSource>>,
-    sync: ResMut<SyncAssetTransfer>,
+fn apply_received_assets<A: Asset>(
+    mut assets: ResMut<Assets<A>>,
+    sync: Res<SyncAssetTransfer>,
     mut sync_tracker: ResMut<SyncTrackerRes>,
 ) {
-    let Ok(mut map) = sync.audios_to_apply.write() else {
-        return;
-    };
-    for (id, audio) in map.drain() {
-        sync_tracker.pushed_handles_from_network.insert(id);
-        let id: AssetId<AudioSource> = AssetId::Uuid { uuid: id };
-        audios.insert(
-            id,
-            AudioSource {
-                bytes: audio.into(),
-            },
-        );
+    for (id, received) in sync.take_received(A::type_path()) {
+        match asset_from_bytes::<A>(&sync_tracker, &received.bytes, sync.max_transfer) {
+            Ok(asset) => {
+                sync_tracker.pushed_handles_from_network.insert(id);
+                let local_id = sync_tracker.local_asset_id::<A>(id);
+                assets.insert(local_id, asset);
+            }
+            Err(e) => report_failure(
+                &sync.failures,
+                id,
+                received.url,
+                format!("could not decode asset: {}", e),
+            ),
+        }
+    }
+}
+
+pub(crate) type CodecError = Box<dyn Error + Send + Sync>;
+type ToBytes<A> = dyn Fn(&A, &mut SyncTrackerRes) -> Result<Vec<u8>, CodecError> + Send + Sync;
+type FromBytes<A> = dyn Fn(&[u8], &SyncTrackerRes, usize) -> Result<A, CodecError> + Send + Sync;
+
+/// Converts the assets of a synched type to the bytes sent to peers and back, registered along
+/// with the type. The tracker gives what is set on the app, like the encoding, and received
+/// bytes decompressing to more than max_size are rejected.
+pub(crate) struct AssetCodec<A> {
+    to_bytes: Box<ToBytes<A>>,
+    from_bytes: Box<FromBytes<A>>,
+}
+
+impl<A: Asset> AssetCodec<A> {
+    /// Codec of the functions given to sync_asset_with, or of a SyncAsset.
+    pub(crate) fn new(to_bytes: AssetToBytes<A>, from_bytes: AssetFromBytes<A>) -> Self {
+        Self {
+            to_bytes: Box::new(move |asset, _| to_bytes(asset)),
+            from_bytes: Box::new(move |bytes, _, _| from_bytes(bytes)),
+        }
+    }
+
+    /// Codec of functions reading what is set on the app from the tracker.
+    pub(crate) fn with_tracker(
+        to_bytes: impl Fn(&A, &mut SyncTrackerRes) -> Result<Vec<u8>, CodecError>
+            + Send
+            + Sync
+            + 'static,
+        from_bytes: impl Fn(&[u8], &SyncTrackerRes, usize) -> Result<A, CodecError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self {
+            to_bytes: Box::new(to_bytes),
+            from_bytes: Box::new(from_bytes),
+        }
+    }
+}
+
+/// Meshes are encoded as set on SyncPlugin, with the vertex attributes registered on the app.
+pub(crate) fn mesh_codec() -> AssetCodec<Mesh> {
+    AssetCodec {
+        to_bytes: Box::new(mesh_to_network),
+        from_bytes: Box::new(mesh_from_network),
+    }
+}
+
+/// Images are compressed as set on SyncPlugin.
+pub(crate) fn image_codec() -> AssetCodec<Image> {
+    AssetCodec {
+        to_bytes: Box::new(|image, track| {
+            image_to_bin(image, track.asset_encoding.image_compression)
+                .ok_or_else(|| "image format is not supported".into())
+        }),
+        from_bytes: Box::new(|bytes, _, max_size| {
+            bin_to_image(bytes, max_size).ok_or_else(|| "invalid image data".into())
+        }),
     }
 }
 
-type MeshCache = Arc<RwLock<HashMap<Uuid, Vec<u8>>>>;
-type ImageCache = Arc<RwLock<HashMap<Uuid, Vec<u8>>>>;
-type AudioCache = Arc<RwLock<HashMap<Uuid, Vec<u8>>>>;
+pub(crate) fn audio_codec() -> AssetCodec<AudioSource> {
+    AssetCodec::new(AudioSource::to_bytes, AudioSource::from_bytes)
+}
+
+/// The morph targets image of a mesh is synched as an asset of its own, the mesh references it
+/// by its uuid on the network.
+fn mesh_to_network(mesh: &Mesh, track: &mut SyncTrackerRes) -> Result<Vec<u8>, CodecError> {
+    let mapped = extract_morph_targets(mesh).as_ref().map(|morph_targets| {
+        let uuid = track.network_uuid(morph_targets.id().untyped());
+        let mut mesh = mesh.clone();
+        mesh.set_morph_targets(Handle::Weak(AssetId::Uuid { uuid }));
+        mesh
+    });
+    let encoding = track.asset_encoding;
+    mesh_to_bin(
+        mapped.as_ref().unwrap_or(mesh),
+        &track.mesh_attributes,
+        encoding.mesh_compression,
+        encoding.quantize_meshes,
+    )
+}
+
+/// Maps the uuid of the morph targets image back to the local asset id.
+fn mesh_from_network(
+    bytes: &[u8],
+    track: &SyncTrackerRes,
+    max_size: usize,
+) -> Result<Mesh, CodecError> {
+    let mut mesh = bin_to_mesh(bytes, &track.mesh_attributes, max_size)?;
+    if let Some(AssetId::Uuid { uuid }) = extract_morph_targets(&mesh).as_ref().map(|m| m.id()) {
+        mesh.set_morph_targets(Handle::Weak(track.local_asset_id::<Image>(uuid)));
+    }
+    Ok(mesh)
+}
+
+/// Bytes of an asset to serve, through the codec registered with its type.
+fn asset_to_bytes<A: Asset>(track: &mut SyncTrackerRes, asset: &A) -> Result<Vec<u8>, CodecError> {
+    let codec = track
+        .asset_codec::<A>()
+        .ok_or("asset type is not synched")?;
+    (codec.to_bytes)(asset, track)
+}
+
+/// Asset decoded from the bytes received from a peer, through the codec registered with its
+/// type.
+fn asset_from_bytes<A: Asset>(
+    track: &SyncTrackerRes,
+    bytes: &[u8],
+    max_size: usize,
+) -> Result<A, CodecError> {
+    let codec = track
+        .asset_codec::<A>()
+        .ok_or("asset type is not synched")?;
+    (codec.from_bytes)(bytes, track, max_size)
+}
+
+impl SyncAsset for AudioSource {
+    fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        Ok(self.bytes.to_vec())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(AudioSource {
+            bytes: bytes.into(),
+        })
+    }
+}
+
+/// Bytes of the served assets, keyed by their type path and id.
+type ServedAssets = Arc<RwLock<HashMap<(String, Uuid), Vec<u8>>>>;
+/// Assets received and waiting to be applied, keyed by their type path and id.
+type ReceivedAssets = Arc<RwLock<HashMap<(String, Uuid), ReceivedAsset>>>;
+
+struct ReceivedAsset {
+    url: String,
+    bytes: Vec<u8>,
+}
+
+/// Asset being received in chunks over the session connection.
+struct ChunkedDownload {
+    peer: ClientId,
+    url: String,
+    hash: AssetHash,
+    bytes: Vec<u8>,
+    received: usize,
+    /// Newer content announced while this one was being received, requested once it ends.
+    newer: Option<(String, AssetHash)>,
+    /// Last time anything was received from peer, the download fails once it is too old.
+    last_received: Instant,
+}
+
+type FailedAssets = Arc<Mutex<Vec<AssetTransferFailed>>>;
+
+pub(crate) fn content_hash(bytes: &[u8]) -> AssetHash {
+    blake3::hash(bytes).into()
+}
 
 #[derive(Resource)]
 pub(crate) struct SyncAssetTransfer {
     base_url: String,
-    server_pool: ThreadPool,
-    download_pool: ThreadPool,
-    meshes: MeshCache,
-    meshes_to_apply: MeshCache,
-    images: ImageCache,
-    images_to_apply: ImageCache,
-    audios: AudioCache,
-    audios_to_apply: AudioCache,
+    /// Port the asset server is bound to, None when served in memory.
+    web_port: Option<u16>,
+    http: HttpTransfer,
+    /// Set when the transfer is dropped, so that downloads stop retrying.
+    closing: Arc<AtomicBool>,
+    /// Assets that could not be fetched, reported as AssetTransferFailed events.
+    failures: FailedAssets,
+    disk_cache: Option<DiskCache>,
+    served: ServedAssets,
+    to_apply: ReceivedAssets,
     max_transfer: usize,
+    /// Asset requests to send over the session connection.
+    outgoing_requests: Vec<(ClientId, Vec<u8>)>,
+    /// Asset chunks waiting for room in the transport, in sending order.
+    outgoing_chunks: VecDeque<(ClientId, Vec<u8>)>,
+    /// Chunk bytes handed to the transport per update, at least one chunk always goes.
+    chunk_bytes_per_update: usize,
+    downloads: HashMap<(String, Uuid), ChunkedDownload>,
+    /// Longest time a download over the connection may go without receiving anything.
+    download_timeout: Duration,
+    /// Requests for assets not received yet, answered once they are or forgotten after the
+    /// download timeout, as the requesting peer then gave up on them.
+    waiting: Vec<(ClientId, String, Uuid, Instant)>,
 }
 
 impl SyncAssetTransfer {
-    pub(crate) fn new(addr: IpAddr, port: u16, max_transfer: usize) -> Self {
+    /// Starts the asset server, a port of 0 binds to any free one.
+    pub(crate) fn new(
+        addr: IpAddr,
+        port: u16,
+        max_transfer: usize,
+        config: &SyncNetworkConfig,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let served = ServedAssets::default();
+        let http = HttpTransfer::start(
+            SocketAddr::new(addr, port),
+            served.clone(),
+            max_transfer,
+            config,
+        )?;
+        let port = http.port().unwrap_or(port);
         let base_url = if addr.is_ipv6() {
             format!("http://[{}]:{}", addr, port)
         } else {
             format!("http://{}:{}", addr, port)
         };
         debug!("Starting asset server on {}", base_url);
-        let server = Server::http(SocketAddr::new(addr, port)).unwrap();
 
-        let server_pool = ThreadPool::new(128);
-        let download_pool = ThreadPool::new(127);
+        let mut result = Self::with_http(base_url, http, max_transfer);
+        result.served = served;
+        result.disk_cache = disk_cache(config);
+        result.web_port = Some(port);
+        Ok(result)
+    }
 
-        let meshes = Arc::new(RwLock::new(HashMap::<Uuid, Vec<u8>>::new()));
-        let meshes_to_apply = Arc::new(RwLock::new(HashMap::<Uuid, Vec<u8>>::new()));
-        let images = Arc::new(RwLock::new(HashMap::<Uuid, Vec<u8>>::new()));
-        let images_to_apply = Arc::new(RwLock::new(HashMap::<Uuid, Vec<u8>>::new()));
-        let audios = Arc::new(RwLock::new(HashMap::<Uuid, Vec<u8>>::new()));
-        let audios_to_apply = Arc::new(RwLock::new(HashMap::<Uuid, Vec<u8>>::new()));
+    pub(crate) fn web_port(&self) -> Option<u16> {
+        self.web_port
+    }
 
-        let result = Self {
+    /// Serves assets to peers in the same process, without opening any port.
+    pub(crate) fn in_memory(name: &str) -> Self {
+        let instance = NEXT_MEMORY_INSTANCE.fetch_add(1, Ordering::SeqCst);
+        let base_url = format!("{}{}/{}", MEMORY_SCHEME, name, instance);
+        let result = Self::with_http(base_url.clone(), HttpTransfer::downloads_only(), usize::MAX);
+        memory_served()
+            .lock()
+            .unwrap()
+            .insert(base_url, result.served.clone());
+        result
+    }
+
+    /// Sends assets in chunks over the session connection, without opening any port.
+    pub(crate) fn over_connection(max_transfer: usize, config: &SyncNetworkConfig) -> Self {
+        let mut result = Self::with_http(
+            CONNECTION_SCHEME.to_string(),
+            HttpTransfer::downloads_only(),
+            max_transfer,
+        );
+        result.chunk_bytes_per_update = config.available_bytes_per_tick as usize;
+        result.download_timeout = config.asset_download_timeout;
+        result.disk_cache = disk_cache(config);
+        result
+    }
+
+    fn with_http(base_url: String, http: HttpTransfer, max_transfer: usize) -> Self {
+        Self {
             base_url,
-            server_pool,
-            download_pool,
-            meshes,
-            meshes_to_apply,
+            web_port: None,
+            http,
+            closing: Arc::new(AtomicBool::new(false)),
+            failures: FailedAssets::default(),
+            disk_cache: None,
+            served: ServedAssets::default(),
+            to_apply: ReceivedAssets::default(),
             max_transfer,
-            images,
-            images_to_apply,
-            audios,
-            audios_to_apply,
-        };
+            outgoing_requests: Vec::new(),
+            outgoing_chunks: VecDeque::new(),
+            chunk_bytes_per_update: usize::MAX,
+            downloads: HashMap::new(),
+            download_timeout: Duration::MAX,
+            waiting: Vec::new(),
+        }
+    }
 
-        let (server_tx, server_rx) = channel::<Request>();
-        result.server_pool.execute(move || {
-            for request in server.incoming_requests() {
-                debug!("Queuing response to {}", request.url());
-                server_tx.send(request).unwrap_or(());
-            }
-        });
-        let meshes = result.meshes.clone();
-        let images = result.images.clone();
-        let audios = result.audios.clone();
-        result
-            .server_pool
-            .execute(move || Self::respond(server_rx, meshes, images, audios, max_transfer));
-        result
+    /// Removes the received assets of asset_type, to be applied.
+    fn take_received(&self, asset_type: &str) -> Vec<(Uuid, ReceivedAsset)> {
+        let mut to_apply = write(&self.to_apply);
+        let ids: Vec<Uuid> = to_apply
+            .keys()
+            .filter(|(received_type, _)| received_type == asset_type)
+            .map(|(_, id)| *id)
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| {
+                let received = to_apply.remove(&(asset_type.to_string(), id))?;
+                Some((id, received))
+            })
+            .collect()
     }
 
-    pub(crate) fn request(&self, asset_type: SyncAssetType, id: Uuid, url: String) {
-        if let Ok(meshes) = self.meshes.read() {
-            if meshes.contains_key(&id) {
+    fn served_bytes(&self, asset_type: &str, id: &Uuid) -> Option<Vec<u8>> {
+        served_bytes(&self.served, asset_type, id)
+    }
+
+    fn asset_url(&self, asset_type: &str, id: &Uuid) -> String {
+        format!("{}/{}/{}", self.base_url, encode_asset_type(asset_type), id)
+    }
+
+    /// Fetches an asset announced by peer at url, applying it only if its content matches hash.
+    pub(crate) fn request(
+        &mut self,
+        asset_type: &str,
+        id: Uuid,
+        url: String,
+        hash: AssetHash,
+        peer: ClientId,
+    ) {
+        let key = (asset_type.to_string(), id);
+        if read(&self.served)
+            .get(&key)
+            .is_some_and(|bytes| content_hash(bytes) == hash)
+        {
+            return;
+        }
+        // what was served before is stale, peers asking for it wait for the new content
+        write(&self.served).remove(&key);
+        if let Some(bytes) = self.disk_cache.as_ref().and_then(|cache| cache.get(&hash)) {
+            debug!("Found {} {} in the disk cache", asset_type, id);
+            if url.starts_with(CONNECTION_SCHEME) {
+                // same as if received, peers relayed through this one may ask for it
+                write(&self.served).insert(key.clone(), bytes.clone());
+                self.answer_waiting(asset_type, &id);
+            }
+            insert_to_apply(&self.to_apply, key, url, bytes);
+            return;
+        }
+        if url.starts_with(CONNECTION_SCHEME) {
+            if let Some(download) = self.downloads.get_mut(&key) {
+                // chunks can't be told apart, so the older content is received first
+                if download.hash != hash {
+                    download.newer = Some((url, hash));
+                }
                 return;
             }
+            debug!("Requesting {}:{} from {:?}", asset_type, id, peer);
+            self.downloads.insert(
+                key,
+                ChunkedDownload {
+                    peer,
+                    url,
+                    hash,
+                    bytes: Vec::new(),
+                    received: 0,
+                    newer: None,
+                    last_received: Instant::now(),
+                },
+            );
+            let request = Message::AssetRequest {
+                asset_type: asset_type.to_string(),
+                id,
+            };
+            self.outgoing_requests
+                .push((peer, bincode::serialize(&request).unwrap()));
+            return;
         }
-        let meshes_to_apply = self.meshes_to_apply.clone();
-        let images_to_apply = self.images_to_apply.clone();
-        let audios_to_apply = self.audios_to_apply.clone();
-        debug!("Queuing request for {:?}:{} at {}", asset_type, id, url);
-        let max_transfer = self.max_transfer;
-        self.download_pool.execute(move || {
-            if let Ok(response) = ureq::get(url.as_str()).call() {
-                let len = response
-                    .header("Content-Length")
-                    .and_then(|s| s.parse::<usize>().ok())
-                    .unwrap_or(max_transfer);
-                let mut bytes: Vec<u8> = Vec::with_capacity(len);
-                if response
-                    .into_reader()
-                    .take(len as u64)
-                    .read_to_end(&mut bytes)
-                    .is_ok()
-                {
-                    match asset_type {
-                        SyncAssetType::Mesh => {
-                            let mut lock = meshes_to_apply.write();
-                            loop {
-                                match lock {
-                                    Ok(mut map) => {
-                                        debug!("Received mesh {} with size {}", id, len);
-                                        map.insert(id, bytes);
-                                        break;
-                                    }
-                                    Err(_) => lock = meshes_to_apply.write(),
-                                }
-                                std::thread::sleep(Duration::from_millis(1));
-                            }
-                        }
-                        SyncAssetType::Image => {
-                            let mut lock = images_to_apply.write();
-                            loop {
-                                match lock {
-                                    Ok(mut map) => {
-                                        debug!("Received image {} with size {}", id, len);
-                                        map.insert(id, bytes);
-                                        break;
-                                    }
-                                    Err(_) => lock = images_to_apply.write(),
-                                }
-                                std::thread::sleep(Duration::from_millis(1));
-                            }
-                        }
-                        SyncAssetType::Audio => {
-                            let mut lock = audios_to_apply.write();
-                            loop {
-                                match lock {
-                                    Ok(mut map) => {
-                                        debug!("Received audio {} with size {}", id, len);
-                                        map.insert(id, bytes);
-                                        break;
-                                    }
-                                    Err(_) => lock = audios_to_apply.write(),
-                                }
-                                std::thread::sleep(Duration::from_millis(1));
-                            }
-                        }
-                    }
+        let to_apply = self.to_apply.clone();
+        if url.starts_with(MEMORY_SCHEME) {
+            // in process transfers are applied right away to keep delivery deterministic
+            let result = fetch_in_memory(&url)
+                .ok_or_else(|| "asset not served".to_string())
+                .and_then(|bytes| verified(bytes, &hash));
+            match result {
+                Ok(bytes) => {
+                    debug!("Received {} {} with size {}", asset_type, id, bytes.len());
+                    insert_to_apply(&to_apply, key, url, bytes);
                 }
+                Err(reason) => report_failure(&self.failures, id, url, reason),
+            }
+            return;
+        }
+        http::request(self, key, url, hash);
+    }
+
+    /// Serves asset to the peers, returning its url and the hash of its content.
+    /// None when the asset cannot be converted to bytes.
+    pub(crate) fn serve<A: Asset>(
+        &mut self,
+        track: &mut SyncTrackerRes,
+        id: &Uuid,
+        asset: &A,
+    ) -> Option<(String, AssetHash)> {
+        let asset_type = A::type_path();
+        let bytes = match asset_to_bytes(track, asset) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                debug!("Could not serve {} {}: {}", asset_type, id, e);
+                return None;
             }
+        };
+        debug!("Serving {} {} with size {}", asset_type, id, bytes.len());
+        let hash = content_hash(&bytes);
+        // a modified asset replaces whatever was served for it before
+        write(&self.served).insert((asset_type.to_string(), *id), bytes);
+        self.answer_waiting(asset_type, id);
+        Some((self.asset_url(asset_type, id), hash))
+    }
+
+    /// Stops serving a removed asset and drops whatever was pending for it.
+    pub(crate) fn forget(&mut self, asset_type: &str, id: &Uuid) {
+        let key = (asset_type.to_string(), *id);
+        write(&self.served).remove(&key);
+        write(&self.to_apply).remove(&key);
+        self.downloads.remove(&key);
+        self.waiting.retain(|(_, waiting_type, waiting_id, _)| {
+            waiting_type != asset_type || waiting_id != id
         });
     }
 
-    pub(crate) fn serve_mesh(&mut self, id: &Uuid, mesh: &Mesh) -> String {
-        let mut lock = self.meshes.write();
-        loop {
-            match lock {
-                Ok(mut map) => {
-                    let mesh = map.entry(*id).or_insert_with(|| mesh_to_bin(mesh));
-                    debug!("Serving mesh {} with size {}", id, mesh.len());
-                    break;
-                }
-                Err(_) => lock = self.meshes.write(),
+    /// Answers an AssetRequest of peer, right away if the asset is known or once it is.
+    pub(crate) fn respond_over_connection(&mut self, peer: ClientId, asset_type: &str, id: Uuid) {
+        match self.served_bytes(asset_type, &id) {
+            Some(bytes) => self.queue_chunks(peer, asset_type, id, &bytes),
+            None => {
+                debug!(
+                    "{}:{} requested by {:?} is not known yet",
+                    asset_type, id, peer
+                );
+                self.waiting
+                    .push((peer, asset_type.to_string(), id, Instant::now()));
             }
-            std::thread::sleep(Duration::from_millis(1));
         }
-        format!("{}/mesh/{}", self.base_url, &id.to_string())
     }
 
-    pub(crate) fn serve_image(&mut self, id: &Uuid, image: &Image) -> String {
-        let mut lock = self.images.write();
-        loop {
-            match lock {
-                Ok(mut map) => {
-                    if let Some(bin) = image_to_bin(image) {
-                        let image = map.entry(*id).or_insert_with(|| bin);
-                        debug!("Serving image {} with size {}", id, image.len());
-                    }
-                    break;
-                }
-                Err(_) => lock = self.meshes.write(),
+    /// Adds a received AssetChunk to its download, applying the asset once complete.
+    pub(crate) fn receive_chunk(
+        &mut self,
+        asset_type: &str,
+        id: Uuid,
+        offset: u64,
+        total: u64,
+        bytes: Vec<u8>,
+    ) {
+        let key = (asset_type.to_string(), id);
+        let Some(download) = self.downloads.get_mut(&key) else {
+            debug!("Ignoring chunk of {}:{} not requested", asset_type, id);
+            return;
+        };
+        let (offset, total) = (offset as usize, total as usize);
+        if total > self.max_transfer
+            || offset.saturating_add(bytes.len()) > total
+            || (download.received > 0 && download.bytes.len() != total)
+        {
+            if let Some(download) = self.downloads.remove(&key) {
+                report_failure(
+                    &self.failures,
+                    id,
+                    download.url,
+                    "chunks do not fit the transfer limits".to_string(),
+                );
             }
-            std::thread::sleep(Duration::from_millis(1));
+            return;
+        }
+        download.bytes.resize(total, 0);
+        download.bytes[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        download.received += bytes.len();
+        let (peer, complete) = (download.peer, download.received >= total);
+        // chunks are sent one asset after the other, the ones queued after this are not stalled
+        let now = Instant::now();
+        for download in self.downloads.values_mut() {
+            if download.peer == peer {
+                download.last_received = now;
+            }
+        }
+        if !complete {
+            return;
+        }
+        let Some(mut download) = self.downloads.remove(&key) else {
+            return;
+        };
+        let newer = download.newer.take();
+        let peer = download.peer;
+        self.complete_download(asset_type, id, download, newer.is_none());
+        if let Some((url, hash)) = newer {
+            self.request(asset_type, id, url, hash, peer);
         }
-        format!("{}/image/{}", self.base_url, &id.to_string())
     }
 
-    pub(crate) fn serve_audio(&mut self, id: &Uuid, audio: &AudioSource) -> String {
-        let mut lock = self.audios.write();
-        loop {
-            match lock {
-                Ok(mut map) => {
-                    let bin = Vec::<u8>::from(audio.as_ref());
-                    let audio = map.entry(*id).or_insert_with(|| bin);
-                    debug!("Serving audio {} with size {}", id, audio.len());
-                    break;
-                }
-                Err(_) => lock = self.meshes.write(),
+    /// Applies a received asset, serving it to the other peers unless newer content follows.
+    fn complete_download(
+        &mut self,
+        asset_type: &str,
+        id: Uuid,
+        download: ChunkedDownload,
+        serve: bool,
+    ) {
+        let key = (asset_type.to_string(), id);
+        let download = match verified(download.bytes, &download.hash) {
+            Ok(bytes) => ChunkedDownload { bytes, ..download },
+            Err(reason) => {
+                report_failure(&self.failures, id, download.url, reason);
+                return;
             }
-            std::thread::sleep(Duration::from_millis(1));
+        };
+        if let Some(cache) = &self.disk_cache {
+            cache.put(&download.hash, &download.bytes);
+        }
+        debug!(
+            "Received {} {} with size {}",
+            asset_type,
+            id,
+            download.bytes.len()
+        );
+        if serve {
+            // kept to answer the other peers, hosts relay the assets of their clients
+            write(&self.served).insert(key.clone(), download.bytes.clone());
+        }
+        insert_to_apply(&self.to_apply, key, download.url, download.bytes);
+        if serve {
+            self.answer_waiting(asset_type, &id);
         }
-        format!("{}/audio/{}", self.base_url, &id.to_string())
     }
 
-    fn respond(
-        rx: Receiver<Request>,
-        meshes: MeshCache,
-        images: ImageCache,
-        audios: AudioCache,
-        max_size: usize,
-    ) {
-        for request in rx.iter() {
-            let url = request.url();
-            let (asset_type, id) = if url.contains("/image/") {
-                let Some(id) = url.strip_prefix("/image/") else {
-                    continue;
-                };
-                (SyncAssetType::Image, id)
-            } else if url.contains("/mesh/") {
-                let Some(id) = url.strip_prefix("/mesh/") else {
-                    continue;
-                };
-                (SyncAssetType::Mesh, id)
-            } else if url.contains("/audio/") {
-                let Some(id) = url.strip_prefix("/audio/") else {
-                    continue;
-                };
-                (SyncAssetType::Audio, id)
-            } else {
-                continue;
-            };
-            let Ok(id) = Uuid::parse_str(id) else {
-                continue;
+    fn answer_waiting(&mut self, asset_type: &str, id: &Uuid) {
+        if self.waiting.is_empty() {
+            return;
+        }
+        let (answered, waiting) = std::mem::take(&mut self.waiting).into_iter().partition(
+            |(_, waiting_type, waiting_id, _)| waiting_type == asset_type && waiting_id == id,
+        );
+        self.waiting = waiting;
+        let Some(bytes) = self.served_bytes(asset_type, id) else {
+            return;
+        };
+        for (peer, _, _, _) in answered {
+            self.queue_chunks(peer, asset_type, *id, &bytes);
+        }
+    }
+
+    fn queue_chunks(&mut self, peer: ClientId, asset_type: &str, id: Uuid, bytes: &[u8]) {
+        debug!(
+            "Sending {} {} with size {} to {:?}",
+            asset_type,
+            id,
+            bytes.len(),
+            peer
+        );
+        let mut offset = 0;
+        loop {
+            let end = (offset + ASSET_CHUNK_SIZE).min(bytes.len());
+            let chunk = Message::AssetChunk {
+                asset_type: asset_type.to_string(),
+                id,
+                offset: offset as u64,
+                total: bytes.len() as u64,
+                bytes: bytes[offset..end].to_vec(),
             };
+            self.outgoing_chunks
+                .push_back((peer, bincode::serialize(&chunk).unwrap()));
+            offset = end;
+            if offset >= bytes.len() {
+                break;
+            }
+        }
+    }
 
-            match asset_type {
-                SyncAssetType::Mesh => {
-                    let Ok(meshesmap) = meshes.read() else {
-                        request
-                            .respond(Response::from_string("").with_status_code(449))
-                            .unwrap_or(());
-                        continue;
-                    };
-                    let Some(mesh) = meshesmap.get(&id) else {
-                        request
-                            .respond(Response::from_string("").with_status_code(404))
-                            .unwrap_or(());
-                        continue;
-                    };
-                    debug!("Responding to {} with size {}", url, mesh.len());
-                    request
-                        .respond(
-                            Response::from_data(mesh.clone())
-                                .with_header(Header {
-                                    field: "Content-Length".parse().unwrap(),
-                                    value: AsciiString::from_ascii(mesh.len().to_string()).unwrap(),
-                                })
-                                .with_chunked_threshold(max_size),
-                        )
-                        .unwrap_or(());
-                }
-                SyncAssetType::Image => {
-                    let Ok(imagesmap) = images.read() else {
-                        request
-                            .respond(Response::from_string("").with_status_code(449))
-                            .unwrap_or(());
-                        continue;
-                    };
-                    let Some(image) = imagesmap.get(&id) else {
-                        request
-                            .respond(Response::from_string("").with_status_code(404))
-                            .unwrap_or(());
-                        continue;
-                    };
-                    debug!("Responding to {} with size {}", url, image.len());
-                    request
-                        .respond(
-                            Response::from_data(image.clone())
-                                .with_header(Header {
-                                    field: "Content-Length".parse().unwrap(),
-                                    value: AsciiString::from_ascii(image.len().to_string())
-                                        .unwrap(),
-                                })
-                                .with_chunked_threshold(max_size),
-                        )
-                        .unwrap_or(());
-                }
-                SyncAssetType::Audio => {
-                    let Ok(audiosmap) = audios.read() else {
-                        request
-                            .respond(Response::from_string("").with_status_code(449))
-                            .unwrap_or(());
-                        continue;
-                    };
-                    let Some(audio) = audiosmap.get(&id) else {
-                        request
-                            .respond(Response::from_string("").with_status_code(404))
-                            .unwrap_or(());
-                        continue;
-                    };
-                    debug!("Responding to {} with size {}", url, audio.len());
-                    request
-                        .respond(
-                            Response::from_data(audio.clone())
-                                .with_header(Header {
-                                    field: "Content-Length".parse().unwrap(),
-                                    value: AsciiString::from_ascii(audio.len().to_string())
-                                        .unwrap(),
-                                })
-                                .with_chunked_threshold(max_size),
-                        )
-                        .unwrap_or(());
-                }
+    /// Hands the queued requests and as many chunks as the transport accepts over to it.
+    fn send_pending(&mut self, transport: &mut dyn SyncTransport) {
+        if self.outgoing_requests.is_empty()
+            && self.outgoing_chunks.is_empty()
+            && self.downloads.is_empty()
+            && self.waiting.is_empty()
+        {
+            return;
+        }
+        // whatever is exchanged with peers that left is dropped
+        let peers = transport.peers();
+        self.outgoing_chunks
+            .retain(|(peer, _)| peers.contains(peer));
+        self.waiting.retain(|(peer, _, _, _)| peers.contains(peer));
+        let failures = &self.failures;
+        self.downloads.retain(|(_, id), download| {
+            let connected = peers.contains(&download.peer);
+            if !connected {
+                report_failure(
+                    failures,
+                    *id,
+                    download.url.clone(),
+                    "peer disconnected".to_string(),
+                );
+            }
+            connected
+        });
+        self.drop_stalled();
+        for (peer, request) in self.outgoing_requests.drain(..) {
+            if peers.contains(&peer) {
+                transport.send(peer, request);
+            }
+        }
+        let mut sent = 0;
+        while let Some((peer, chunk)) = self.outgoing_chunks.front() {
+            if (sent > 0 && sent + chunk.len() > self.chunk_bytes_per_update)
+                || !transport.can_send_asset(*peer, chunk.len())
+            {
+                break;
+            }
+            sent += chunk.len();
+            if let Some((peer, chunk)) = self.outgoing_chunks.pop_front() {
+                transport.send_asset(peer, chunk);
+            }
+        }
+    }
+
+    /// Fails the downloads of peers that sent nothing for too long, and forgets the requests
+    /// waiting as long, their peer having given up on them as well.
+    fn drop_stalled(&mut self) {
+        let timeout = self.download_timeout;
+        self.waiting
+            .retain(|(_, _, _, since)| since.elapsed() <= timeout);
+        let failures = &self.failures;
+        self.downloads.retain(|(_, id), download| {
+            let stalled = download.last_received.elapsed() > timeout;
+            if stalled {
+                report_failure(
+                    failures,
+                    *id,
+                    download.url.clone(),
+                    format!("nothing received for {:?}", timeout),
+                );
+            }
+            !stalled
+        });
+    }
+}
+
+impl Drop for SyncAssetTransfer {
+    fn drop(&mut self) {
+        // downloads not started yet see closing and give up
+        self.closing.store(true, Ordering::SeqCst);
+        self.http.close();
+        if self.base_url.starts_with(MEMORY_SCHEME) {
+            if let Ok(mut served) = memory_served().lock() {
+                served.remove(&self.base_url);
             }
         }
     }
 }
+
+fn disk_cache(config: &SyncNetworkConfig) -> Option<DiskCache> {
+    let dir = config.asset_cache_dir.clone()?;
+    match DiskCache::new(dir, config.asset_cache_max_bytes) {
+        Ok(cache) => Some(cache),
+        Err(e) => {
+            warn!(
+                "Could not use the asset cache, assets are always downloaded: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+fn verified(bytes: Vec<u8>, hash: &AssetHash) -> Result<Vec<u8>, String> {
+    if content_hash(&bytes) == *hash {
+        Ok(bytes)
+    } else {
+        Err("content does not match its hash".to_string())
+    }
+}
+
+fn report_failure(failures: &FailedAssets, id: Uuid, url: String, reason: String) {
+    if let Ok(mut failures) = failures.lock() {
+        failures.push(AssetTransferFailed { id, url, reason });
+    }
+}
+
+fn read<V>(map: &RwLock<V>) -> RwLockReadGuard<'_, V> {
+    map.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<V>(map: &RwLock<V>) -> RwLockWriteGuard<'_, V> {
+    map.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn insert_to_apply(to_apply: &ReceivedAssets, key: (String, Uuid), url: String, bytes: Vec<u8>) {
+    write(to_apply).insert(key, ReceivedAsset { url, bytes });
+}
+
+fn served_bytes(served: &ServedAssets, asset_type: &str, id: &Uuid) -> Option<Vec<u8>> {
+    read(served).get(&(asset_type.to_string(), *id)).cloned()
+}
+
+fn fetch_in_memory(url: &str) -> Option<Vec<u8>> {
+    let (base_url, asset_type, id) = split_asset_url(url)?;
+    let served = memory_served().lock().ok()?.get(base_url).cloned()?;
+    served_bytes(&served, &asset_type, &id)
+}
+
+/// Type paths hold characters that are not allowed in urls, such as spaces and brackets.
+fn encode_asset_type(asset_type: &str) -> String {
+    asset_type
+        .bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_' | b'-' | b'.' | b':' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn decode_asset_type(encoded: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut rest = encoded.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Splits an asset url into its base url, asset type and asset id.
+fn split_asset_url(url: &str) -> Option<(&str, String, Uuid)> {
+    let (rest, id) = url.rsplit_once('/')?;
+    let (base_url, asset_type) = rest.rsplit_once('/')?;
+    Some((
+        base_url,
+        decode_asset_type(asset_type)?,
+        Uuid::parse_str(id).ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Write,
+        net::{Ipv4Addr, TcpListener, TcpStream},
+        time::Instant,
+    };
+
+    use bevy::render::{
+        mesh::{MeshVertexAttribute, PrimitiveTopology},
+        render_asset::RenderAssetUsages,
+        render_resource::VertexFormat,
+    };
+
+    use super::{
+        http::{download, fetch, RetryPolicy},
+        *,
+    };
+    use crate::AssetCompression;
+
+    fn start(config: &SyncNetworkConfig) -> SyncAssetTransfer {
+        SyncAssetTransfer::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0, 1_000_000, config).unwrap()
+    }
+
+    fn serve_bytes(transfer: &SyncAssetTransfer, id: Uuid, bytes: Vec<u8>) -> String {
+        let asset_type = AudioSource::type_path();
+        write(&transfer.served).insert((asset_type.to_string(), id), bytes);
+        transfer.asset_url(asset_type, &id)
+    }
+
+    fn received(transfer: &SyncAssetTransfer, id: Uuid) -> Option<Vec<u8>> {
+        let key = (AudioSource::type_path().to_string(), id);
+        read(&transfer.to_apply)
+            .get(&key)
+            .map(|received| received.bytes.clone())
+    }
+
+    #[test]
+    fn dropping_transfer_releases_its_port() {
+        let config = SyncNetworkConfig {
+            asset_server_threads: 1,
+            asset_download_threads: 1,
+            ..Default::default()
+        };
+        for _ in 0..20 {
+            let transfer = start(&config);
+            let port = transfer.web_port().unwrap();
+            drop(transfer);
+            TcpListener::bind((Ipv4Addr::LOCALHOST, port)).unwrap();
+        }
+    }
+
+    #[test]
+    fn dropping_does_not_wait_for_transfers() {
+        let config = SyncNetworkConfig {
+            asset_download_timeout: Duration::from_secs(30),
+            ..Default::default()
+        };
+        let mut transfer =
+            SyncAssetTransfer::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0, usize::MAX, &config)
+                .unwrap();
+        // a peer asking for more than the socket buffers hold and never reading it
+        let id = Uuid::new_v4();
+        let url = serve_bytes(&transfer, id, vec![0; 64 * 1024 * 1024]);
+        let mut reader =
+            TcpStream::connect((Ipv4Addr::LOCALHOST, transfer.web_port().unwrap())).unwrap();
+        let path = url.split_once(&transfer.base_url).unwrap().1;
+        write!(reader, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        // accepts connections but never answers, downloads only end with their timeout
+        let stalled = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let id = Uuid::new_v4();
+        let url = format!("http://{}/audio/{}", stalled.local_addr().unwrap(), id);
+        transfer.request(
+            AudioSource::type_path(),
+            id,
+            url,
+            [0; 32],
+            ClientId::from_raw(1),
+        );
+        std::thread::sleep(Duration::from_millis(50));
+
+        let dropping = Instant::now();
+        drop(transfer);
+        assert!(dropping.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn serves_assets_until_dropped() {
+        let transfer = start(&SyncNetworkConfig::default());
+        let id = Uuid::new_v4();
+        let url = serve_bytes(&transfer, id, vec![1, 2, 3]);
+
+        let mut bytes = vec![];
+        ureq::get(&url)
+            .call()
+            .unwrap()
+            .into_reader()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes, vec![1, 2, 3]);
+
+        drop(transfer);
+        assert!(ureq::get(&url).call().is_err());
+    }
+
+    #[test]
+    fn stops_serving_forgotten_assets() {
+        let mut transfer = start(&SyncNetworkConfig::default());
+        let id = Uuid::new_v4();
+        let url = serve_bytes(&transfer, id, vec![1, 2, 3]);
+        assert!(ureq::get(&url).call().is_ok());
+
+        transfer.forget(AudioSource::type_path(), &id);
+        assert!(matches!(
+            ureq::get(&url).call(),
+            Err(ureq::Error::Status(404, _))
+        ));
+    }
+
+    #[test]
+    fn serves_byte_ranges() {
+        let transfer = start(&SyncNetworkConfig::default());
+        let id = Uuid::new_v4();
+        let bytes: Vec<u8> = (0..10).collect();
+        let url = serve_bytes(&transfer, id, bytes.clone());
+
+        let response = ureq::get(&url).set("Range", "bytes=4-").call().unwrap();
+        assert_eq!(response.status(), 206);
+        assert_eq!(response.header("Content-Range"), Some("bytes 4-9/10"));
+        let mut body = vec![];
+        response.into_reader().read_to_end(&mut body).unwrap();
+        assert_eq!(body, bytes[4..]);
+
+        let response = ureq::get(&url).set("Range", "bytes=20-").call();
+        assert!(matches!(response, Err(ureq::Error::Status(416, _))));
+    }
+
+    #[test]
+    fn resumes_downloads_after_the_bytes_received() {
+        let transfer = start(&SyncNetworkConfig::default());
+        let id = Uuid::new_v4();
+        let bytes: Vec<u8> = (0..10).collect();
+        let url = serve_bytes(&transfer, id, bytes.clone());
+
+        let mut received = bytes[..6].to_vec();
+        fetch(&transfer.http.agent, &url, &mut received, 1_000).unwrap();
+        assert_eq!(received, bytes);
+    }
+
+    #[test]
+    fn downloads_must_match_their_hash() {
+        let transfer = start(&SyncNetworkConfig::default());
+        let id = Uuid::new_v4();
+        let bytes: Vec<u8> = (0..10).collect();
+        let url = serve_bytes(&transfer, id, bytes.clone());
+        let retry = RetryPolicy {
+            retries: 1,
+            backoff: Duration::from_millis(1),
+        };
+        let closing = AtomicBool::new(false);
+
+        let downloaded = download(
+            &transfer.http.agent,
+            &url,
+            &content_hash(&bytes),
+            1_000,
+            retry,
+            &closing,
+        );
+        assert_eq!(downloaded, Ok(bytes));
+        let downloaded = download(&transfer.http.agent, &url, &[0; 32], 1_000, retry, &closing);
+        assert!(downloaded.is_err());
+    }
+
+    #[test]
+    fn applies_cached_assets_without_downloading_them() {
+        let dir = std::env::temp_dir().join(format!("bevy_sync_cache_{}", Uuid::new_v4()));
+        let mut transfer = start(&SyncNetworkConfig {
+            asset_cache_dir: Some(dir.clone()),
+            ..Default::default()
+        });
+        let id = Uuid::new_v4();
+        let bytes = vec![1, 2, 3];
+        let hash = content_hash(&bytes);
+        transfer.disk_cache.as_ref().unwrap().put(&hash, &bytes);
+
+        // nothing listens there, the asset can only come from the cache
+        let url = format!("http://127.0.0.1:1/audio/{}", id);
+        transfer.request(
+            AudioSource::type_path(),
+            id,
+            url,
+            hash,
+            ClientId::from_raw(1),
+        );
+        assert_eq!(received(&transfer, id), Some(bytes));
+        drop(transfer);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_assets_failing_their_hash() {
+        let mut sender = SyncAssetTransfer::in_memory("hash_failure");
+        let mut transfer = SyncAssetTransfer::in_memory("hash_failure");
+        let id = Uuid::new_v4();
+        let (url, _) = sender
+            .serve(
+                &mut audio_tracker(),
+                &id,
+                &AudioSource {
+                    bytes: vec![1, 2, 3].into(),
+                },
+            )
+            .unwrap();
+
+        transfer.request(
+            AudioSource::type_path(),
+            id,
+            url,
+            [0; 32],
+            ClientId::from_raw(1),
+        );
+        assert_eq!(received(&transfer, id), None);
+        let failures = transfer.failures.lock().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].id, id);
+    }
+
+    #[test]
+    fn reassembles_chunks_received_out_of_order() {
+        let config = SyncNetworkConfig::default();
+        let mut sender = SyncAssetTransfer::over_connection(1_000_000, &config);
+        let mut receiver = SyncAssetTransfer::over_connection(1_000_000, &config);
+        let id = Uuid::new_v4();
+        let bytes: Vec<u8> = (0..3 * ASSET_CHUNK_SIZE + 7).map(|i| i as u8).collect();
+        let (url, hash) = sender
+            .serve(
+                &mut audio_tracker(),
+                &id,
+                &AudioSource {
+                    bytes: bytes.clone().into(),
+                },
+            )
+            .unwrap();
+
+        let peer = ClientId::from_raw(7);
+        receiver.request(AudioSource::type_path(), id, url, hash, peer);
+        let (to, request) = receiver.outgoing_requests.pop().unwrap();
+        assert_eq!(to, peer);
+        let Message::AssetRequest { asset_type, id } = bincode::deserialize(&request).unwrap()
+        else {
+            panic!("expected an asset request");
+        };
+        sender.respond_over_connection(peer, &asset_type, id);
+        assert_eq!(sender.outgoing_chunks.len(), 4);
+
+        for (_, chunk) in sender.outgoing_chunks.drain(..).rev() {
+            let Message::AssetChunk {
+                asset_type,
+                id,
+                offset,
+                total,
+                bytes,
+            } = bincode::deserialize(&chunk).unwrap()
+            else {
+                panic!("expected an asset chunk");
+            };
+            receiver.receive_chunk(&asset_type, id, offset, total, bytes);
+        }
+        assert_eq!(received(&receiver, id), Some(bytes));
+        assert!(receiver.downloads.is_empty());
+    }
+
+    #[test]
+    fn meshes_are_sent_with_the_encoding_and_attributes_of_the_app() {
+        const ATTRIBUTE_BLEND: MeshVertexAttribute =
+            MeshVertexAttribute::new("Vertex_Blend", 988540917, VertexFormat::Float32);
+        let mut mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::default(),
+        );
+        mesh.insert_attribute(
+            Mesh::ATTRIBUTE_POSITION,
+            vec![[0., 0., 0.], [1., 2., 1.], [2., 0., 0.]],
+        );
+        mesh.insert_attribute(ATTRIBUTE_BLEND, vec![0.5, 1., 2.]);
+        let mut track = SyncTrackerRes::default();
+        track.set_asset_codec(mesh_codec());
+        assert!(asset_to_bytes(&mut track, &mesh).is_err());
+
+        track.mesh_attributes.push((988540917, ATTRIBUTE_BLEND));
+        track.asset_encoding.mesh_compression = AssetCompression::Zstd;
+        let bytes = asset_to_bytes(&mut track, &mesh).unwrap();
+        let zstd = codec::compress(AssetCompression::Zstd, &[]).unwrap()[0];
+        assert_eq!(bytes[0], zstd);
+        let received: Mesh = asset_from_bytes(&track, &bytes, usize::MAX).unwrap();
+        assert_eq!(
+            received.attribute(ATTRIBUTE_BLEND).unwrap().get_bytes(),
+            mesh.attribute(ATTRIBUTE_BLEND).unwrap().get_bytes()
+        );
+    }
+
+    fn audio_tracker() -> SyncTrackerRes {
+        let mut track = SyncTrackerRes::default();
+        track.set_asset_codec(audio_codec());
+        track
+    }
+
+    fn serve_audio(
+        transfer: &mut SyncAssetTransfer,
+        id: Uuid,
+        bytes: Vec<u8>,
+    ) -> (String, AssetHash) {
+        let audio = AudioSource {
+            bytes: bytes.into(),
+        };
+        transfer.serve(&mut audio_tracker(), &id, &audio).unwrap()
+    }
+
+    fn deliver_chunks(sender: &mut SyncAssetTransfer, receiver: &mut SyncAssetTransfer) {
+        let peer = ClientId::from_raw(7);
+        for (_, request) in receiver.outgoing_requests.drain(..).collect::<Vec<_>>() {
+            let Message::AssetRequest { asset_type, id } = bincode::deserialize(&request).unwrap()
+            else {
+                panic!("expected an asset request");
+            };
+            sender.respond_over_connection(peer, &asset_type, id);
+        }
+        for (_, chunk) in sender.outgoing_chunks.drain(..) {
+            let Message::AssetChunk {
+                asset_type,
+                id,
+                offset,
+                total,
+                bytes,
+            } = bincode::deserialize(&chunk).unwrap()
+            else {
+                panic!("expected an asset chunk");
+            };
+            receiver.receive_chunk(&asset_type, id, offset, total, bytes);
+        }
+    }
+
+    #[test]
+    fn serves_modified_assets_with_their_new_content() {
+        let mut transfer = SyncAssetTransfer::in_memory("modified_assets");
+        let id = Uuid::new_v4();
+        let (_, hash) = serve_audio(&mut transfer, id, vec![1, 2, 3]);
+        let (_, modified_hash) = serve_audio(&mut transfer, id, vec![4, 5]);
+
+        assert_ne!(hash, modified_hash);
+        let served = transfer
+            .served_bytes(AudioSource::type_path(), &id)
+            .unwrap();
+        assert_eq!(content_hash(&served), modified_hash);
+    }
+
+    #[test]
+    fn requests_again_assets_announced_with_a_new_hash() {
+        let config = SyncNetworkConfig::default();
+        let mut sender = SyncAssetTransfer::over_connection(1_000_000, &config);
+        let mut receiver = SyncAssetTransfer::over_connection(1_000_000, &config);
+        let id = Uuid::new_v4();
+        let peer = ClientId::from_raw(7);
+        let (url, hash) = serve_audio(&mut sender, id, vec![1, 2, 3]);
+        receiver.request(AudioSource::type_path(), id, url.clone(), hash, peer);
+        deliver_chunks(&mut sender, &mut receiver);
+
+        receiver.request(AudioSource::type_path(), id, url.clone(), hash, peer);
+        assert!(receiver.outgoing_requests.is_empty());
+
+        let (url, hash) = serve_audio(&mut sender, id, vec![4, 5]);
+        receiver.request(AudioSource::type_path(), id, url, hash, peer);
+        deliver_chunks(&mut sender, &mut receiver);
+        assert_eq!(received(&receiver, id), Some(vec![4, 5]));
+    }
+
+    #[test]
+    fn requests_newer_content_once_the_older_is_received() {
+        let config = SyncNetworkConfig::default();
+        let mut sender = SyncAssetTransfer::over_connection(1_000_000, &config);
+        let mut receiver = SyncAssetTransfer::over_connection(1_000_000, &config);
+        let id = Uuid::new_v4();
+        let peer = ClientId::from_raw(7);
+        let (url, hash) = serve_audio(&mut sender, id, vec![1, 2, 3]);
+        receiver.request(AudioSource::type_path(), id, url.clone(), hash, peer);
+        let (_, request) = receiver.outgoing_requests.pop().unwrap();
+        let Message::AssetRequest { asset_type, id } = bincode::deserialize(&request).unwrap()
+        else {
+            panic!("expected an asset request");
+        };
+        sender.respond_over_connection(peer, &asset_type, id);
+
+        let (url, newer_hash) = serve_audio(&mut sender, id, vec![4, 5]);
+        receiver.request(AudioSource::type_path(), id, url, newer_hash, peer);
+        assert!(receiver.outgoing_requests.is_empty());
+        deliver_chunks(&mut sender, &mut receiver);
+        assert_eq!(received(&receiver, id), Some(vec![1, 2, 3]));
+        assert!(receiver
+            .served_bytes(AudioSource::type_path(), &id)
+            .is_none());
+
+        deliver_chunks(&mut sender, &mut receiver);
+        assert_eq!(received(&receiver, id), Some(vec![4, 5]));
+        assert!(receiver.failures.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn fails_downloads_and_forgets_requests_after_the_timeout() {
+        let config = SyncNetworkConfig {
+            asset_download_timeout: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let mut sender = SyncAssetTransfer::over_connection(1_000_000, &config);
+        let mut receiver = SyncAssetTransfer::over_connection(1_000_000, &config);
+        let id = Uuid::new_v4();
+        let peer = ClientId::from_raw(7);
+        let url = receiver.asset_url(AudioSource::type_path(), &id);
+        receiver.request(AudioSource::type_path(), id, url, [0; 32], peer);
+        // the sender never gets the asset, as a host relaying a type it does not sync
+        sender.respond_over_connection(peer, AudioSource::type_path(), id);
+        assert_eq!(sender.waiting.len(), 1);
+
+        std::thread::sleep(Duration::from_millis(20));
+        sender.drop_stalled();
+        receiver.drop_stalled();
+        assert!(sender.waiting.is_empty());
+        assert!(receiver.downloads.is_empty());
+        let failures = receiver.failures.lock().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].id, id);
+    }
+
+    #[test]
+    fn asset_urls_carry_the_type_path() {
+        let transfer = SyncAssetTransfer::in_memory("asset_urls");
+        let id = Uuid::new_v4();
+        let asset_type = "my_game::Level<my_game::Outdoor, 2>";
+        let url = transfer.asset_url(asset_type, &id);
+        assert!(!url.contains(' ') && !url.contains('<'));
+
+        let (base_url, split_type, split_id) = split_asset_url(&url).unwrap();
+        assert_eq!(base_url, transfer.base_url);
+        assert_eq!(split_type, asset_type);
+        assert_eq!(split_id, id);
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{IpAddr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+#[cfg(not(target_arch = "wasm32"))]
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{
+    networking::{transport::ServerTransport, unspecified_of},
+    AssetTransport, DiscoveredSession, DiscoveredSessions, DiscoveryPlugin, ServerState,
+    SyncConnectionParameters, SyncNetworkConfig, SyncPeers,
+};
+
+/// Tells announcements apart from any other traffic on the discovery port.
+const ANNOUNCEMENT_MAGIC: [u8; 4] = *b"BSYN";
+/// Bumped whenever the announcement layout changes.
+const ANNOUNCEMENT_VERSION: u16 = 2;
+const MAX_ANNOUNCEMENT_SIZE: usize = 1200;
+
+#[derive(Serialize, Deserialize)]
+struct Announcement {
+    magic: [u8; 4],
+    version: u16,
+    protocol_id: u64,
+    name: String,
+    port: u16,
+    web_port: u16,
+    asset_transport: AssetTransport,
+    players: u32,
+    secure: bool,
+}
+
+#[derive(Resource)]
+struct Discovery {
+    session_name: String,
+    port: u16,
+    broadcast_ip: IpAddr,
+    interval: Duration,
+    timeout: Duration,
+    sender: Option<UdpSocket>,
+    listener: Option<UdpSocket>,
+    announced_at: Option<Duration>,
+    listen_attempted_at: Option<Duration>,
+}
+
+impl Plugin for DiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DiscoveredSessions>();
+        app.init_resource::<SyncNetworkConfig>();
+        if cfg!(target_arch = "wasm32") {
+            warn!("Sessions cannot be discovered on wasm32, browsers have no UDP.");
+            return;
+        }
+        app.insert_resource(Discovery {
+            session_name: self.session_name.clone(),
+            port: self.port,
+            broadcast_ip: self.broadcast_ip,
+            interval: self.interval,
+            timeout: self.timeout,
+            sender: None,
+            listener: None,
+            announced_at: None,
+            listen_attempted_at: None,
+        });
+        app.add_systems(
+            Update,
+            (
+                announce_session
+                    .run_if(resource_exists::<ServerTransport>)
+                    .run_if(resource_exists::<SyncConnectionParameters>)
+                    .run_if(in_state(ServerState::Connected)),
+                listen_for_sessions.run_if(not(resource_exists::<ServerTransport>)),
+            ),
+        );
+    }
+}
+
+fn announce_session(
+    mut discovery: ResMut<Discovery>,
+    params: Res<SyncConnectionParameters>,
+    config: Res<SyncNetworkConfig>,
+    peers: Res<SyncPeers>,
+    time: Res<Time>,
+) {
+    // hosts don't browse, leave the port to clients on the same machine
+    discovery.listener = None;
+    let (port, web_port, asset_transport, secure) = match params.as_ref() {
+        SyncConnectionParameters::Socket {
+            port,
+            web_port,
+            asset_transport,
+            ..
+        } => (*port, *web_port, *asset_transport, false),
+        SyncConnectionParameters::SecureSocket {
+            port,
+            web_port,
+            asset_transport,
+            ..
+        } => (*port, *web_port, *asset_transport, true),
+        SyncConnectionParameters::InMemory { .. } | SyncConnectionParameters::WebSocket { .. } => {
+            return
+        }
+    };
+    let now = time.elapsed();
+    if discovery
+        .announced_at
+        .is_some_and(|at| now - at < discovery.interval)
+    {
+        return;
+    }
+    discovery.announced_at = Some(now);
+    if discovery.sender.is_none() {
+        match bind_sender(discovery.broadcast_ip) {
+            Ok(sender) => discovery.sender = Some(sender),
+            Err(e) => {
+                warn!("Could not announce the session: {}", e);
+                return;
+            }
+        }
+    }
+    let announcement = Announcement {
+        magic: ANNOUNCEMENT_MAGIC,
+        version: ANNOUNCEMENT_VERSION,
+        protocol_id: config.protocol_id,
+        name: discovery.session_name.clone(),
+        port,
+        web_port,
+        asset_transport,
+        players: peers.peers.len() as u32 + 1,
+        secure,
+    };
+    let target = SocketAddr::new(discovery.broadcast_ip, discovery.port);
+    let Some(sender) = &discovery.sender else {
+        return;
+    };
+    if let Err(e) = sender.send_to(&bincode::serialize(&announcement).unwrap(), target) {
+        debug!("Could not send session announcement to {}: {}", target, e);
+    }
+}
+
+fn listen_for_sessions(
+    mut discovery: ResMut<Discovery>,
+    mut discovered: ResMut<DiscoveredSessions>,
+    config: Res<SyncNetworkConfig>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed();
+    if discovery.listener.is_none()
+        && discovery
+            .listen_attempted_at
+            .map_or(true, |at| now - at >= discovery.interval)
+    {
+        discovery.listen_attempted_at = Some(now);
+        match bind_listener(discovery.broadcast_ip, discovery.port) {
+            Ok(listener) => discovery.listener = Some(listener),
+            Err(e) => warn!(
+                "Could not listen for sessions on port {}: {}",
+                discovery.port, e
+            ),
+        }
+    }
+    if let Some(listener) = &discovery.listener {
+        let mut buffer = [0; MAX_ANNOUNCEMENT_SIZE];
+        loop {
+            match listener.recv_from(&mut buffer) {
+                Ok((len, from)) => {
+                    let Some(session) =
+                        read_announcement(&buffer[..len], from.ip(), config.protocol_id, now)
+                    else {
+                        continue;
+                    };
+                    discovered
+                        .sessions
+                        .insert(SocketAddr::new(session.ip, session.port), session);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    debug!("Could not receive session announcements: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+    let timeout = discovery.timeout;
+    let stale = |session: &DiscoveredSession| now - session.last_seen >= timeout;
+    if discovered.sessions.values().any(stale) {
+        discovered.sessions.retain(|_, session| !stale(session));
+    }
+}
+
+fn read_announcement(
+    bytes: &[u8],
+    ip: IpAddr,
+    protocol_id: u64,
+    now: Duration,
+) -> Option<DiscoveredSession> {
+    let announcement: Announcement = bincode::deserialize(bytes).ok()?;
+    if announcement.magic != ANNOUNCEMENT_MAGIC
+        || announcement.version != ANNOUNCEMENT_VERSION
+        || announcement.protocol_id != protocol_id
+    {
+        return None;
+    }
+    Some(DiscoveredSession {
+        name: announcement.name,
+        ip,
+        port: announcement.port,
+        web_port: announcement.web_port,
+        asset_transport: announcement.asset_transport,
+        players: announcement.players as usize,
+        secure: announcement.secure,
+        last_seen: now,
+    })
+}
+
+fn bind_sender(broadcast_ip: IpAddr) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind((unspecified_of(broadcast_ip), 0))?;
+    socket.set_broadcast(true)?;
+    Ok(socket)
+}
+
+/// Listener on the discovery port, shared with the other apps listening on the same machine.
+#[cfg(not(target_arch = "wasm32"))]
+fn bind_listener(broadcast_ip: IpAddr, port: u16) -> io::Result<UdpSocket> {
+    let addr = SocketAddr::new(unspecified_of(broadcast_ip), port);
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(all(
+        unix,
+        not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))
+    ))]
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket.into())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn bind_listener(_: IpAddr, _: u16) -> io::Result<UdpSocket> {
+    Err(ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn listeners_share_the_discovery_port() {
+        let port = portpicker::pick_unused_port().unwrap();
+        let broadcast_ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let first = bind_listener(broadcast_ip, port).unwrap();
+        let second = bind_listener(broadcast_ip, port).unwrap();
+        assert_eq!(first.local_addr().unwrap(), second.local_addr().unwrap());
+    }
+}
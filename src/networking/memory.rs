@@ -0,0 +1,378 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::Duration,
+};
+
+use bevy::utils::HashMap;
+use bevy_renet::renet::ClientId;
+
+use super::transport::{SyncTransport, TransportEvent, HOST_PEER};
+
+type Queue = Arc<Mutex<VecDeque<Vec<u8>>>>;
+type Listener = Arc<Mutex<Vec<MemoryLink>>>;
+
+static LISTENERS: OnceLock<Mutex<HashMap<String, Listener>>> = OnceLock::new();
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+fn listeners() -> &'static Mutex<HashMap<String, Listener>> {
+    LISTENERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Both ends of an in-process connection share the same link.
+#[derive(Clone)]
+struct MemoryLink {
+    client_id: ClientId,
+    to_host: Queue,
+    to_client: Queue,
+    accepted: Arc<AtomicBool>,
+    open: Arc<AtomicBool>,
+}
+
+impl MemoryLink {
+    fn close(&self) {
+        self.open.store(false, Ordering::SeqCst);
+    }
+
+    fn is_open(&self) -> bool {
+        self.open.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Default)]
+struct Mailbox {
+    inbox: VecDeque<Vec<u8>>,
+    outbox: VecDeque<Vec<u8>>,
+}
+
+/// Host side of the in-process transport, listening on a session name.
+pub(crate) struct MemoryServer {
+    name: String,
+    listener: Listener,
+    max_clients: usize,
+    peers: HashMap<ClientId, (MemoryLink, Mailbox)>,
+    events: VecDeque<TransportEvent>,
+}
+
+/// Client side of the in-process transport, joining a session name.
+pub(crate) struct MemoryClient {
+    name: String,
+    link: Option<MemoryLink>,
+    mailbox: Mailbox,
+    disconnected: bool,
+}
+
+impl MemoryServer {
+    /// Fails when another host of the process already listens on the name.
+    pub(crate) fn bind(
+        name: &str,
+        max_clients: usize,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let listener = Listener::default();
+        let mut listeners = listeners().lock().unwrap();
+        if listeners.contains_key(name) {
+            return Err(format!("in-memory session {} is already hosted", name).into());
+        }
+        listeners.insert(name.to_string(), listener.clone());
+        Ok(Self {
+            name: name.to_string(),
+            listener,
+            max_clients,
+            peers: HashMap::new(),
+            events: VecDeque::new(),
+        })
+    }
+}
+
+impl MemoryClient {
+    pub(crate) fn connect(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            link: None,
+            mailbox: Mailbox::default(),
+            disconnected: false,
+        }
+    }
+
+    fn try_connect(&mut self) {
+        let Some(listener) = listeners().lock().unwrap().get(&self.name).cloned() else {
+            return;
+        };
+        let link = MemoryLink {
+            client_id: ClientId::from_raw(NEXT_CLIENT_ID.fetch_add(1, Ordering::SeqCst)),
+            to_host: Queue::default(),
+            to_client: Queue::default(),
+            accepted: Arc::new(AtomicBool::new(false)),
+            open: Arc::new(AtomicBool::new(true)),
+        };
+        listener.lock().unwrap().push(link.clone());
+        self.link = Some(link);
+    }
+}
+
+impl Drop for MemoryServer {
+    fn drop(&mut self) {
+        let mut listeners = listeners().lock().unwrap();
+        if listeners
+            .get(&self.name)
+            .is_some_and(|l| Arc::ptr_eq(l, &self.listener))
+        {
+            listeners.remove(&self.name);
+        }
+        for link in self.listener.lock().unwrap().drain(..) {
+            link.close();
+        }
+        for (link, _) in self.peers.values() {
+            link.close();
+        }
+    }
+}
+
+impl Drop for MemoryClient {
+    fn drop(&mut self) {
+        if let Some(link) = &self.link {
+            link.close();
+        }
+    }
+}
+
+impl SyncTransport for MemoryServer {
+    fn update(&mut self, _: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for link in self.listener.lock().unwrap().drain(..) {
+            if !link.is_open() {
+                continue;
+            }
+            if self.peers.len() >= self.max_clients {
+                link.close();
+                continue;
+            }
+            link.accepted.store(true, Ordering::SeqCst);
+            self.events.push_back(TransportEvent::PeerConnected {
+                peer: link.client_id,
+            });
+            self.peers
+                .insert(link.client_id, (link, Mailbox::default()));
+        }
+        let mut closed = vec![];
+        for (client_id, (link, mailbox)) in self.peers.iter_mut() {
+            mailbox.inbox.extend(link.to_host.lock().unwrap().drain(..));
+            if !link.is_open() {
+                closed.push(*client_id);
+            }
+        }
+        for client_id in closed {
+            self.peers.remove(&client_id);
+            self.events.push_back(TransportEvent::PeerDisconnected {
+                peer: client_id,
+                reason: "connection closed".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn send_packets(&mut self) {
+        for (link, mailbox) in self.peers.values_mut() {
+            link.to_client
+                .lock()
+                .unwrap()
+                .extend(mailbox.outbox.drain(..));
+        }
+    }
+
+    fn peers(&self) -> Vec<ClientId> {
+        let mut peers: Vec<ClientId> = self.peers.keys().copied().collect();
+        peers.sort();
+        peers
+    }
+
+    fn send(&mut self, peer: ClientId, message: Vec<u8>) {
+        if let Some((_, mailbox)) = self.peers.get_mut(&peer) {
+            mailbox.outbox.push_back(message);
+        }
+    }
+
+    fn receive(&mut self, peer: ClientId) -> Option<Vec<u8>> {
+        self.peers
+            .get_mut(&peer)
+            .and_then(|(_, mailbox)| mailbox.inbox.pop_front())
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn is_disconnected(&self) -> bool {
+        false
+    }
+
+    fn disconnect(&mut self, peer: ClientId) {
+        if let Some((link, _)) = self.peers.remove(&peer) {
+            link.close();
+            self.events.push_back(TransportEvent::PeerDisconnected {
+                peer,
+                reason: "disconnected by host".to_string(),
+            });
+        }
+    }
+
+    fn disconnect_all(&mut self) {
+        for peer in self.peers() {
+            self.disconnect(peer);
+        }
+    }
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        self.events.pop_front()
+    }
+}
+
+impl SyncTransport for MemoryClient {
+    fn update(&mut self, _: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.disconnected {
+            return Ok(());
+        }
+        let Some(link) = &self.link else {
+            self.try_connect();
+            return Ok(());
+        };
+        self.mailbox
+            .inbox
+            .extend(link.to_client.lock().unwrap().drain(..));
+        if !link.is_open() {
+            self.disconnected = true;
+        }
+        Ok(())
+    }
+
+    fn send_packets(&mut self) {
+        if !self.is_connected() {
+            return;
+        }
+        if let Some(link) = &self.link {
+            link.to_host
+                .lock()
+                .unwrap()
+                .extend(self.mailbox.outbox.drain(..));
+        }
+    }
+
+    fn peers(&self) -> Vec<ClientId> {
+        if self.is_connected() {
+            vec![HOST_PEER]
+        } else {
+            vec![]
+        }
+    }
+
+    fn send(&mut self, _: ClientId, message: Vec<u8>) {
+        if !self.disconnected {
+            self.mailbox.outbox.push_back(message);
+        }
+    }
+
+    fn receive(&mut self, _: ClientId) -> Option<Vec<u8>> {
+        self.mailbox.inbox.pop_front()
+    }
+
+    fn is_connected(&self) -> bool {
+        !self.disconnected
+            && self
+                .link
+                .as_ref()
+                .is_some_and(|link| link.accepted.load(Ordering::SeqCst))
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    fn disconnect(&mut self, _: ClientId) {
+        if let Some(link) = &self.link {
+            link.close();
+        }
+        self.disconnected = true;
+    }
+
+    fn disconnect_all(&mut self) {
+        self.disconnect(HOST_PEER);
+    }
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn client_connects_and_exchanges_messages() {
+        let mut server = MemoryServer::bind("memory-test-exchange", 64).unwrap();
+        let mut client = MemoryClient::connect("memory-test-exchange");
+        client.update(Duration::ZERO).unwrap();
+        assert!(!client.is_connected());
+        server.update(Duration::ZERO).unwrap();
+        let peer = server.peers()[0];
+        assert_eq!(
+            server.poll_event(),
+            Some(TransportEvent::PeerConnected { peer })
+        );
+        assert!(client.is_connected());
+
+        client.send(HOST_PEER, vec![1, 2]);
+        client.send_packets();
+        server.update(Duration::ZERO).unwrap();
+        assert_eq!(server.receive(peer), Some(vec![1, 2]));
+
+        server.send(peer, vec![3]);
+        server.send_packets();
+        client.update(Duration::ZERO).unwrap();
+        assert_eq!(client.receive(HOST_PEER), Some(vec![3]));
+        assert_eq!(client.receive(HOST_PEER), None);
+    }
+
+    #[test]
+    fn dropping_client_disconnects_from_server() {
+        let mut server = MemoryServer::bind("memory-test-drop", 64).unwrap();
+        let mut client = MemoryClient::connect("memory-test-drop");
+        client.update(Duration::ZERO).unwrap();
+        server.update(Duration::ZERO).unwrap();
+        let peer = server.peers()[0];
+        server.poll_event();
+        drop(client);
+        server.update(Duration::ZERO).unwrap();
+        assert!(server.peers().is_empty());
+        assert!(matches!(
+            server.poll_event(),
+            Some(TransportEvent::PeerDisconnected { peer: p, .. }) if p == peer
+        ));
+    }
+
+    #[test]
+    fn clients_over_max_clients_are_refused() {
+        let mut server = MemoryServer::bind("memory-test-max-clients", 1).unwrap();
+        let mut first = MemoryClient::connect("memory-test-max-clients");
+        let mut second = MemoryClient::connect("memory-test-max-clients");
+        first.update(Duration::ZERO).unwrap();
+        second.update(Duration::ZERO).unwrap();
+        server.update(Duration::ZERO).unwrap();
+        first.update(Duration::ZERO).unwrap();
+        second.update(Duration::ZERO).unwrap();
+        assert_eq!(server.peers().len(), 1);
+        assert!(first.is_connected());
+        assert!(second.is_disconnected());
+    }
+
+    #[test]
+    fn name_already_hosted_cannot_be_bound() {
+        let server = MemoryServer::bind("memory-test-bound", 64).unwrap();
+        assert!(MemoryServer::bind("memory-test-bound", 64).is_err());
+        drop(server);
+        assert!(MemoryServer::bind("memory-test-bound", 64).is_ok());
+    }
+}
@@ -1,95 +1,522 @@
 pub mod assets;
+pub(crate) mod discovery;
+pub(crate) mod memory;
+pub(crate) mod netcode;
+pub(crate) mod transport;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod websocket;
+#[cfg(target_arch = "wasm32")]
+mod websocket_wasm;
+#[cfg(target_arch = "wasm32")]
+use websocket_wasm as websocket;
 
 use std::{
-    net::{IpAddr, SocketAddr, UdpSocket},
+    error::Error,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
     time::SystemTime,
 };
 
 use bevy::prelude::*;
-use bevy_renet::{
-    renet::{
-        transport::{
-            ClientAuthentication, NetcodeClientTransport, NetcodeServerTransport,
-            ServerAuthentication, ServerConfig,
-        },
-        ConnectionConfig, RenetClient, RenetServer,
+use bevy_renet::renet::{
+    transport::{
+        generate_random_bytes, ClientAuthentication, ConnectToken, NetcodeClientTransport,
+        NetcodeServerTransport, ServerAuthentication, ServerConfig,
     },
-    transport::{NetcodeClientPlugin, NetcodeServerPlugin},
-    RenetClientPlugin, RenetServerPlugin,
+    ConnectionConfig, RenetClient, RenetServer, SendType,
 };
 
-use crate::SyncConnectionParameters;
+use crate::{
+    lib_priv::SyncTrackerRes, AssetTransport, ClientState, JoinSession, LeaveSession, PeerIdentity,
+    ServerState, StartHosting, SyncConnectionParameters, SyncNetworkConfig, SyncPeers,
+    SyncStartupError,
+};
 
-const PROTOCOL_ID: u64 = 1;
+use self::{
+    assets::SyncAssetTransfer,
+    memory::{MemoryClient, MemoryServer},
+    netcode::{NetcodeClient, NetcodeServer},
+    transport::{ClientTransport, FailedClient, ServerTransport},
+    websocket::WebSocketClient,
+};
 
-pub(crate) fn setup_server(app: &mut App, params: SyncConnectionParameters) {
-    match params {
+const CONNECT_TOKEN_TIMEOUT_SECONDS: i32 = 15;
+const WEBSOCKET_MAX_TRANSFER: usize = 100_000_000;
+
+/// Session management: runtime requests and the asset transfer systems.
+pub(crate) struct SessionPlugin;
+
+impl Plugin for SessionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StartHosting>();
+        app.add_event::<JoinSession>();
+        app.add_event::<LeaveSession>();
+        app.add_systems(Update, handle_session_requests);
+        assets::init(app);
+    }
+}
+
+fn handle_session_requests(
+    mut cmd: Commands,
+    mut leave: EventReader<LeaveSession>,
+    mut host: EventReader<StartHosting>,
+    mut join: EventReader<JoinSession>,
+) {
+    if leave.read().count() > 0 {
+        cmd.add(leave_session);
+    }
+    for StartHosting { parameters } in host.read() {
+        let parameters = parameters.clone();
+        cmd.add(move |world: &mut World| start_hosting(world, parameters));
+    }
+    for JoinSession { parameters } in join.read() {
+        let parameters = parameters.clone();
+        cmd.add(move |world: &mut World| join_session(world, parameters));
+    }
+}
+
+/// Hosts a new session, leaving the current one first.
+pub(crate) fn start_hosting(world: &mut World, mut params: SyncConnectionParameters) {
+    leave_session(world);
+    let result = network_config(world).and_then(|config| {
+        start_asset_transfer(world, &mut params, &config, true)?;
+        create_server(&mut params, &config)
+    });
+    world.insert_resource(params);
+    match result {
+        Ok(server) => world.insert_resource(server),
+        Err(e) => {
+            // nothing is left half started, the asset server port is released as well
+            world.remove_resource::<SyncAssetTransfer>();
+            server_startup_failed(world, e.to_string());
+        }
+    }
+}
+
+/// Joins a session, leaving the current one first.
+pub(crate) fn join_session(world: &mut World, mut params: SyncConnectionParameters) {
+    leave_session(world);
+    let identity = world
+        .get_resource_or_insert_with(SyncPeers::default)
+        .local
+        .clone();
+    let result = network_config(world).and_then(|config| {
+        start_asset_transfer(world, &mut params, &config, false)?;
+        create_client(&params, &identity, &config)
+    });
+    world.insert_resource(params);
+    match result {
+        Ok(client) => world.insert_resource(client),
+        Err(e) => {
+            world.remove_resource::<SyncAssetTransfer>();
+            client_startup_failed(world, e.to_string());
+        }
+    }
+}
+
+/// Closes the connections and the asset server of the current session, if any.
+pub(crate) fn leave_session(world: &mut World) {
+    if let Some(mut server) = world.remove_resource::<ServerTransport>() {
+        info!("Leaving hosted session.");
+        server.disconnect_all();
+        server.send_packets();
+    }
+    if let Some(mut client) = world.remove_resource::<ClientTransport>() {
+        info!("Leaving joined session.");
+        client.disconnect_all();
+        client.send_packets();
+    }
+    world.remove_resource::<SyncAssetTransfer>();
+    if let Some(mut tracker) = world.get_resource_mut::<SyncTrackerRes>() {
+        tracker.reset_session();
+    }
+    if let Some(mut peers) = world.get_resource_mut::<SyncPeers>() {
+        peers.peers.clear();
+    }
+    if world
+        .get_resource::<State<ServerState>>()
+        .is_some_and(|state| *state.get() != ServerState::Disconnected)
+    {
+        world
+            .resource_mut::<NextState<ServerState>>()
+            .set(ServerState::Disconnected);
+    }
+    if world
+        .get_resource::<State<ClientState>>()
+        .is_some_and(|state| *state.get() != ClientState::Disconnected)
+    {
+        world
+            .resource_mut::<NextState<ClientState>>()
+            .set(ClientState::Disconnected);
+    }
+}
+
+/// Reports that hosting could not start.
+pub(crate) fn server_startup_failed(world: &mut World, reason: String) {
+    error!("Could not start hosting: {}", reason);
+    world
+        .resource_mut::<NextState<ServerState>>()
+        .set(ServerState::Failed(reason.clone()));
+    world.send_event(SyncStartupError { reason });
+}
+
+/// Reports that joining could not start.
+pub(crate) fn client_startup_failed(world: &mut World, reason: String) {
+    error!("Could not start joining: {}", reason);
+    world
+        .resource_mut::<NextState<ClientState>>()
+        .set(ClientState::Failed(reason.clone()));
+    world.send_event(SyncStartupError { reason });
+}
+
+fn network_config(world: &mut World) -> StartupResult<SyncNetworkConfig> {
+    let config = world
+        .get_resource_or_insert_with(SyncNetworkConfig::default)
+        .clone();
+    if !config.channels.iter().any(|channel| {
+        channel.channel_id == config.sync_channel
+            && matches!(channel.send_type, SendType::ReliableOrdered { .. })
+    }) {
+        return Err(
+            "SyncNetworkConfig sync_channel must be one of its reliable ordered channels".into(),
+        );
+    }
+    Ok(config)
+}
+
+/// Asset chunks of netcode connections need a reliable channel of their own.
+fn check_asset_channel(config: &SyncNetworkConfig) -> StartupResult<()> {
+    if !config.channels.iter().any(|channel| {
+        channel.channel_id == config.asset_channel
+            && !matches!(channel.send_type, SendType::Unreliable)
+    }) {
+        return Err("SyncNetworkConfig asset_channel must be one of its reliable channels".into());
+    }
+    Ok(())
+}
+
+/// Starts the asset transfer, publishing its bound port in the parameters.
+fn start_asset_transfer(
+    world: &mut World,
+    params: &mut SyncConnectionParameters,
+    config: &SyncNetworkConfig,
+    hosting: bool,
+) -> StartupResult<()> {
+    let transfer = match params {
+        SyncConnectionParameters::Socket {
+            max_transfer,
+            asset_transport: AssetTransport::Connection,
+            ..
+        }
+        | SyncConnectionParameters::SecureSocket {
+            max_transfer,
+            asset_transport: AssetTransport::Connection,
+            ..
+        } => {
+            check_asset_channel(config)?;
+            SyncAssetTransfer::over_connection(*max_transfer, config)
+        }
         SyncConnectionParameters::Socket {
             ip,
             port,
             web_port,
             max_transfer,
+            ..
+        }
+        | SyncConnectionParameters::SecureSocket {
+            ip,
+            port,
+            web_port,
+            max_transfer,
+            ..
         } => {
-            setup_networking(app, ip, web_port, max_transfer);
-            app.insert_resource(create_server(ip, port));
+            // clients serve their assets on the interface facing the host
+            let web_ip = if hosting {
+                *ip
+            } else {
+                local_addr_towards(SocketAddr::new(*ip, *port))?.ip()
+            };
+            let transfer = SyncAssetTransfer::new(web_ip, *web_port, *max_transfer, config)?;
+            *web_port = transfer.web_port().unwrap_or(*web_port);
+            transfer
         }
-    }
+        SyncConnectionParameters::InMemory { name } => SyncAssetTransfer::in_memory(name),
+        // assets go over the WebSocket as well, so that a single connection is needed
+        SyncConnectionParameters::WebSocket { .. } => {
+            SyncAssetTransfer::over_connection(WEBSOCKET_MAX_TRANSFER, config)
+        }
+    };
+    world.insert_resource(transfer);
+    Ok(())
 }
 
-pub(crate) fn setup_client(app: &mut App, params: SyncConnectionParameters) {
+type StartupResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Starts hosting, publishing the bound port in the parameters.
+pub(crate) fn create_server(
+    params: &mut SyncConnectionParameters,
+    config: &SyncNetworkConfig,
+) -> StartupResult<ServerTransport> {
     match params {
-        SyncConnectionParameters::Socket {
+        SyncConnectionParameters::Socket { ip, port, .. } => {
+            create_socket_server(*ip, port, ServerAuthentication::Unsecure, config)
+        }
+        SyncConnectionParameters::SecureSocket {
             ip,
             port,
-            web_port,
-            max_transfer,
+            private_key,
+            ..
         } => {
-            setup_networking(app, ip, web_port, max_transfer);
-            app.insert_resource(create_client(ip, port));
+            let private_key =
+                private_key.ok_or("hosting a SecureSocket session needs the private key")?;
+            create_socket_server(
+                *ip,
+                port,
+                ServerAuthentication::Secure { private_key },
+                config,
+            )
+        }
+        SyncConnectionParameters::InMemory { name } => Ok(ServerTransport::new(
+            MemoryServer::bind(name, config.max_clients)?,
+        )),
+        #[cfg(not(target_arch = "wasm32"))]
+        SyncConnectionParameters::WebSocket { url } => {
+            let server = websocket::WebSocketServer::bind(url, config.max_clients)?;
+            *url = websocket::with_port(url, server.local_addr()?.port())?;
+            Ok(ServerTransport::new(server))
+        }
+        #[cfg(target_arch = "wasm32")]
+        SyncConnectionParameters::WebSocket { .. } => {
+            Err("browsers cannot accept connections, host the WebSocket session natively".into())
         }
     }
 }
 
-fn setup_networking(app: &mut App, ip: IpAddr, asset_port: u16, max_transfer: usize) {
-    assets::init(app, ip, asset_port, max_transfer);
+pub(crate) fn create_client(
+    params: &SyncConnectionParameters,
+    identity: &PeerIdentity,
+    config: &SyncNetworkConfig,
+) -> StartupResult<ClientTransport> {
+    match params {
+        SyncConnectionParameters::Socket { ip, port, .. } => {
+            create_socket_client(*ip, *port, netcode_client_id(identity), config)
+        }
+        SyncConnectionParameters::SecureSocket {
+            ip,
+            port,
+            connect_token,
+            ..
+        } => create_secure_socket_client(SocketAddr::new(*ip, *port), connect_token, config),
+        SyncConnectionParameters::InMemory { name } => {
+            Ok(ClientTransport::new(MemoryClient::connect(name)))
+        }
+        SyncConnectionParameters::WebSocket { url } => {
+            Ok(ClientTransport::new(WebSocketClient::connect(url)))
+        }
+    }
+}
 
-    app.add_plugins(RenetServerPlugin);
-    app.insert_resource(RenetServer::new(ConnectionConfig::default()));
-    app.add_plugins(NetcodeServerPlugin);
+/// Parameters received from another peer, which come without secrets, completed with the
+/// secrets this peer holds.
+pub(crate) fn with_own_secrets(
+    params: &SyncConnectionParameters,
+    own: &SyncConnectionParameters,
+) -> SyncConnectionParameters {
+    let mut params = params.clone();
+    if let (
+        SyncConnectionParameters::SecureSocket {
+            private_key,
+            connect_token,
+            ..
+        },
+        SyncConnectionParameters::SecureSocket {
+            private_key: own_private_key,
+            connect_token: own_connect_token,
+            ..
+        },
+    ) = (&mut params, own)
+    {
+        *private_key = *own_private_key;
+        connect_token.clone_from(own_connect_token);
+    }
+    params
+}
 
-    app.add_plugins(RenetClientPlugin);
-    app.insert_resource(RenetClient::new(ConnectionConfig::default()));
-    app.add_plugins(NetcodeClientPlugin);
+/// Generates a new random private key to share between hosts and the token issuing service.
+pub fn generate_private_key() -> [u8; 32] {
+    generate_random_bytes()
 }
 
-pub(crate) fn create_server(ip: IpAddr, port: u16) -> NetcodeServerTransport {
-    let socket = UdpSocket::bind((ip, port)).unwrap();
-    let server_addr = socket.local_addr().unwrap();
-    const MAX_CLIENTS: usize = 64;
-    let current_time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
+/// Issues a connect token for SecureSocket sessions, allowing the client with client_id
+/// to join the hosts at server_addresses for the next expire_seconds.
+/// The protocol id is the one of the hosts SyncNetworkConfig. Peers reconnect to a promoted
+/// host with the token they joined with, so server_addresses must also list the address of
+/// every client that may be promoted to host.
+pub fn generate_connect_token(
+    private_key: &[u8; 32],
+    protocol_id: u64,
+    client_id: u64,
+    server_addresses: Vec<SocketAddr>,
+    expire_seconds: u64,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    let token = ConnectToken::generate(
+        now,
+        protocol_id,
+        expire_seconds,
+        client_id,
+        CONNECT_TOKEN_TIMEOUT_SECONDS,
+        server_addresses,
+        None,
+        private_key,
+    )?;
+    let mut bytes = vec![];
+    token.write(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn create_socket_server(
+    ip: IpAddr,
+    port: &mut u16,
+    authentication: ServerAuthentication,
+    config: &SyncNetworkConfig,
+) -> StartupResult<ServerTransport> {
+    let socket = UdpSocket::bind((ip, *port))?;
+    let server_addr = socket.local_addr()?;
+    *port = server_addr.port();
+    let current_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
     let server_config = ServerConfig {
         current_time,
-        max_clients: MAX_CLIENTS,
-        protocol_id: PROTOCOL_ID,
+        max_clients: config.max_clients,
+        protocol_id: config.protocol_id,
         public_addresses: vec![server_addr],
-        authentication: ServerAuthentication::Unsecure,
+        authentication,
     };
-    NetcodeServerTransport::new(server_config, socket).unwrap()
+    Ok(ServerTransport::new(NetcodeServer {
+        server: RenetServer::new(connection_config(config)),
+        transport: NetcodeServerTransport::new(server_config, socket)?,
+        channel: config.sync_channel,
+        asset_channel: asset_channel(config),
+    }))
+}
+
+/// Local address this machine uses to reach the given one, so that peers can reach back.
+fn local_addr_towards(addr: SocketAddr) -> io::Result<SocketAddr> {
+    let socket = UdpSocket::bind((unspecified_of(addr.ip()), 0))?;
+    socket.connect(addr)?;
+    socket.local_addr()
+}
+
+/// Any address of the same family, to bind sockets on every interface.
+pub(crate) fn unspecified_of(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    }
+}
+
+/// Channel of the asset chunks, the sync channel when asset_channel is not configured as
+/// only AssetTransport::Connection sessions need it.
+fn asset_channel(config: &SyncNetworkConfig) -> u8 {
+    if config
+        .channels
+        .iter()
+        .any(|channel| channel.channel_id == config.asset_channel)
+    {
+        config.asset_channel
+    } else {
+        config.sync_channel
+    }
+}
+
+fn connection_config(config: &SyncNetworkConfig) -> ConnectionConfig {
+    ConnectionConfig {
+        available_bytes_per_tick: config.available_bytes_per_tick,
+        server_channels_config: config.channels.clone(),
+        client_channels_config: config.channels.clone(),
+    }
 }
 
-pub(crate) fn create_client(ip: IpAddr, port: u16) -> NetcodeClientTransport {
-    let socket = UdpSocket::bind((ip, 0)).unwrap();
-    let now = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap();
-    let client_id = now.as_millis() as u64;
+/// Unsecure netcode client id, stable for the same identity. Never 0, which is HOST_PEER.
+fn netcode_client_id(identity: &PeerIdentity) -> u64 {
+    let hash = blake3::hash(identity.id.as_bytes());
+    let id = u64::from_le_bytes(hash.as_bytes()[..8].try_into().unwrap());
+    id.max(1)
+}
+
+fn create_socket_client(
+    ip: IpAddr,
+    port: u16,
+    client_id: u64,
+    config: &SyncNetworkConfig,
+) -> StartupResult<ClientTransport> {
+    let socket = UdpSocket::bind((unspecified_of(ip), 0))?;
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
     let authentication = ClientAuthentication::Unsecure {
         client_id,
         server_addr: SocketAddr::new(ip, port),
-        protocol_id: PROTOCOL_ID,
+        protocol_id: config.protocol_id,
         user_data: None,
     };
-    NetcodeClientTransport::new(now, authentication, socket).unwrap()
+    Ok(ClientTransport::new(NetcodeClient {
+        client: RenetClient::new(connection_config(config)),
+        transport: NetcodeClientTransport::new(now, authentication, socket)?,
+        channel: config.sync_channel,
+        asset_channel: asset_channel(config),
+    }))
+}
+
+/// Connects to server_addr first when the token lists it, as after a host promotion, the
+/// other addresses of the token being tried next.
+fn create_secure_socket_client(
+    server_addr: SocketAddr,
+    connect_token: &[u8],
+    config: &SyncNetworkConfig,
+) -> StartupResult<ClientTransport> {
+    let mut connect_token = match ConnectToken::read(&mut &connect_token[..]) {
+        Ok(connect_token) => connect_token,
+        Err(e) => {
+            return Ok(ClientTransport::new(FailedClient(format!(
+                "invalid connect token: {}",
+                e
+            ))))
+        }
+    };
+    // hosts only check their address is in the private part of the token, so the public
+    // addresses can be reordered
+    let addresses = &mut connect_token.server_addresses;
+    if let Some(index) = addresses.iter().position(|addr| *addr == Some(server_addr)) {
+        addresses[..=index].rotate_right(1);
+    }
+    let socket = UdpSocket::bind((unspecified_of(server_addr.ip()), 0))?;
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+    let authentication = ClientAuthentication::Secure { connect_token };
+    Ok(ClientTransport::new(NetcodeClient {
+        client: RenetClient::new(connection_config(config)),
+        transport: NetcodeClientTransport::new(now, authentication, socket)?,
+        channel: config.sync_channel,
+        asset_channel: asset_channel(config),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn netcode_client_id_is_never_the_host() {
+        // both halves equal, which gave 0 when they were combined with a xor
+        let identity = PeerIdentity {
+            id: Uuid::from_u64_pair(7, 7),
+            name: "twin halves".to_string(),
+        };
+        assert_ne!(netcode_client_id(&identity), 0);
+        assert_eq!(netcode_client_id(&identity), netcode_client_id(&identity));
+        assert_ne!(
+            netcode_client_id(&identity),
+            netcode_client_id(&PeerIdentity::named("other"))
+        );
+    }
 }
@@ -0,0 +1,189 @@
+use std::{error::Error, time::Duration};
+
+use bevy_renet::renet::{
+    transport::{NetcodeClientTransport, NetcodeServerTransport},
+    ClientId, RenetClient, RenetServer, ServerEvent,
+};
+
+use super::transport::{PeerNetworkInfo, SyncTransport, TransportEvent, HOST_PEER};
+
+/// Host side transport over renet netcode UDP.
+pub(crate) struct NetcodeServer {
+    pub(crate) server: RenetServer,
+    pub(crate) transport: NetcodeServerTransport,
+    /// Channel carrying the sync messages.
+    pub(crate) channel: u8,
+    /// Channel carrying the asset chunks.
+    pub(crate) asset_channel: u8,
+}
+
+/// Client side transport over renet netcode UDP.
+pub(crate) struct NetcodeClient {
+    pub(crate) client: RenetClient,
+    pub(crate) transport: NetcodeClientTransport,
+    /// Channel carrying the sync messages.
+    pub(crate) channel: u8,
+    /// Channel carrying the asset chunks.
+    pub(crate) asset_channel: u8,
+}
+
+impl SyncTransport for NetcodeServer {
+    fn update(&mut self, delta: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.server.update(delta);
+        self.transport.update(delta, &mut self.server)?;
+        Ok(())
+    }
+
+    fn send_packets(&mut self) {
+        self.transport.send_packets(&mut self.server);
+    }
+
+    fn peers(&self) -> Vec<ClientId> {
+        self.server.clients_id()
+    }
+
+    fn send(&mut self, peer: ClientId, message: Vec<u8>) {
+        self.server.send_message(peer, self.channel, message);
+    }
+
+    fn receive(&mut self, peer: ClientId) -> Option<Vec<u8>> {
+        self.server
+            .receive_message(peer, self.channel)
+            .or_else(|| {
+                (self.asset_channel != self.channel)
+                    .then(|| self.server.receive_message(peer, self.asset_channel))
+                    .flatten()
+            })
+            .map(|bytes| bytes.to_vec())
+    }
+
+    fn send_asset(&mut self, peer: ClientId, message: Vec<u8>) {
+        self.server.send_message(peer, self.asset_channel, message);
+    }
+
+    fn can_send_asset(&self, peer: ClientId, size: usize) -> bool {
+        self.server.can_send_message(peer, self.asset_channel, size)
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn is_disconnected(&self) -> bool {
+        false
+    }
+
+    fn disconnect(&mut self, peer: ClientId) {
+        self.server.disconnect(peer);
+    }
+
+    fn disconnect_all(&mut self) {
+        self.transport.disconnect_all(&mut self.server);
+    }
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        self.server.get_event().map(|event| match event {
+            ServerEvent::ClientConnected { client_id } => {
+                TransportEvent::PeerConnected { peer: client_id }
+            }
+            ServerEvent::ClientDisconnected { client_id, reason } => {
+                TransportEvent::PeerDisconnected {
+                    peer: client_id,
+                    reason: reason.to_string(),
+                }
+            }
+        })
+    }
+
+    fn network_info(&self, peer: ClientId) -> Option<PeerNetworkInfo> {
+        let info = self.server.network_info(peer).ok()?;
+        Some(PeerNetworkInfo {
+            rtt: info.rtt,
+            packet_loss: info.packet_loss,
+        })
+    }
+}
+
+impl SyncTransport for NetcodeClient {
+    fn update(&mut self, delta: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.client.update(delta);
+        self.transport.update(delta, &mut self.client)?;
+        Ok(())
+    }
+
+    fn send_packets(&mut self) {
+        // errors here are only for an already disconnected client, reported by update
+        self.transport.send_packets(&mut self.client).unwrap_or(());
+    }
+
+    fn peers(&self) -> Vec<ClientId> {
+        if self.client.is_connected() {
+            vec![HOST_PEER]
+        } else {
+            vec![]
+        }
+    }
+
+    fn send(&mut self, _: ClientId, message: Vec<u8>) {
+        self.client.send_message(self.channel, message);
+    }
+
+    fn receive(&mut self, _: ClientId) -> Option<Vec<u8>> {
+        self.client
+            .receive_message(self.channel)
+            .or_else(|| {
+                (self.asset_channel != self.channel)
+                    .then(|| self.client.receive_message(self.asset_channel))
+                    .flatten()
+            })
+            .map(|bytes| bytes.to_vec())
+    }
+
+    fn send_asset(&mut self, _: ClientId, message: Vec<u8>) {
+        self.client.send_message(self.asset_channel, message);
+    }
+
+    fn can_send_asset(&self, _: ClientId, size: usize) -> bool {
+        self.client.can_send_message(self.asset_channel, size)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.client.is_connected()
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.client.is_disconnected()
+    }
+
+    fn disconnect(&mut self, _: ClientId) {
+        self.client.disconnect();
+    }
+
+    fn disconnect_all(&mut self) {
+        self.client.disconnect();
+        self.transport.disconnect();
+    }
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        None
+    }
+
+    fn disconnect_reason(&self) -> Option<String> {
+        self.transport
+            .disconnect_reason()
+            .map(|reason| reason.to_string())
+            .or_else(|| {
+                self.client
+                    .disconnect_reason()
+                    .map(|reason| reason.to_string())
+            })
+    }
+
+    fn network_info(&self, _: ClientId) -> Option<PeerNetworkInfo> {
+        let info = self.client.network_info();
+        Some(PeerNetworkInfo {
+            rtt: info.rtt,
+            packet_loss: info.packet_loss,
+        })
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    error::Error,
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+
+use bevy::{app::AppExit, prelude::*};
+use bevy_renet::renet::ClientId;
+use serde::{Deserialize, Serialize};
+
+/// Peer id that client transports use to address the host.
+pub const HOST_PEER: ClientId = ClientId::from_raw(0);
+
+/// Connection quality of a peer, as measured by the transport.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct PeerNetworkInfo {
+    /// Round trip time, in seconds.
+    pub rtt: f64,
+    /// Fraction of the packets lost, from 0 to 1.
+    pub packet_loss: f64,
+}
+
+/// Connection events reported by a transport.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportEvent {
+    PeerConnected { peer: ClientId },
+    PeerDisconnected { peer: ClientId, reason: String },
+}
+
+/// Network layer used by bevy_sync to exchange messages between peers.
+///
+/// A host transport has one peer per connected client, while a client transport only has the
+/// host as peer, addressed by HOST_PEER. Messages are opaque bytes and must be delivered
+/// reliably and in order, except asset chunks which only need to be reliable.
+pub trait SyncTransport: Send + Sync + 'static {
+    /// Advances the transport and receives pending data from the network.
+    fn update(&mut self, delta: Duration) -> Result<(), Box<dyn Error + Send + Sync>>;
+    /// Sends out all the messages queued since the last call.
+    fn send_packets(&mut self);
+    /// Currently connected peers.
+    fn peers(&self) -> Vec<ClientId>;
+    fn send(&mut self, peer: ClientId, message: Vec<u8>);
+    fn receive(&mut self, peer: ClientId) -> Option<Vec<u8>>;
+    fn is_connected(&self) -> bool;
+    fn is_disconnected(&self) -> bool;
+    fn disconnect(&mut self, peer: ClientId);
+    fn disconnect_all(&mut self);
+    fn poll_event(&mut self) -> Option<TransportEvent>;
+
+    /// Why the connection ended, when known.
+    fn disconnect_reason(&self) -> Option<String> {
+        None
+    }
+
+    /// Connection quality with a peer, when the transport measures it.
+    fn network_info(&self, _peer: ClientId) -> Option<PeerNetworkInfo> {
+        None
+    }
+
+    /// Sends a chunk of asset data, on a channel of its own when the transport has one so
+    /// that sync messages are not held back behind large assets.
+    fn send_asset(&mut self, peer: ClientId, message: Vec<u8>) {
+        self.send(peer, message);
+    }
+
+    /// Whether an asset chunk of that size can be queued now, chunks wait otherwise.
+    fn can_send_asset(&self, _peer: ClientId, _size: usize) -> bool {
+        true
+    }
+
+    fn broadcast(&mut self, message: Vec<u8>) {
+        for peer in self.peers() {
+            self.send(peer, message.clone());
+        }
+    }
+
+    fn broadcast_except(&mut self, except: ClientId, message: Vec<u8>) {
+        for peer in self.peers() {
+            if peer != except {
+                self.send(peer, message.clone());
+            }
+        }
+    }
+}
+
+/// Client that could not even start connecting, it only reports why.
+pub(crate) struct FailedClient(pub(crate) String);
+
+impl SyncTransport for FailedClient {
+    fn update(&mut self, _: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    fn send_packets(&mut self) {}
+
+    fn peers(&self) -> Vec<ClientId> {
+        vec![]
+    }
+
+    fn send(&mut self, _: ClientId, _: Vec<u8>) {}
+
+    fn receive(&mut self, _: ClientId) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn is_connected(&self) -> bool {
+        false
+    }
+
+    fn is_disconnected(&self) -> bool {
+        true
+    }
+
+    fn disconnect(&mut self, _: ClientId) {}
+
+    fn disconnect_all(&mut self) {}
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        None
+    }
+
+    fn disconnect_reason(&self) -> Option<String> {
+        Some(self.0.clone())
+    }
+}
+
+/// Transport used while hosting. Its presence means this app is acting as host.
+#[derive(Resource)]
+pub struct ServerTransport(pub Box<dyn SyncTransport>);
+
+/// Transport used while joined to a host. Its presence means this app is acting as client.
+#[derive(Resource)]
+pub struct ClientTransport(pub Box<dyn SyncTransport>);
+
+impl ServerTransport {
+    pub fn new(transport: impl SyncTransport) -> Self {
+        Self(Box::new(transport))
+    }
+}
+
+impl ClientTransport {
+    pub fn new(transport: impl SyncTransport) -> Self {
+        Self(Box::new(transport))
+    }
+}
+
+impl Deref for ServerTransport {
+    type Target = dyn SyncTransport;
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl DerefMut for ServerTransport {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut()
+    }
+}
+
+impl Deref for ClientTransport {
+    type Target = dyn SyncTransport;
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref()
+    }
+}
+
+impl DerefMut for ClientTransport {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut()
+    }
+}
+
+/// Drives whichever transports are present: receive before Update, send after it.
+pub(crate) struct TransportPlugin;
+
+impl Plugin for TransportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (
+                update_server.run_if(resource_exists::<ServerTransport>),
+                update_client.run_if(resource_exists::<ClientTransport>),
+            ),
+        );
+        app.add_systems(
+            PostUpdate,
+            (
+                send_server_packets.run_if(resource_exists::<ServerTransport>),
+                send_client_packets.run_if(resource_exists::<ClientTransport>),
+            ),
+        );
+        app.add_systems(Last, disconnect_on_exit);
+    }
+}
+
+fn update_server(mut transport: ResMut<ServerTransport>, time: Res<Time>) {
+    if let Err(e) = transport.update(time.delta()) {
+        debug!("Server transport error: {}", e);
+    }
+}
+
+fn update_client(mut transport: ResMut<ClientTransport>, time: Res<Time>) {
+    if let Err(e) = transport.update(time.delta()) {
+        debug!("Client transport error: {}", e);
+    }
+}
+
+fn send_server_packets(mut transport: ResMut<ServerTransport>) {
+    transport.send_packets();
+}
+
+fn send_client_packets(mut transport: ResMut<ClientTransport>) {
+    transport.send_packets();
+}
+
+fn disconnect_on_exit(
+    exit: EventReader<AppExit>,
+    server: Option<ResMut<ServerTransport>>,
+    client: Option<ResMut<ClientTransport>>,
+) {
+    if exit.is_empty() {
+        return;
+    }
+    if let Some(mut server) = server {
+        server.disconnect_all();
+    }
+    if let Some(mut client) = client {
+        client.disconnect_all();
+    }
+}
@@ -0,0 +1,461 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    io::{self, ErrorKind},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        mpsc::{channel, Receiver, TryRecvError},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use bevy::utils::HashMap;
+use bevy_renet::renet::ClientId;
+use tungstenite::{
+    handshake::{server::NoCallback, MidHandshake},
+    http::{uri::InvalidUri, Uri},
+    HandshakeError, Message, ServerHandshake, WebSocket,
+};
+
+use super::transport::{SyncTransport, TransportEvent, HOST_PEER};
+
+type Handshake = MidHandshake<ServerHandshake<TcpStream, NoCallback>>;
+type Connecting = Mutex<Receiver<Result<WebSocket<TcpStream>, String>>>;
+
+/// Longest time a connection may take to complete its handshake, it counts as a client until
+/// then.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Resolves the address of a ws:// url, defaulting to port 80.
+pub(crate) fn socket_addr(url: &str) -> io::Result<SocketAddr> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|e| io::Error::new(ErrorKind::InvalidInput, e))?;
+    let host = uri
+        .host()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "url has no host"))?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    (host, uri.port_u16().unwrap_or(80))
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(ErrorKind::AddrNotAvailable, "url host did not resolve"))
+}
+
+/// Same url with another port, keeping the scheme, host and path.
+pub(crate) fn with_port(url: &str, port: u16) -> io::Result<String> {
+    let invalid = |e: String| io::Error::new(ErrorKind::InvalidInput, e);
+    let uri: Uri = url
+        .parse()
+        .map_err(|e: InvalidUri| invalid(e.to_string()))?;
+    let host = uri
+        .host()
+        .ok_or_else(|| invalid("url has no host".to_string()))?;
+    let authority = format!("{}:{}", host, port)
+        .parse()
+        .map_err(|e: InvalidUri| invalid(e.to_string()))?;
+    let mut parts = uri.into_parts();
+    parts.authority = Some(authority);
+    Uri::from_parts(parts)
+        .map(|uri| uri.to_string())
+        .map_err(|e| invalid(e.to_string()))
+}
+
+fn would_block(e: &tungstenite::Error) -> bool {
+    matches!(e, tungstenite::Error::Io(e) if e.kind() == ErrorKind::WouldBlock)
+}
+
+struct Peer {
+    socket: WebSocket<TcpStream>,
+    inbox: VecDeque<Vec<u8>>,
+}
+
+/// Host side transport accepting WebSocket connections, one binary frame per message.
+pub(crate) struct WebSocketServer {
+    listener: TcpListener,
+    next_client_id: u64,
+    max_clients: usize,
+    handshake_timeout: Duration,
+    /// Connections still handshaking, with the time they were accepted at.
+    handshakes: Vec<(ClientId, Instant, Handshake)>,
+    peers: HashMap<ClientId, Peer>,
+    events: VecDeque<TransportEvent>,
+}
+
+/// Client side transport joining a WebSocket host.
+pub(crate) struct WebSocketClient {
+    connecting: Option<Connecting>,
+    socket: Option<WebSocket<TcpStream>>,
+    inbox: VecDeque<Vec<u8>>,
+    outbox: VecDeque<Vec<u8>>,
+    disconnected: bool,
+    reason: Option<String>,
+}
+
+impl WebSocketServer {
+    pub(crate) fn bind(url: &str, max_clients: usize) -> io::Result<Self> {
+        let listener = TcpListener::bind(socket_addr(url)?)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            next_client_id: 1,
+            max_clients,
+            handshake_timeout: HANDSHAKE_TIMEOUT,
+            handshakes: vec![],
+            peers: HashMap::new(),
+            events: VecDeque::new(),
+        })
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    fn accept(&mut self) {
+        // stalled handshakes are dropped before they keep new clients out
+        let now = Instant::now();
+        for (client_id, accepted_at, handshake) in std::mem::take(&mut self.handshakes) {
+            if now - accepted_at < self.handshake_timeout {
+                self.handshake(client_id, accepted_at, handshake.handshake());
+            }
+        }
+        while let Ok((stream, _)) = self.listener.accept() {
+            if self.peers.len() + self.handshakes.len() >= self.max_clients
+                || stream.set_nonblocking(true).is_err()
+            {
+                continue;
+            }
+            stream.set_nodelay(true).unwrap_or(());
+            let client_id = ClientId::from_raw(self.next_client_id);
+            self.next_client_id += 1;
+            self.handshake(client_id, now, tungstenite::accept(stream));
+        }
+    }
+
+    fn handshake(
+        &mut self,
+        client_id: ClientId,
+        accepted_at: Instant,
+        result: Result<
+            WebSocket<TcpStream>,
+            HandshakeError<ServerHandshake<TcpStream, NoCallback>>,
+        >,
+    ) {
+        match result {
+            Ok(socket) => {
+                self.peers.insert(
+                    client_id,
+                    Peer {
+                        socket,
+                        inbox: VecDeque::new(),
+                    },
+                );
+                self.events
+                    .push_back(TransportEvent::PeerConnected { peer: client_id });
+            }
+            Err(HandshakeError::Interrupted(handshake)) => {
+                self.handshakes.push((client_id, accepted_at, handshake));
+            }
+            Err(HandshakeError::Failure(_)) => {}
+        }
+    }
+}
+
+impl WebSocketClient {
+    pub(crate) fn connect(url: &str) -> Self {
+        let (tx, rx) = channel();
+        let url = url.to_string();
+        std::thread::spawn(move || {
+            let result = socket_addr(&url)
+                .and_then(TcpStream::connect)
+                .map_err(|e| e.to_string())
+                .and_then(|stream| {
+                    tungstenite::client(url.as_str(), stream).map_err(|e| e.to_string())
+                })
+                .and_then(|(socket, _)| {
+                    let stream = socket.get_ref();
+                    stream.set_nodelay(true).unwrap_or(());
+                    stream.set_nonblocking(true).map_err(|e| e.to_string())?;
+                    Ok(socket)
+                });
+            tx.send(result).unwrap_or(());
+        });
+        Self {
+            connecting: Some(Mutex::new(rx)),
+            socket: None,
+            inbox: VecDeque::new(),
+            outbox: VecDeque::new(),
+            disconnected: false,
+            reason: None,
+        }
+    }
+}
+
+impl SyncTransport for WebSocketServer {
+    fn update(&mut self, _: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.accept();
+        let mut closed = vec![];
+        for (client_id, peer) in self.peers.iter_mut() {
+            loop {
+                match peer.socket.read() {
+                    Ok(Message::Binary(bytes)) => peer.inbox.push_back(bytes),
+                    Ok(Message::Close(_)) => {
+                        closed.push((*client_id, "connection closed".to_string()));
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(e) if would_block(&e) => break,
+                    Err(e) => {
+                        closed.push((*client_id, e.to_string()));
+                        break;
+                    }
+                }
+            }
+        }
+        for (client_id, reason) in closed {
+            self.peers.remove(&client_id);
+            self.events.push_back(TransportEvent::PeerDisconnected {
+                peer: client_id,
+                reason,
+            });
+        }
+        Ok(())
+    }
+
+    fn send_packets(&mut self) {
+        for peer in self.peers.values_mut() {
+            // failures surface as a disconnection on the next read
+            peer.socket.flush().unwrap_or(());
+        }
+    }
+
+    fn peers(&self) -> Vec<ClientId> {
+        let mut peers: Vec<ClientId> = self.peers.keys().copied().collect();
+        peers.sort();
+        peers
+    }
+
+    fn send(&mut self, peer: ClientId, message: Vec<u8>) {
+        if let Some(peer) = self.peers.get_mut(&peer) {
+            // a blocked write still keeps the message buffered for the next flush
+            peer.socket.write(Message::Binary(message)).unwrap_or(());
+        }
+    }
+
+    fn receive(&mut self, peer: ClientId) -> Option<Vec<u8>> {
+        self.peers
+            .get_mut(&peer)
+            .and_then(|peer| peer.inbox.pop_front())
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn is_disconnected(&self) -> bool {
+        false
+    }
+
+    fn disconnect(&mut self, peer: ClientId) {
+        if let Some(mut removed) = self.peers.remove(&peer) {
+            removed.socket.close(None).unwrap_or(());
+            removed.socket.flush().unwrap_or(());
+            self.events.push_back(TransportEvent::PeerDisconnected {
+                peer,
+                reason: "disconnected by host".to_string(),
+            });
+        }
+    }
+
+    fn disconnect_all(&mut self) {
+        for peer in self.peers() {
+            self.disconnect(peer);
+        }
+    }
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        self.events.pop_front()
+    }
+}
+
+impl SyncTransport for WebSocketClient {
+    fn update(&mut self, _: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(connecting) = &self.connecting {
+            let result = connecting.lock().unwrap().try_recv();
+            match result {
+                Ok(Ok(socket)) => {
+                    self.socket = Some(socket);
+                    self.connecting = None;
+                }
+                Ok(Err(e)) => {
+                    self.connecting = None;
+                    self.disconnected = true;
+                    self.reason = Some(e.clone());
+                    return Err(e.into());
+                }
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => {
+                    self.connecting = None;
+                    self.disconnected = true;
+                }
+            }
+        }
+        let Some(socket) = &mut self.socket else {
+            return Ok(());
+        };
+        loop {
+            match socket.read() {
+                Ok(Message::Binary(bytes)) => self.inbox.push_back(bytes),
+                Ok(Message::Close(_)) => {
+                    self.socket = None;
+                    self.disconnected = true;
+                    self.reason = Some("connection closed by host".to_string());
+                    return Ok(());
+                }
+                Ok(_) => {}
+                Err(e) if would_block(&e) => return Ok(()),
+                Err(e) => {
+                    self.socket = None;
+                    self.disconnected = true;
+                    self.reason = Some(e.to_string());
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+
+    fn send_packets(&mut self) {
+        let Some(socket) = &mut self.socket else {
+            return;
+        };
+        for message in self.outbox.drain(..) {
+            socket.write(Message::Binary(message)).unwrap_or(());
+        }
+        socket.flush().unwrap_or(());
+    }
+
+    fn peers(&self) -> Vec<ClientId> {
+        if self.is_connected() {
+            vec![HOST_PEER]
+        } else {
+            vec![]
+        }
+    }
+
+    fn send(&mut self, _: ClientId, message: Vec<u8>) {
+        if !self.disconnected {
+            self.outbox.push_back(message);
+        }
+    }
+
+    fn receive(&mut self, _: ClientId) -> Option<Vec<u8>> {
+        self.inbox.pop_front()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    fn disconnect(&mut self, _: ClientId) {
+        if let Some(mut socket) = self.socket.take() {
+            socket.close(None).unwrap_or(());
+            socket.flush().unwrap_or(());
+        }
+        self.connecting = None;
+        self.disconnected = true;
+    }
+
+    fn disconnect_all(&mut self) {
+        self.disconnect(HOST_PEER);
+    }
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        None
+    }
+
+    fn disconnect_reason(&self) -> Option<String> {
+        self.reason.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wait_for(mut condition: impl FnMut() -> bool) {
+        for _ in 0..500 {
+            if condition() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        panic!("condition not reached in time");
+    }
+
+    #[test]
+    fn client_connects_and_exchanges_messages() {
+        let port = portpicker::pick_unused_port().unwrap();
+        let url = format!("ws://127.0.0.1:{}", port);
+        let mut server = WebSocketServer::bind(&url, 64).unwrap();
+        let mut client = WebSocketClient::connect(&url);
+        wait_for(|| {
+            server.update(Duration::ZERO).unwrap();
+            client.update(Duration::ZERO).unwrap();
+            client.is_connected() && !server.peers().is_empty()
+        });
+        let peer = server.peers()[0];
+        assert_eq!(
+            server.poll_event(),
+            Some(TransportEvent::PeerConnected { peer })
+        );
+
+        client.send(HOST_PEER, vec![1, 2]);
+        client.send_packets();
+        wait_for(|| {
+            server.update(Duration::ZERO).unwrap();
+            server.peers.get(&peer).is_some_and(|p| !p.inbox.is_empty())
+        });
+        assert_eq!(server.receive(peer), Some(vec![1, 2]));
+
+        server.send(peer, vec![3]);
+        server.send_packets();
+        wait_for(|| {
+            client.update(Duration::ZERO).unwrap();
+            !client.inbox.is_empty()
+        });
+        assert_eq!(client.receive(HOST_PEER), Some(vec![3]));
+
+        client.disconnect_all();
+        wait_for(|| {
+            server.update(Duration::ZERO).unwrap();
+            server.peers().is_empty()
+        });
+    }
+
+    #[test]
+    fn stalled_handshakes_do_not_keep_clients_out() {
+        let port = portpicker::pick_unused_port().unwrap();
+        let url = format!("ws://127.0.0.1:{}", port);
+        let mut server = WebSocketServer::bind(&url, 1).unwrap();
+        server.handshake_timeout = Duration::from_millis(100);
+        // connects without ever sending its handshake
+        let _stalled = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        wait_for(|| {
+            server.update(Duration::ZERO).unwrap();
+            !server.handshakes.is_empty()
+        });
+        std::thread::sleep(Duration::from_millis(100));
+        let mut client = WebSocketClient::connect(&url);
+        wait_for(|| {
+            server.update(Duration::ZERO).unwrap();
+            client.update(Duration::ZERO).unwrap();
+            client.is_connected() && !server.peers().is_empty()
+        });
+        assert!(server.handshakes.is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use std::{cell::RefCell, collections::VecDeque, error::Error, rc::Rc, time::Duration};
+
+use bevy_renet::renet::ClientId;
+use js_sys::{ArrayBuffer, Uint8Array};
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{BinaryType, CloseEvent, MessageEvent, WebSocket};
+
+use super::transport::{SyncTransport, TransportEvent, HOST_PEER};
+
+/// What the browser callbacks report, read back on the next update.
+#[derive(Default)]
+struct Received {
+    opened: bool,
+    inbox: VecDeque<Vec<u8>>,
+    closed: Option<String>,
+}
+
+/// Callbacks registered on the socket, kept alive as long as it is.
+struct Callbacks {
+    _on_open: Closure<dyn FnMut()>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+}
+
+/// Client side transport joining a WebSocket host from a browser, one binary frame per message.
+pub(crate) struct WebSocketClient {
+    socket: Option<(WebSocket, Callbacks)>,
+    received: Rc<RefCell<Received>>,
+    outbox: VecDeque<Vec<u8>>,
+    disconnected: bool,
+    reason: Option<String>,
+}
+
+// SAFETY: wasm32-unknown-unknown is built without atomics, so everything runs on the browser
+// thread and the socket and its callbacks are never reached from another one.
+unsafe impl Send for WebSocketClient {}
+unsafe impl Sync for WebSocketClient {}
+
+impl WebSocketClient {
+    pub(crate) fn connect(url: &str) -> Self {
+        let received = Rc::new(RefCell::new(Received::default()));
+        let socket = match WebSocket::new(url) {
+            Ok(socket) => Some((socket.clone(), listen(&socket, &received))),
+            Err(e) => {
+                received.borrow_mut().closed = Some(format!("{:?}", e));
+                None
+            }
+        };
+        Self {
+            socket,
+            received,
+            outbox: VecDeque::new(),
+            disconnected: false,
+            reason: None,
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some((socket, _)) = self.socket.take() {
+            socket.set_onopen(None);
+            socket.set_onmessage(None);
+            socket.set_onclose(None);
+            socket.close().unwrap_or(());
+        }
+        self.disconnected = true;
+    }
+}
+
+fn listen(socket: &WebSocket, received: &Rc<RefCell<Received>>) -> Callbacks {
+    socket.set_binary_type(BinaryType::Arraybuffer);
+    let opened = received.clone();
+    let on_open = Closure::<dyn FnMut()>::new(move || opened.borrow_mut().opened = true);
+    let inbox = received.clone();
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+        if let Ok(buffer) = event.data().dyn_into::<ArrayBuffer>() {
+            inbox
+                .borrow_mut()
+                .inbox
+                .push_back(Uint8Array::new(&buffer).to_vec());
+        }
+    });
+    let closed = received.clone();
+    let on_close = Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+        let mut closed = closed.borrow_mut();
+        // browsers do not tell why a connection failed, errors are followed by a close
+        let reason = match event.reason() {
+            reason if !reason.is_empty() => reason,
+            _ if closed.opened => "connection closed by host".to_string(),
+            _ => format!("connection failed with code {}", event.code()),
+        };
+        closed.closed.get_or_insert(reason);
+    });
+    socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+    socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    Callbacks {
+        _on_open: on_open,
+        _on_message: on_message,
+        _on_close: on_close,
+    }
+}
+
+impl SyncTransport for WebSocketClient {
+    fn update(&mut self, _: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.disconnected {
+            return Ok(());
+        }
+        let (opened, closed) = {
+            let mut received = self.received.borrow_mut();
+            (received.opened, received.closed.take())
+        };
+        let Some(reason) = closed else {
+            return Ok(());
+        };
+        self.close();
+        self.reason = Some(reason.clone());
+        // as natively, only a connection that never opened is an error
+        if opened {
+            Ok(())
+        } else {
+            Err(reason.into())
+        }
+    }
+
+    fn send_packets(&mut self) {
+        if !self.is_connected() {
+            return;
+        }
+        let Some((socket, _)) = &self.socket else {
+            return;
+        };
+        for message in self.outbox.drain(..) {
+            // failures surface as a close on a later update
+            socket.send_with_u8_array(&message).unwrap_or(());
+        }
+    }
+
+    fn peers(&self) -> Vec<ClientId> {
+        if self.is_connected() {
+            vec![HOST_PEER]
+        } else {
+            vec![]
+        }
+    }
+
+    fn send(&mut self, _: ClientId, message: Vec<u8>) {
+        if !self.disconnected {
+            self.outbox.push_back(message);
+        }
+    }
+
+    fn receive(&mut self, _: ClientId) -> Option<Vec<u8>> {
+        self.received.borrow_mut().inbox.pop_front()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.socket
+            .as_ref()
+            .is_some_and(|(socket, _)| socket.ready_state() == WebSocket::OPEN)
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    fn disconnect(&mut self, _: ClientId) {
+        self.close();
+    }
+
+    fn disconnect_all(&mut self) {
+        self.disconnect(HOST_PEER);
+    }
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        None
+    }
+
+    fn disconnect_reason(&self) -> Option<String> {
+        self.reason.clone()
+    }
+}
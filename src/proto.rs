@@ -3,17 +3,12 @@ use bevy_renet::renet::ClientId;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::SyncConnectionParameters;
+use crate::{JoinCredentials, PeerIdentity, PeerNetworkInfo, SyncConnectionParameters};
 
 pub type EntityId = Uuid;
 pub type AssId = Uuid;
-
-#[derive(Debug)]
-pub(crate) enum SyncAssetType {
-    Mesh,
-    Image,
-    Audio,
-}
+/// Blake3 hash of the bytes an asset is transferred as.
+pub type AssetHash = [u8; 32];
 
 #[derive(Serialize, Deserialize, Debug)]
 #[repr(u8)]
@@ -33,28 +28,64 @@ pub(crate) enum Message {
         name: String,
         data: Vec<u8>,
     } = 5,
-    StandardMaterialUpdated {
+    /// Material of a type registered with sync_material, as its reflected data.
+    MaterialUpdated {
         id: AssId,
         material: Vec<u8>,
     } = 6,
-    MeshUpdated {
+    /// Asset of a type registered with sync_asset, asset_type being its type path.
+    AssetUpdated {
+        asset_type: String,
         id: Uuid,
         url: String,
+        hash: AssetHash,
+        /// AssetPath the asset was loaded from when synched by path, receivers load it from
+        /// their own files and download it from url only if that fails.
+        path: Option<String>,
     } = 7,
-    ImageUpdated {
-        id: Uuid,
-        url: String,
-    } = 8,
-    AudioUpdated {
-        id: Uuid,
-        url: String,
-    } = 9,
     PromoteToHost = 10,
     NewHost {
         params: SyncConnectionParameters,
     } = 11,
     RequestInitialSync = 12,
     FinishedInitialSync = 13,
+    Join {
+        credentials: JoinCredentials,
+        identity: PeerIdentity,
+    } = 14,
+    JoinRejected {
+        reason: String,
+    } = 15,
+    PeerList {
+        peers: Vec<(u64, PeerIdentity)>,
+    } = 16,
+    PeerJoined {
+        client_id: u64,
+        identity: PeerIdentity,
+    } = 17,
+    PeerLeft {
+        client_id: u64,
+        reason: String,
+    } = 18,
+    PeerNetworkInfo {
+        peers: Vec<(u64, PeerNetworkInfo)>,
+    } = 19,
+    AssetRequest {
+        asset_type: String,
+        id: Uuid,
+    } = 20,
+    AssetChunk {
+        asset_type: String,
+        id: Uuid,
+        offset: u64,
+        total: u64,
+        bytes: Vec<u8>,
+    } = 21,
+    /// Asset or material removed, asset_type being its type path.
+    AssetRemoved {
+        asset_type: String,
+        id: Uuid,
+    } = 22,
 }
 
 #[derive(Event)]
@@ -0,0 +1,347 @@
+use std::any::TypeId;
+
+use bevy::{
+    ecs::component::Tick,
+    prelude::*,
+    reflect::TypeRegistry,
+    render::mesh::skinning::SkinnedMesh,
+    scene::{DynamicEntity, InstanceId, SceneInstance},
+    utils::HashSet,
+};
+use uuid::Uuid;
+
+use crate::{
+    binreflect::{reflect_to_bin, try_bin_to_reflect},
+    lib_priv::{ComponentChangeId, SkinnedMeshSyncMapper, SyncTrackerRes},
+    networking::assets::{AssetCodec, CodecError},
+    SyncComponent, SyncEntity,
+};
+
+/// Instancing of scenes under synched entities, enabled with sync_scenes.
+/// The Handle<Scene> of a synched entity is sent like other handles, the scene asset being
+/// transferred once and instanced by every peer, the spawned entities being synched with uuids
+/// derived from the one of the scene root, so that they match on every peer without being sent.
+pub(crate) struct SceneSyncPlugin;
+
+impl Plugin for SceneSyncPlugin {
+    fn build(&self, app: &mut App) {
+        app.sync_component::<SyncedScene>();
+        app.add_systems(
+            PreUpdate,
+            track_scene_nodes
+                .run_if(scenes_synced)
+                .run_if(resource_exists::<SceneSpawner>),
+        );
+        app.add_systems(
+            Update,
+            (share_scenes, instance_synced_scenes).distributive_run_if(scenes_synced),
+        );
+    }
+}
+
+/// Scene instanced under a synched entity, set from its Handle<Scene>.
+#[derive(Component, Reflect, Clone, Debug, Default, PartialEq)]
+#[reflect(Component, Default)]
+pub(crate) struct SyncedScene {
+    scene: Handle<Scene>,
+}
+
+/// Entity spawned by a synched scene, its components are sent only once changed after
+/// tracked_at since every peer spawns the same ones.
+#[derive(Component)]
+pub(crate) struct SceneNode {
+    pub(crate) tracked_at: Tick,
+}
+
+/// Scene instance whose entities were given their uuid.
+#[derive(Component)]
+struct TrackedSceneInstance(InstanceId);
+
+fn scenes_synced(tracker: Res<SyncTrackerRes>) -> bool {
+    tracker.sync_scenes
+}
+
+/// Scenes are sent as the reflected components of their entities, their handles mapped to the
+/// network uuids of the assets, which are sent when their type is synched.
+pub(crate) fn scene_codec(registry: AppTypeRegistry) -> AssetCodec<Scene> {
+    let received_registry = registry.clone();
+    AssetCodec::with_tracker(
+        move |scene, track| scene_to_network(scene, track, &registry.read()),
+        move |bytes, track, _| scene_from_network(bytes, track, &received_registry),
+    )
+}
+
+/// Bits of each scene entity, with its components as reflected data.
+type NetworkScene = Vec<(u64, Vec<Vec<u8>>)>;
+
+fn scene_to_network(
+    scene: &Scene,
+    track: &mut SyncTrackerRes,
+    registry: &TypeRegistry,
+) -> Result<Vec<u8>, CodecError> {
+    let world = &scene.world;
+    let mut entities = NetworkScene::new();
+    for entity in world.iter_entities() {
+        let mut components = Vec::new();
+        for c_id in entity.archetype().components() {
+            let Some(component) = world
+                .components()
+                .get_info(c_id)
+                .and_then(|info| info.type_id())
+                .and_then(|type_id| registry.get_type_data::<ReflectComponent>(type_id))
+                .and_then(|reflect_component| reflect_component.reflect(entity))
+            else {
+                continue;
+            };
+            let mut component = component.clone_value();
+            track.handles_to_network(component.as_reflect_mut(), registry);
+            match reflect_to_bin(component.as_reflect(), registry) {
+                Ok(bin) => components.push(bin),
+                Err(e) => debug!(
+                    "Could not send scene component {}: {}",
+                    component.reflect_type_path(),
+                    e
+                ),
+            }
+        }
+        entities.push((entity.id().to_bits(), components));
+    }
+    Ok(bincode::serialize(&entities)?)
+}
+
+fn scene_from_network(
+    bytes: &[u8],
+    track: &SyncTrackerRes,
+    registry: &AppTypeRegistry,
+) -> Result<Scene, CodecError> {
+    let entities: NetworkScene = bincode::deserialize(bytes)?;
+    let mut scene = DynamicScene::default();
+    {
+        let registry = registry.read();
+        for (entity, components) in entities {
+            let components = components
+                .iter()
+                .map(|bin| {
+                    let mut component = try_bin_to_reflect(bin, &registry)?;
+                    track.handles_from_network(component.as_reflect_mut(), &registry);
+                    Ok(component)
+                })
+                .collect::<Result<_, CodecError>>()?;
+            scene.entities.push(DynamicEntity {
+                entity: Entity::try_from_bits(entity)?,
+                components,
+            });
+        }
+    }
+    // the entities referenced by the components, like Parent, are mapped to the new ones
+    Ok(Scene::from_dynamic_scene(&scene, registry)?)
+}
+
+#[allow(clippy::type_complexity)]
+fn share_scenes(
+    mut cmd: Commands,
+    query: Query<
+        (Entity, &Handle<Scene>, Option<&SyncedScene>),
+        (
+            With<SyncEntity>,
+            Or<(Changed<Handle<Scene>>, Added<SyncEntity>)>,
+        ),
+    >,
+) {
+    for (e_id, handle, synced) in query.iter() {
+        if synced.is_some_and(|synced| synced.scene.id() == handle.id()) {
+            continue;
+        }
+        cmd.entity(e_id).insert(SyncedScene {
+            scene: handle.clone_weak(),
+        });
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn instance_synced_scenes(
+    mut cmd: Commands,
+    query: Query<(Entity, &SyncedScene, Option<&Handle<Scene>>), Changed<SyncedScene>>,
+) {
+    for (e_id, synced, handle) in query.iter() {
+        if handle.is_some_and(|handle| handle.id() == synced.scene.id()) {
+            continue;
+        }
+        // the scene is spawned once its asset is received
+        debug!("Instancing scene {:?} under {:?}", synced.scene.id(), e_id);
+        cmd.entity(e_id).insert(synced.scene.clone());
+        cmd.add(move |world: &mut World| {
+            // the rest of SceneBundle, unless synched already
+            let Some(mut entity) = world.get_entity_mut(e_id) else {
+                return;
+            };
+            if !entity.contains::<Transform>() {
+                entity.insert(TransformBundle::default());
+            }
+            if !entity.contains::<Visibility>() {
+                entity.insert(VisibilityBundle::default());
+            }
+        });
+    }
+}
+
+/// Gives the entities of the ready scene instances their uuid, derived from the uuid of the
+/// scene root and the position of the entity in the scene hierarchy.
+fn track_scene_nodes(world: &mut World) {
+    let roots: Vec<(Entity, Uuid, InstanceId)> = world
+        .query::<(
+            Entity,
+            &SyncEntity,
+            &SceneInstance,
+            Option<&TrackedSceneInstance>,
+        )>()
+        .iter(world)
+        .filter(|(_, _, instance, tracked)| {
+            tracked.map_or(true, |tracked| tracked.0 != ***instance)
+        })
+        .map(|(e_id, sup, instance, _)| (e_id, sup.uuid, **instance))
+        .collect();
+    for (root, root_uuid, instance) in roots {
+        let scene_spawner = world.resource::<SceneSpawner>();
+        if !scene_spawner.instance_is_ready(instance) {
+            continue;
+        }
+        let spawned: HashSet<Entity> = scene_spawner.iter_instance_entities(instance).collect();
+        let mut nodes = Vec::new();
+        collect_scene_nodes(world, root, &spawned, root_uuid, "", &mut nodes);
+        for (e_id, uuid) in nodes {
+            track_scene_node(world, e_id, uuid);
+        }
+        world
+            .entity_mut(root)
+            .insert(TrackedSceneInstance(instance));
+    }
+}
+
+fn collect_scene_nodes(
+    world: &World,
+    parent: Entity,
+    spawned: &HashSet<Entity>,
+    root_uuid: Uuid,
+    parent_key: &str,
+    nodes: &mut Vec<(Entity, Uuid)>,
+) {
+    let Some(children) = world.get::<Children>(parent) else {
+        return;
+    };
+    // children spawned by the scene keep the order of the scene on every peer
+    for (i, &child) in children
+        .iter()
+        .filter(|child| spawned.contains(*child))
+        .enumerate()
+    {
+        let key = format!("{}/{}", parent_key, i);
+        nodes.push((child, Uuid::new_v5(&root_uuid, key.as_bytes())));
+        collect_scene_nodes(world, child, spawned, root_uuid, &key, nodes);
+    }
+}
+
+fn track_scene_node(world: &mut World, e_id: Entity, uuid: Uuid) {
+    let placeholder = world
+        .resource::<SyncTrackerRes>()
+        .uuid_to_entity
+        .get(&uuid)
+        .copied()
+        .filter(|&placeholder| placeholder != e_id);
+    if let Some(placeholder) = placeholder {
+        merge_placeholder(world, placeholder, e_id);
+    }
+
+    // only the components spawned since the last run are seen as changed by the sync, the
+    // scene spawned them on every peer so they are not sent
+    let (last_run, this_run) = (world.last_change_tick(), world.read_change_tick());
+    let entity = world.entity(e_id);
+    let spawned_components: Vec<String> = {
+        let track = world.resource::<SyncTrackerRes>();
+        entity
+            .archetype()
+            .components()
+            .filter(|c_id| track.registered_componets_for_sync.contains(c_id))
+            .filter(|&c_id| {
+                entity
+                    .get_change_ticks_by_id(c_id)
+                    .is_some_and(|ticks| ticks.is_changed(last_run, this_run))
+            })
+            .filter_map(|c_id| world.components().get_info(c_id)?.type_id())
+            .filter_map(|type_id| synched_type_path(world, type_id))
+            .collect()
+    };
+
+    let mut track = world.resource_mut::<SyncTrackerRes>();
+    for name in spawned_components {
+        track
+            .pushed_component_from_network
+            .insert(ComponentChangeId { id: uuid, name });
+    }
+    track.uuid_to_entity.insert(uuid, e_id);
+    track.entity_to_uuid.insert(e_id, uuid);
+    world.entity_mut(e_id).insert((
+        SyncEntity { uuid },
+        SceneNode {
+            tracked_at: this_run,
+        },
+    ));
+}
+
+/// Type path of the component as sent over the network.
+fn synched_type_path(world: &World, type_id: TypeId) -> Option<String> {
+    if type_id == TypeId::of::<SkinnedMesh>() {
+        return Some(SkinnedMeshSyncMapper::type_path().to_string());
+    }
+    let registry = world.resource::<AppTypeRegistry>().read();
+    Some(registry.get(type_id)?.type_info().type_path().to_string())
+}
+
+/// Moves the components received for a scene entity before the scene was instanced to the
+/// spawned entity, then despawns the entity that held them.
+fn merge_placeholder(world: &mut World, placeholder: Entity, e_id: Entity) {
+    debug!("Merging {:?} into scene entity {:?}", placeholder, e_id);
+    let registry = world.resource::<AppTypeRegistry>().clone();
+    let registry = registry.read();
+    let received: Vec<Box<dyn Reflect>> = {
+        let track = world.resource::<SyncTrackerRes>();
+        let entity = world.entity(placeholder);
+        entity
+            .archetype()
+            .components()
+            .filter(|c_id| track.registered_componets_for_sync.contains(c_id))
+            .filter_map(|c_id| world.components().get_info(c_id)?.type_id())
+            // the joints of the spawned skinned meshes are the scene ones
+            .filter(|&type_id| type_id != TypeId::of::<SkinnedMesh>())
+            .filter_map(|type_id| registry.get_type_data::<ReflectComponent>(type_id))
+            .filter_map(|reflect_component| reflect_component.reflect(entity))
+            .map(|component| component.clone_value())
+            .collect()
+    };
+    for component in received {
+        let Some(reflect_component) = component
+            .get_represented_type_info()
+            .and_then(|info| registry.get_type_data::<ReflectComponent>(info.type_id()))
+        else {
+            continue;
+        };
+        reflect_component.apply_or_insert(
+            &mut world.entity_mut(e_id),
+            component.as_reflect(),
+            &registry,
+        );
+    }
+    let children: Vec<Entity> = world
+        .get::<Children>(placeholder)
+        .map(|children| children.to_vec())
+        .unwrap_or_default();
+    for child in children {
+        world.entity_mut(child).set_parent(e_id);
+    }
+    world.entity_mut(placeholder).remove_parent();
+    world.despawn(placeholder);
+    world
+        .resource_mut::<SyncTrackerRes>()
+        .entity_to_uuid
+        .remove(&placeholder);
+}
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
-use bevy_renet::renet::{ClientId, DefaultChannel, RenetServer};
+use bevy_renet::renet::ClientId;
 
-use crate::{full_sync::build_full_sync, proto::Message};
+use crate::{full_sync::build_full_sync, networking::transport::ServerTransport, proto::Message};
 
 pub(crate) fn send_initial_sync(client_id: ClientId, world: &mut World) {
     info!("Sending initial sync to client id {}", client_id);
@@ -16,18 +16,18 @@ pub(crate) fn send_initial_sync(client_id: ClientId, world: &mut World) {
             return;
         }
     };
-    let mut server = world.resource_mut::<RenetServer>();
+    let mut server = world.resource_mut::<ServerTransport>();
     debug!("Initial sync size: {}", initial_sync.len());
     for msg in initial_sync.drain(..) {
         let Ok(msg_bin) = bincode::serialize(&msg) else {
             warn!("Could not deserialize {:?}", msg);
             continue;
         };
-        server.send_message(client_id, DefaultChannel::ReliableOrdered, msg_bin);
+        server.send(client_id, msg_bin);
     }
     let Ok(msg_bin) = bincode::serialize(&Message::FinishedInitialSync) else {
         warn!("Could not deserialize FinishedInitialSync");
         return;
     };
-    server.send_message(client_id, DefaultChannel::ReliableOrdered, msg_bin);
+    server.send(client_id, msg_bin);
 }
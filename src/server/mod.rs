@@ -1,40 +1,88 @@
-use bevy::prelude::*;
-use bevy_renet::renet::{
-    transport::{NetcodeClientTransport, NetcodeServerTransport},
-    DefaultChannel, RenetClient, RenetServer, ServerEvent,
+use std::time::Duration;
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
 };
+use bevy_renet::renet::ClientId;
 
 use crate::{
-    lib_priv::{sync_audio_enabled, sync_material_enabled, sync_mesh_enabled, SyncTrackerRes}, proto::{Message, PromoteToHostEvent}, server::initial_sync::send_initial_sync, InitialSyncFinished, ServerState, SyncConnectionParameters
+    lib_priv::{sync_asset_enabled, sync_material_enabled, SyncTrackerRes},
+    networking::transport::{ClientTransport, ServerTransport, TransportEvent},
+    proto::{Message, PromoteToHostEvent},
+    server::initial_sync::send_initial_sync,
+    InitialSyncFinished, JoinApproval, PeerLeft, ServerState, SyncConnectionParameters,
+    SyncNetworkConfig, SyncPeers,
 };
 
 use self::track::{
     entity_created_on_server, entity_parented_on_server, entity_removed_from_server,
-    react_on_changed_audios, react_on_changed_components, react_on_changed_images,
-    react_on_changed_materials, react_on_changed_meshes,
+    react_on_changed_assets, react_on_changed_components, react_on_changed_materials,
 };
 
 mod initial_sync;
 mod receiver;
 mod track;
 
+/// Time left to rejected clients to receive the reason before being disconnected.
+const REJECTED_DISCONNECT_DELAY: Duration = Duration::from_secs(1);
+/// How often clients are told about the connection quality of the peers.
+const PEER_NETWORK_INFO_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Clients that passed the join approval, rejected ones waiting to be disconnected, connected
+/// ones that did not ask to join yet, and the ones promoted to host.
+#[derive(Resource, Default)]
+pub(crate) struct JoinedPeers {
+    approved: HashSet<ClientId>,
+    rejected: HashMap<ClientId, Duration>,
+    not_joined: HashMap<ClientId, Duration>,
+    /// Only these may announce themselves as the new host.
+    promoted: HashSet<ClientId>,
+    join_timeout: Duration,
+    approval_required: bool,
+    network_info_sent_at: Duration,
+}
+
+impl JoinedPeers {
+    /// Whether the client is sent the session. With a JoinApproval, clients that are not
+    /// approved yet or were rejected are sent nothing, the initial sync brings the session to
+    /// them once approved.
+    fn shares_session(&self, client_id: &ClientId) -> bool {
+        !self.approval_required || self.approved.contains(client_id)
+    }
+}
+
+/// Sends the message to the clients sharing the session, except the given one.
+pub(crate) fn broadcast_to_joined(
+    server: &mut ServerTransport,
+    joined: &JoinedPeers,
+    except: Option<ClientId>,
+    msg: &Message,
+) {
+    let bin = bincode::serialize(msg).unwrap();
+    for client_id in server.peers() {
+        if joined.shares_session(&client_id) && Some(client_id) != except {
+            server.send(client_id, bin.clone());
+        }
+    }
+}
+
 pub(crate) struct ServerSyncPlugin;
 
 impl Plugin for ServerSyncPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<JoinedPeers>();
         app.add_systems(
             Update,
             server_connected
-                .run_if(resource_exists::<RenetServer>)
-                .run_if(in_state(ServerState::Disconnected))
-                .run_if(resource_added::<NetcodeServerTransport>),
+                .run_if(server_not_connected)
+                .run_if(resource_exists::<ServerTransport>),
         );
         app.add_systems(
             Update,
             server_disconnected
-                .run_if(resource_exists::<RenetServer>)
                 .run_if(in_state(ServerState::Connected))
-                .run_if(resource_removed::<NetcodeServerTransport>()),
+                .run_if(resource_removed::<ServerTransport>()),
         );
 
         app.add_systems(
@@ -44,52 +92,101 @@ impl Plugin for ServerSyncPlugin {
                 entity_created_on_server,
                 entity_parented_on_server,
                 react_on_changed_components,
-                react_on_changed_materials.run_if(sync_material_enabled),
-                react_on_changed_images.run_if(sync_material_enabled),
-                react_on_changed_meshes.run_if(sync_mesh_enabled),
-                react_on_changed_audios.run_if(sync_audio_enabled),
                 promote_to_host_event_reader,
             )
                 .chain()
-                .run_if(resource_exists::<RenetServer>)
-                .run_if(resource_exists::<NetcodeServerTransport>)
+                .distributive_run_if(resource_exists::<ServerTransport>)
                 .run_if(in_state(ServerState::Connected)),
         );
         app.add_systems(
             Update,
-            (client_connected, receiver::poll_for_messages)
+            (
+                client_connected,
+                receiver::poll_for_messages,
+                disconnect_rejected_peers,
+                disconnect_peers_not_joined,
+                share_peer_network_info,
+            )
                 .chain()
-                .run_if(resource_exists::<RenetServer>)
-                .run_if(resource_exists::<NetcodeServerTransport>)
+                .distributive_run_if(resource_exists::<ServerTransport>)
                 .run_if(in_state(ServerState::Connected)),
         );
         app.add_systems(
             OnEnter(ServerState::Connected),
-            server_promoted_is_ready.run_if(resource_exists::<NetcodeClientTransport>),
+            server_promoted_is_ready.run_if(resource_exists::<ClientTransport>),
         );
     }
 }
 
+/// Sends the changes to the materials of type M once they are synched with sync_material.
+pub(crate) fn track_material<M: Material + Reflect>(app: &mut App) {
+    app.add_systems(
+        Update,
+        react_on_changed_materials::<M>
+            .run_if(sync_material_enabled::<M>)
+            .run_if(resource_exists::<ServerTransport>)
+            .run_if(in_state(ServerState::Connected)),
+    );
+}
+
+/// Sends the changes to the assets of type A once they are synched with sync_asset.
+pub(crate) fn track_asset<A: Asset>(app: &mut App) {
+    app.add_systems(
+        Update,
+        react_on_changed_assets::<A>
+            .run_if(sync_asset_enabled::<A>)
+            .run_if(resource_exists::<ServerTransport>)
+            .run_if(in_state(ServerState::Connected)),
+    );
+}
+
 fn client_connected(
     mut cmd: Commands,
-    mut server: ResMut<RenetServer>,
-    mut server_events: EventReader<ServerEvent>,
+    mut server: ResMut<ServerTransport>,
     mut tracker: ResMut<SyncTrackerRes>,
+    mut joined: ResMut<JoinedPeers>,
+    mut sync_peers: ResMut<SyncPeers>,
+    mut peer_left: EventWriter<PeerLeft>,
+    time: Res<Time>,
 ) {
-    for event in server_events.read() {
+    while let Some(event) = server.poll_event() {
         match event {
-            ServerEvent::ClientConnected { client_id } => {
-                let client_id = *client_id;
+            TransportEvent::PeerConnected { peer: client_id } => {
                 info!("Client connected with client id: {}", client_id);
+                joined.not_joined.insert(client_id, time.elapsed());
                 if tracker.host_promotion_in_progress {
                     info!("Promotion: first connection to a promoted host, removing previous client instance.");
                     // remove any previous pending client since the instance is a server now
                     // this clients can be pending after a host promotion
-                    cmd.remove_resource::<NetcodeClientTransport>();
+                    cmd.remove_resource::<ClientTransport>();
                     tracker.host_promotion_in_progress = false;
                 }
             }
-            ServerEvent::ClientDisconnected { client_id, reason } => {
+            TransportEvent::PeerDisconnected {
+                peer: client_id,
+                reason,
+            } => {
+                joined.rejected.remove(&client_id);
+                joined.not_joined.remove(&client_id);
+                joined.promoted.remove(&client_id);
+                if joined.approved.remove(&client_id) {
+                    if let Some(peer) = sync_peers.peers.remove(&client_id) {
+                        broadcast_to_joined(
+                            &mut server,
+                            &joined,
+                            None,
+                            &Message::PeerLeft {
+                                client_id: client_id.raw(),
+                                reason: reason.clone(),
+                            },
+                        );
+                        peer_left.send(PeerLeft {
+                            client_id,
+                            identity: peer.identity,
+                            reason: reason.clone(),
+                        });
+                    }
+                }
                 if tracker.host_promotion_in_progress {
                     info!(
                         "Promotion: Client flushed after host promotion with client id: {}, reason: {}",
@@ -104,10 +201,10 @@ fn client_connected(
 
                 // After all clients finished disconnecting, reset the state as
                 // if promotion never happened
-                if server.connected_clients() == 0 && tracker.host_promotion_in_progress {
+                if server.peers().is_empty() && tracker.host_promotion_in_progress {
                     info!("Promotion: Last client disconnected after a promotion to client, closing server.");
                     server.disconnect_all();
-                    cmd.remove_resource::<NetcodeServerTransport>();
+                    cmd.remove_resource::<ServerTransport>();
                     tracker.host_promotion_in_progress = false;
                 }
             }
@@ -115,39 +212,125 @@ fn client_connected(
     }
 }
 
+fn disconnect_rejected_peers(
+    mut server: ResMut<ServerTransport>,
+    mut joined: ResMut<JoinedPeers>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed();
+    joined.rejected.retain(|client_id, rejected_at| {
+        if now - *rejected_at < REJECTED_DISCONNECT_DELAY {
+            return true;
+        }
+        info!("Disconnecting rejected client id: {}", client_id);
+        server.disconnect(*client_id);
+        false
+    });
+}
+
+fn disconnect_peers_not_joined(
+    mut server: ResMut<ServerTransport>,
+    mut joined: ResMut<JoinedPeers>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed();
+    let join_timeout = joined.join_timeout;
+    joined.not_joined.retain(|client_id, connected_at| {
+        if now - *connected_at < join_timeout {
+            return true;
+        }
+        info!(
+            "Disconnecting client id {} which did not join in time",
+            client_id
+        );
+        server.disconnect(*client_id);
+        false
+    });
+}
+
+fn share_peer_network_info(
+    mut server: ResMut<ServerTransport>,
+    mut joined: ResMut<JoinedPeers>,
+    mut sync_peers: ResMut<SyncPeers>,
+    time: Res<Time>,
+) {
+    for (client_id, peer) in sync_peers.peers.iter_mut() {
+        peer.network = server.network_info(*client_id).unwrap_or_default();
+    }
+    if time.elapsed() - joined.network_info_sent_at < PEER_NETWORK_INFO_INTERVAL {
+        return;
+    }
+    joined.network_info_sent_at = time.elapsed();
+    let peers = sync_peers
+        .peers
+        .iter()
+        .map(|(client_id, peer)| (client_id.raw(), peer.network))
+        .collect();
+    broadcast_to_joined(
+        &mut server,
+        &joined,
+        None,
+        &Message::PeerNetworkInfo { peers },
+    );
+}
+
+fn server_not_connected(state: Res<State<ServerState>>) -> bool {
+    matches!(
+        state.get(),
+        ServerState::Disconnected | ServerState::Failed(_)
+    )
+}
+
 fn server_disconnected(mut state: ResMut<NextState<ServerState>>) {
     info!("Server is shut down.");
     state.set(ServerState::Disconnected);
 }
 
-fn server_connected(mut state: ResMut<NextState<ServerState>>, mut event: EventWriter<InitialSyncFinished>) {
+fn server_connected(
+    mut state: ResMut<NextState<ServerState>>,
+    mut event: EventWriter<InitialSyncFinished>,
+    mut joined: ResMut<JoinedPeers>,
+    mut sync_peers: ResMut<SyncPeers>,
+    config: Option<Res<SyncNetworkConfig>>,
+    approval: Option<Res<JoinApproval>>,
+) {
     info!("Server ready to accept connections.");
+    *joined = JoinedPeers {
+        join_timeout: config.map_or_else(
+            || SyncNetworkConfig::default().join_timeout,
+            |config| config.join_timeout,
+        ),
+        approval_required: approval.is_some(),
+        ..default()
+    };
+    sync_peers.peers.clear();
     state.set(ServerState::Connected);
     // Server is always 'ready' so it's finished from the start
     event.send(InitialSyncFinished);
 }
 
 fn server_promoted_is_ready(
-    mut client: ResMut<RenetClient>,
+    mut client: ResMut<ClientTransport>,
     connection_parameters: Res<SyncConnectionParameters>,
 ) {
     info!("Promotion: New server is ready, tell old server to shut down.");
     let message = bincode::serialize(&Message::NewHost {
-        params: connection_parameters.clone(),
+        params: connection_parameters.as_ref().clone(),
     })
     .unwrap();
-    client.send_message(DefaultChannel::ReliableOrdered, message);
+    client.broadcast(message);
 }
 
 fn promote_to_host_event_reader(
-    mut server: ResMut<RenetServer>,
+    mut server: ResMut<ServerTransport>,
+    mut joined: ResMut<JoinedPeers>,
     mut events: EventReader<PromoteToHostEvent>,
 ) {
     for event in events.read() {
         info!("Promoting {} to host", event.id);
-        server.send_message(
+        joined.promoted.insert(event.id);
+        server.send(
             event.id,
-            DefaultChannel::ReliableOrdered,
             bincode::serialize(&Message::PromoteToHost {}).unwrap(),
         );
     }
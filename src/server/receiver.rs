@@ -1,28 +1,89 @@
 use bevy_renet::renet::ClientId;
 
 use crate::{
+    lib_priv::AssetFallback,
     logging::{log_message_received, Who},
-    networking::{assets::SyncAssetTransfer, create_client},
-    proto::SyncAssetType,
-    SyncEntity,
+    networking::{
+        assets::{is_connection_url, SyncAssetTransfer},
+        client_startup_failed, create_client,
+        transport::HOST_PEER,
+        with_own_secrets,
+    },
+    PeerIdentity, PeerInfo, PeerJoined, PeerNetworkInfo, SyncConnectionParameters, SyncEntity,
+    SyncNetworkConfig, SyncPeers,
 };
 
 use super::*;
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn poll_for_messages(
     mut commands: Commands,
-    mut server: ResMut<RenetServer>,
+    mut server: ResMut<ServerTransport>,
     mut track: ResMut<SyncTrackerRes>,
     mut sync_assets: ResMut<SyncAssetTransfer>,
+    mut joined: ResMut<JoinedPeers>,
+    mut sync_peers: ResMut<SyncPeers>,
+    mut peer_joined: EventWriter<PeerJoined>,
+    approval: Option<Res<JoinApproval>>,
+    time: Res<Time>,
 ) {
-    for client_id in server.clients_id().into_iter() {
-        while let Some(message) = server.receive_message(client_id, DefaultChannel::ReliableOrdered)
-        {
-            let deser_message = bincode::deserialize(&message).unwrap();
+    joined.approval_required = approval.is_some();
+    for client_id in server.peers().into_iter() {
+        while let Some(message) = server.receive(client_id) {
+            let deser_message = match bincode::deserialize(&message) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!(
+                        "Disconnecting client id {} after a malformed message: {}",
+                        client_id, e
+                    );
+                    server.disconnect(client_id);
+                    break;
+                }
+            };
+            if let Message::Join {
+                credentials,
+                identity,
+            } = &deser_message
+            {
+                log_message_received(Who::Server, &deser_message);
+                if !joined.not_joined.contains_key(&client_id) {
+                    debug!("Ignoring Join from client id {} already handled", client_id);
+                    continue;
+                }
+                if let Err(reason) = unique_identity(identity, &sync_peers).and_then(|_| {
+                    approval
+                        .as_ref()
+                        .map_or(Ok(()), |approval| approval.approve(client_id, credentials))
+                }) {
+                    reject(client_id, reason, &mut joined, &mut server, &time);
+                    continue;
+                }
+                join(
+                    client_id,
+                    identity.clone(),
+                    &mut joined,
+                    &mut sync_peers,
+                    &mut server,
+                );
+                peer_joined.send(PeerJoined {
+                    client_id,
+                    identity: identity.clone(),
+                });
+                continue;
+            }
+            if !joined.shares_session(&client_id) {
+                debug!(
+                    "Ignoring message from client id {} not yet approved",
+                    client_id
+                );
+                continue;
+            }
             server_received_a_message(
                 client_id,
                 deser_message,
                 &mut server,
+                &joined,
                 &mut track,
                 &mut sync_assets,
                 &mut commands,
@@ -31,11 +92,87 @@ pub(crate) fn poll_for_messages(
     }
 }
 
+/// Two peers of a session cannot share the identity, which tells them apart across sessions.
+fn unique_identity(identity: &PeerIdentity, sync_peers: &SyncPeers) -> Result<(), String> {
+    let taken = sync_peers.local.id == identity.id
+        || sync_peers
+            .peers
+            .values()
+            .any(|peer| peer.identity.id == identity.id);
+    match taken {
+        true => Err(format!(
+            "identity {} ({}) is already in the session",
+            identity.name, identity.id
+        )),
+        false => Ok(()),
+    }
+}
+
+fn join(
+    client_id: ClientId,
+    identity: PeerIdentity,
+    joined: &mut JoinedPeers,
+    sync_peers: &mut SyncPeers,
+    server: &mut ServerTransport,
+) {
+    info!(
+        "Client id {} joined as {} ({})",
+        client_id, identity.name, identity.id
+    );
+    joined.not_joined.remove(&client_id);
+    joined.approved.insert(client_id);
+    sync_peers.peers.insert(
+        client_id,
+        PeerInfo {
+            identity: identity.clone(),
+            network: PeerNetworkInfo::default(),
+        },
+    );
+    // the host is not one of its own peers, clients know it as HOST_PEER
+    let peers = sync_peers
+        .peers
+        .iter()
+        .map(|(client_id, peer)| (client_id.raw(), peer.identity.clone()))
+        .chain([(HOST_PEER.raw(), sync_peers.local.clone())])
+        .collect();
+    server.send(
+        client_id,
+        bincode::serialize(&Message::PeerList { peers }).unwrap(),
+    );
+    broadcast_to_joined(
+        server,
+        joined,
+        Some(client_id),
+        &Message::PeerJoined {
+            client_id: client_id.raw(),
+            identity,
+        },
+    );
+}
+
+fn reject(
+    client_id: ClientId,
+    reason: String,
+    joined: &mut JoinedPeers,
+    server: &mut ServerTransport,
+    time: &Time,
+) {
+    info!("Client id {} rejected: {}", client_id, reason);
+    server.send(
+        client_id,
+        bincode::serialize(&Message::JoinRejected { reason }).unwrap(),
+    );
+    joined.not_joined.remove(&client_id);
+    joined.approved.remove(&client_id);
+    joined.rejected.insert(client_id, time.elapsed());
+}
+
 #[allow(clippy::too_many_arguments)]
 fn server_received_a_message(
     client_id: ClientId,
     msg: Message,
-    server: &mut ResMut<RenetServer>,
+    server: &mut ResMut<ServerTransport>,
+    joined: &JoinedPeers,
     track: &mut ResMut<SyncTrackerRes>,
     sync_assets: &mut ResMut<SyncAssetTransfer>,
     cmd: &mut Commands,
@@ -47,7 +184,12 @@ fn server_received_a_message(
             // Need to update the map right away or else adjacent messages won't see each other entity
             track.uuid_to_entity.insert(id, e_id);
             track.entity_to_uuid.insert(e_id, id);
-            repeat_except_for_client(client_id, server, &Message::EntitySpawn { id });
+            broadcast_to_joined(
+                server,
+                joined,
+                Some(client_id),
+                &Message::EntitySpawn { id },
+            );
         }
         Message::EntityParented {
             entity_id: me_id,
@@ -73,7 +215,7 @@ fn server_received_a_message(
                 }
                 repeat_except_for_client(
                     client_id,
-                    &mut world.resource_mut::<RenetServer>(),
+                    world,
                     &Message::EntityParented {
                         entity_id: me_id,
                         parent_id: mp_id,
@@ -90,7 +232,12 @@ fn server_received_a_message(
                     track.entity_to_uuid.remove(&id);
                 }
             }
-            repeat_except_for_client(client_id, server, &Message::EntityDelete { id: mid });
+            broadcast_to_joined(
+                server,
+                joined,
+                Some(client_id),
+                &Message::EntityDelete { id: mid },
+            );
         }
         Message::ComponentUpdated { id, name, data } => {
             let Some(&e_id) = track.uuid_to_entity.get(&id) else {
@@ -107,98 +254,144 @@ fn server_received_a_message(
                 if changed {
                     repeat_except_for_client(
                         client_id,
-                        &mut world.resource_mut::<RenetServer>(),
+                        world,
                         &Message::ComponentUpdated { id, name, data },
                     );
                 }
             });
         }
-        Message::StandardMaterialUpdated { id, material } => cmd.add(move |world: &mut World| {
+        Message::MaterialUpdated { id, material } => cmd.add(move |world: &mut World| {
             SyncTrackerRes::apply_material_change_from_network(id, &material, world);
 
-            repeat_except_for_client(
-                client_id,
-                &mut world.resource_mut::<RenetServer>(),
-                &Message::StandardMaterialUpdated { id, material },
-            );
+            repeat_except_for_client(client_id, world, &Message::MaterialUpdated { id, material });
         }),
-        Message::MeshUpdated { id, url } => {
-            sync_assets.request(SyncAssetType::Mesh, id, url.clone());
-            cmd.add(move |world: &mut World| {
-                repeat_except_for_client(
-                    client_id,
-                    &mut world.resource_mut::<RenetServer>(),
-                    &Message::MeshUpdated { id, url },
-                );
-            })
-        }
-        Message::ImageUpdated { id, url } => {
-            sync_assets.request(SyncAssetType::Image, id, url.clone());
+        Message::AssetUpdated {
+            asset_type,
+            id,
+            url,
+            hash,
+            path,
+        } => {
+            let synced = track.is_asset_synced(&asset_type);
+            if !synced && is_connection_url(&url) {
+                // the other clients would request it from this host, which never has it
+                debug!("Not relaying {} {} of a type not synched", asset_type, id);
+                return;
+            }
+            // assets sent over the connection are downloaded to be relayed to the other clients
+            let local_path = path.clone().filter(|_| synced && !is_connection_url(&url));
+            if synced && local_path.is_none() {
+                sync_assets.request(&asset_type, id, url.clone(), hash, client_id);
+            }
             cmd.add(move |world: &mut World| {
+                if let Some(local_path) = local_path {
+                    let fallback = AssetFallback {
+                        asset_type: asset_type.clone(),
+                        url: url.clone(),
+                        hash,
+                        peer: client_id,
+                    };
+                    SyncTrackerRes::apply_asset_path_from_network(world, id, local_path, fallback);
+                }
                 repeat_except_for_client(
                     client_id,
-                    &mut world.resource_mut::<RenetServer>(),
-                    &Message::ImageUpdated { id, url },
+                    world,
+                    &Message::AssetUpdated {
+                        asset_type,
+                        id,
+                        url,
+                        hash,
+                        path,
+                    },
                 );
             })
         }
-        Message::AudioUpdated { id, url } => {
-            sync_assets.request(SyncAssetType::Audio, id, url.clone());
-            cmd.add(move |world: &mut World| {
-                repeat_except_for_client(
-                    client_id,
-                    &mut world.resource_mut::<RenetServer>(),
-                    &Message::AudioUpdated { id, url },
-                );
-            })
+        Message::AssetRemoved { asset_type, id } => cmd.add(move |world: &mut World| {
+            SyncTrackerRes::apply_asset_removal_from_network(world, &asset_type, id);
+
+            repeat_except_for_client(client_id, world, &Message::AssetRemoved { asset_type, id });
+        }),
+        Message::AssetRequest { asset_type, id } => {
+            sync_assets.respond_over_connection(client_id, &asset_type, id)
         }
+        Message::AssetChunk {
+            asset_type,
+            id,
+            offset,
+            total,
+            bytes,
+        } => sync_assets.receive_chunk(&asset_type, id, offset, total, bytes),
         // server is already host, no operation to do
         Message::PromoteToHost => (),
+        Message::NewHost { .. } if !joined.promoted.contains(&client_id) => {
+            warn!(
+                "Ignoring NewHost from client id {} that was not promoted",
+                client_id
+            );
+        }
         Message::NewHost { params } => {
-            match params {
-                SyncConnectionParameters::Socket {
-                    ip,
-                    port,
-                    web_port: _,
-                    max_transfer: _,
-                } => {
-                    info!("Promotion: A new host has been promoted. Relaying the info to all parties.");
-                    // This client has already became server, so remove it from the pool
-                    server.disconnect(client_id);
-                    // Tell all other clients who is the new host
-                    repeat_except_for_client(client_id, server, &Message::NewHost { params });
-                    info!("Promotion: A new host has been promoted. Reconnecting to new host");
-                    cmd.add(move |world: &mut World| {
-                        info!("Promotion: Creating a new client connection to new host...");
-                        world
-                            .resource_mut::<SyncTrackerRes>()
-                            .host_promotion_in_progress = true;
-                        world.insert_resource(create_client(ip, port));
-                    });
+            info!("Promotion: A new host has been promoted. Relaying the info to all parties.");
+            // This client has already became server, so remove it from the pool
+            server.disconnect(client_id);
+            // Tell all other clients who is the new host
+            broadcast_to_joined(
+                server,
+                joined,
+                Some(client_id),
+                &Message::NewHost {
+                    params: params.clone(),
+                },
+            );
+            info!("Promotion: A new host has been promoted. Reconnecting to new host");
+            cmd.add(move |world: &mut World| {
+                info!("Promotion: Creating a new client connection to new host...");
+                world
+                    .resource_mut::<SyncTrackerRes>()
+                    .host_promotion_in_progress = true;
+                let params =
+                    with_own_secrets(&params, world.resource::<SyncConnectionParameters>());
+                // the new host will send its own list of peers once joined
+                let mut peers = world.resource_mut::<SyncPeers>();
+                peers.peers.clear();
+                let identity = peers.local.clone();
+                let config = world.resource::<SyncNetworkConfig>().clone();
+                match create_client(&params, &identity, &config) {
+                    Ok(client) => world.insert_resource(client),
+                    Err(e) => client_startup_failed(world, e.to_string()),
                 }
-            }
+            });
         }
         Message::RequestInitialSync => {
             debug!("Sending initial sync to client id: {}", client_id);
             cmd.add(move |world: &mut World| send_initial_sync(client_id, world));
         }
         Message::FinishedInitialSync => (),
+        // handled before reaching here, or only sent by the server
+        Message::Join {
+            credentials: _,
+            identity: _,
+        }
+        | Message::JoinRejected { reason: _ }
+        | Message::PeerList { peers: _ }
+        | Message::PeerJoined {
+            client_id: _,
+            identity: _,
+        }
+        | Message::PeerLeft {
+            client_id: _,
+            reason: _,
+        }
+        | Message::PeerNetworkInfo { peers: _ } => (),
     }
 }
 
-fn repeat_except_for_client(
-    msg_client_id: bevy_renet::renet::ClientId,
-    server: &mut RenetServer,
-    msg: &Message,
-) {
-    for client_id in server.clients_id().into_iter() {
-        if client_id == msg_client_id {
-            continue;
-        }
-        server.send_message(
-            client_id,
-            DefaultChannel::ReliableOrdered,
-            bincode::serialize(msg).unwrap(),
+fn repeat_except_for_client(msg_client_id: ClientId, world: &mut World, msg: &Message) {
+    world.resource_scope(|world, mut server: Mut<ServerTransport>| {
+        broadcast_to_joined(
+            &mut server,
+            world.resource::<JoinedPeers>(),
+            Some(msg_client_id),
+            msg,
         );
-    }
+    });
 }
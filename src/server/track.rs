@@ -1,27 +1,31 @@
 use bevy::{prelude::*, utils::HashSet};
-use bevy_renet::renet::{DefaultChannel, RenetServer};
 use uuid::Uuid;
 
 use crate::{
-    binreflect::reflect_to_bin, lib_priv::SyncTrackerRes, networking::assets::SyncAssetTransfer,
-    proto::Message, SyncEntity, SyncMark,
+    binreflect::reflect_to_bin,
+    lib_priv::SyncTrackerRes,
+    networking::{assets::SyncAssetTransfer, transport::ServerTransport},
+    proto::Message,
+    SyncEntity, SyncMark,
 };
 
+use super::{broadcast_to_joined, JoinedPeers};
+
 pub(crate) fn entity_created_on_server(
     mut track: ResMut<SyncTrackerRes>,
     mut commands: Commands,
-    mut server: ResMut<RenetServer>,
+    mut server: ResMut<ServerTransport>,
+    joined: Res<JoinedPeers>,
     mut query: Query<Entity, Added<SyncMark>>,
 ) {
     for id in query.iter_mut() {
         let uuid = Uuid::new_v4();
-        for client_id in server.clients_id().into_iter() {
-            server.send_message(
-                client_id,
-                DefaultChannel::ReliableOrdered,
-                bincode::serialize(&Message::EntitySpawn { id: uuid }).unwrap(),
-            );
-        }
+        broadcast_to_joined(
+            &mut server,
+            &joined,
+            None,
+            &Message::EntitySpawn { id: uuid },
+        );
         track.uuid_to_entity.insert(uuid, id);
         track.entity_to_uuid.insert(id, uuid);
         commands
@@ -33,33 +37,33 @@ pub(crate) fn entity_created_on_server(
 }
 
 pub(crate) fn entity_parented_on_server(
-    mut server: ResMut<RenetServer>,
+    mut server: ResMut<ServerTransport>,
+    joined: Res<JoinedPeers>,
     track: ResMut<SyncTrackerRes>,
     query: Query<(Entity, &Parent), Changed<Parent>>,
 ) {
     for (e_id, p) in query.iter() {
-        for client_id in server.clients_id().into_iter() {
-            let Some(id) = track.entity_to_uuid.get(&e_id) else {
-                continue;
-            };
-            let Some(pid) = track.entity_to_uuid.get(&p.get()) else {
-                continue;
-            };
-            server.send_message(
-                client_id,
-                DefaultChannel::ReliableOrdered,
-                bincode::serialize(&Message::EntityParented {
-                    entity_id: *id,
-                    parent_id: *pid,
-                })
-                .unwrap(),
-            );
-        }
+        let Some(id) = track.entity_to_uuid.get(&e_id) else {
+            continue;
+        };
+        let Some(pid) = track.entity_to_uuid.get(&p.get()) else {
+            continue;
+        };
+        broadcast_to_joined(
+            &mut server,
+            &joined,
+            None,
+            &Message::EntityParented {
+                entity_id: *id,
+                parent_id: *pid,
+            },
+        );
     }
 }
 
 pub(crate) fn entity_removed_from_server(
-    mut server: ResMut<RenetServer>,
+    mut server: ResMut<ServerTransport>,
+    joined: Res<JoinedPeers>,
     mut track: ResMut<SyncTrackerRes>,
     query: Query<Entity, With<SyncEntity>>,
 ) {
@@ -74,23 +78,24 @@ pub(crate) fn entity_removed_from_server(
     });
     for uuid in despawned_entities.iter() {
         track.uuid_to_entity.remove(uuid);
-        for cid in server.clients_id().into_iter() {
-            server.send_message(
-                cid,
-                DefaultChannel::ReliableOrdered,
-                bincode::serialize(&Message::EntityDelete { id: *uuid }).unwrap(),
-            );
-        }
+        broadcast_to_joined(
+            &mut server,
+            &joined,
+            None,
+            &Message::EntityDelete { id: *uuid },
+        );
     }
 }
 
 pub(crate) fn react_on_changed_components(
     registry: Res<AppTypeRegistry>,
-    mut server: ResMut<RenetServer>,
+    mut server: ResMut<ServerTransport>,
+    joined: Res<JoinedPeers>,
     mut track: ResMut<SyncTrackerRes>,
 ) {
     let registry = registry.read();
-    while let Some(change) = track.changed_components_to_send.pop_front() {
+    while let Some(mut change) = track.changed_components_to_send.pop_front() {
+        track.handles_to_network(change.data.as_reflect_mut(), &registry);
         let bin = match reflect_to_bin(change.data.as_reflect(), &registry) {
             Ok(bin) => bin,
             Err(e) => {
@@ -106,167 +111,128 @@ pub(crate) fn react_on_changed_components(
             name: change.change_id.name.clone(),
             data: bin,
         };
-        for cid in server.clients_id().into_iter() {
-            server.send_message(
-                cid,
-                DefaultChannel::ReliableOrdered,
-                bincode::serialize(msg).unwrap(),
-            );
-        }
+        broadcast_to_joined(&mut server, &joined, None, msg);
     }
 }
 
-pub(crate) fn react_on_changed_materials(
+pub(crate) fn react_on_changed_materials<M: Material + Reflect>(
     mut track: ResMut<SyncTrackerRes>,
     registry: Res<AppTypeRegistry>,
-    mut server: ResMut<RenetServer>,
-    materials: Res<Assets<StandardMaterial>>,
-    mut events: EventReader<AssetEvent<StandardMaterial>>,
+    mut server: ResMut<ServerTransport>,
+    joined: Res<JoinedPeers>,
+    materials: Res<Assets<M>>,
+    mut events: EventReader<AssetEvent<M>>,
 ) {
     let registry = registry.read();
+    let mut changed = track.take_newly_mapped::<M>();
     for event in &mut events.read() {
         match event {
             AssetEvent::Added { id } | AssetEvent::Modified { id } => {
-                let Some(material) = materials.get(*id) else {
-                    continue;
-                };
-                let AssetId::Uuid { uuid: id } = id else {
+                let Some(uuid) = track.known_uuid(id.untyped()) else {
                     continue;
                 };
-                if track.skip_network_handle_change(*id) {
+                if track.skip_network_handle_change(uuid) {
                     continue;
                 }
-                let Ok(bin) = reflect_to_bin(material.as_reflect(), &registry) else {
-                    continue;
-                };
-                let msg = &Message::StandardMaterialUpdated {
-                    id: *id,
-                    material: bin,
-                };
-                for cid in server.clients_id().into_iter() {
-                    server.send_message(
-                        cid,
-                        DefaultChannel::ReliableOrdered,
-                        bincode::serialize(msg).unwrap(),
-                    );
+                if !changed.contains(&(*id, uuid)) {
+                    changed.push((*id, uuid));
                 }
             }
-            AssetEvent::Removed { id: _ } => {}
-            _ => (),
-        }
-    }
-}
-
-pub(crate) fn react_on_changed_audios(
-    mut track: ResMut<SyncTrackerRes>,
-    mut server: ResMut<RenetServer>,
-    assets: Res<Assets<AudioSource>>,
-    mut events: EventReader<AssetEvent<AudioSource>>,
-    mut sync_assets: ResMut<SyncAssetTransfer>,
-) {
-    for event in &mut events.read() {
-        match event {
-            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
-                let Some(asset) = assets.get(*id) else {
+            AssetEvent::Removed { id } => {
+                let Some(uuid) = track.forget_asset_id(id.untyped()) else {
                     continue;
                 };
-                let AssetId::Uuid { uuid: id } = id else {
+                if track.skip_network_handle_change(uuid) {
                     continue;
-                };
-                if track.skip_network_handle_change(*id) {
-                    continue;
-                }
-                let url = sync_assets.serve_audio(id, asset);
-                for cid in server.clients_id().into_iter() {
-                    server.send_message(
-                        cid,
-                        DefaultChannel::ReliableOrdered,
-                        bincode::serialize(&Message::AudioUpdated {
-                            id: *id,
-                            url: url.clone(),
-                        })
-                        .unwrap(),
-                    );
                 }
+                let msg = &Message::AssetRemoved {
+                    asset_type: M::type_path().to_string(),
+                    id: uuid,
+                };
+                broadcast_to_joined(&mut server, &joined, None, msg);
             }
-            AssetEvent::Removed { id: _ } => {}
             _ => (),
         }
     }
+    for (id, uuid) in changed {
+        let Some(material) = materials.get(id) else {
+            continue;
+        };
+        let mut material = material.clone();
+        track.handles_to_network(material.as_reflect_mut(), &registry);
+        let Ok(bin) = reflect_to_bin(material.as_reflect(), &registry) else {
+            continue;
+        };
+        let msg = &Message::MaterialUpdated {
+            id: uuid,
+            material: bin,
+        };
+        broadcast_to_joined(&mut server, &joined, None, msg);
+    }
 }
 
-pub(crate) fn react_on_changed_meshes(
+pub(crate) fn react_on_changed_assets<A: Asset>(
     mut track: ResMut<SyncTrackerRes>,
-    mut server: ResMut<RenetServer>,
-    assets: Res<Assets<Mesh>>,
-    mut events: EventReader<AssetEvent<Mesh>>,
+    mut server: ResMut<ServerTransport>,
+    joined: Res<JoinedPeers>,
+    assets: Res<Assets<A>>,
+    asset_server: Res<AssetServer>,
+    mut events: EventReader<AssetEvent<A>>,
     mut sync_assets: ResMut<SyncAssetTransfer>,
 ) {
+    let mut changed = track.take_newly_mapped::<A>();
+    let mut modified = HashSet::new();
     for event in &mut events.read() {
         match event {
             AssetEvent::Added { id } | AssetEvent::Modified { id } => {
-                let Some(mesh) = assets.get(*id) else {
-                    continue;
-                };
-                let AssetId::Uuid { uuid: id } = id else {
+                let Some(uuid) = track.known_uuid(id.untyped()) else {
                     continue;
                 };
-                if track.skip_network_handle_change(*id) {
+                if let AssetEvent::Modified { .. } = event {
+                    modified.insert(*id);
+                }
+                if track.skip_network_handle_change(uuid) {
                     continue;
                 }
-                let url = sync_assets.serve_mesh(id, mesh);
-                for cid in server.clients_id().into_iter() {
-                    server.send_message(
-                        cid,
-                        DefaultChannel::ReliableOrdered,
-                        bincode::serialize(&Message::MeshUpdated {
-                            id: *id,
-                            url: url.clone(),
-                        })
-                        .unwrap(),
-                    );
+                if !changed.contains(&(*id, uuid)) {
+                    changed.push((*id, uuid));
                 }
             }
-            AssetEvent::Removed { id: _ } => {}
-            _ => (),
-        }
-    }
-}
-
-pub(crate) fn react_on_changed_images(
-    mut track: ResMut<SyncTrackerRes>,
-    mut server: ResMut<RenetServer>,
-    assets: Res<Assets<Image>>,
-    mut events: EventReader<AssetEvent<Image>>,
-    mut sync_assets: ResMut<SyncAssetTransfer>,
-) {
-    for event in &mut events.read() {
-        match event {
-            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
-                let Some(image) = assets.get(*id) else {
-                    continue;
-                };
-                let AssetId::Uuid { uuid: id } = id else {
+            AssetEvent::Removed { id } => {
+                let Some(uuid) = track.forget_asset_id(id.untyped()) else {
                     continue;
                 };
-                if track.skip_network_handle_change(*id) {
+                if track.skip_network_handle_change(uuid) {
                     continue;
                 }
-                let url = sync_assets.serve_image(id, image);
-                for cid in server.clients_id().into_iter() {
-                    server.send_message(
-                        cid,
-                        DefaultChannel::ReliableOrdered,
-                        bincode::serialize(&Message::ImageUpdated {
-                            id: *id,
-                            url: url.clone(),
-                        })
-                        .unwrap(),
-                    );
-                }
+                sync_assets.forget(A::type_path(), &uuid);
+                let msg = &Message::AssetRemoved {
+                    asset_type: A::type_path().to_string(),
+                    id: uuid,
+                };
+                broadcast_to_joined(&mut server, &joined, None, msg);
             }
-            AssetEvent::Removed { id: _ } => {}
             _ => (),
         }
     }
+    for (id, uuid) in changed {
+        let Some(asset) = assets.get(id) else {
+            continue;
+        };
+        let Some((url, hash)) = sync_assets.serve(&mut track, &uuid, asset) else {
+            continue;
+        };
+        let msg = &Message::AssetUpdated {
+            asset_type: A::type_path().to_string(),
+            id: uuid,
+            url,
+            hash,
+            // modified assets may not match their file anymore
+            path: match modified.contains(&id) {
+                true => None,
+                false => track.asset_path_to_network(&asset_server, id),
+            },
+        };
+        broadcast_to_joined(&mut server, &joined, None, msg);
+    }
 }
@@ -1,6 +1,5 @@
 use bevy::prelude::*;
-use bevy_renet::renet::{DefaultChannel, RenetClient, RenetServer};
-use bevy_sync::SyncEntity;
+use bevy_sync::{ClientTransport, ServerTransport, SyncEntity, HOST_PEER};
 use uuid::Uuid;
 
 use crate::setup::{sample_audio, sample_image, sample_mesh, MySynched, TestEnv};
@@ -27,11 +26,9 @@ pub(crate) fn entities_in_sync<T>(env: &mut TestEnv, _: T, entity_count: u32) {
 
 #[allow(dead_code)]
 pub(crate) fn no_messages_left_for_server(s: &mut App) {
-    let mut server = s.world_mut().resource_mut::<RenetServer>();
-    for client_id in server.clients_id().into_iter() {
-        assert!(server
-            .receive_message(client_id, DefaultChannel::ReliableOrdered)
-            .is_none());
+    let mut server = s.world_mut().resource_mut::<ServerTransport>();
+    for client_id in server.peers().into_iter() {
+        assert!(server.receive(client_id).is_none());
     }
 }
 
@@ -44,10 +41,8 @@ pub(crate) fn no_messages_left_for_clients(cs: &mut Vec<App>) {
 
 #[allow(dead_code)]
 pub(crate) fn no_messages_left_for_client(c: &mut App) {
-    let mut client = c.world_mut().resource_mut::<RenetClient>();
-    assert!(client
-        .receive_message(DefaultChannel::ReliableOrdered)
-        .is_none());
+    let mut client = c.world_mut().resource_mut::<ClientTransport>();
+    assert!(client.receive(HOST_PEER).is_none());
 }
 
 #[allow(dead_code)]
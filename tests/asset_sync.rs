@@ -2,12 +2,176 @@ mod assert;
 mod setup;
 
 use assert::{
-    assets_has_sample_audio, assets_has_sample_image, assets_has_sample_mesh, material_has_color,
+    assets_has_sample_audio, assets_has_sample_image, assets_has_sample_mesh,
+    get_first_entity_component, material_has_color,
 };
-use bevy::prelude::*;
-use bevy_sync::SyncComponent;
+use std::{error::Error, thread, time::Duration};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    gltf::GltfPlugin,
+    prelude::*,
+    reflect::Struct,
+    render::{mesh::VertexAttributeValues, render_resource::AsBindGroup},
+    scene::ScenePlugin,
+};
+use bevy_sync::{AssetCompression, SyncAsset, SyncComponent, SyncMark, SyncPlugin};
 use serial_test::serial;
-use setup::{spawn_new_audio, spawn_new_image, spawn_new_material, spawn_new_mesh, TestRun};
+use setup::{
+    sample_image, sample_mesh, spawn_new_audio, spawn_new_image, spawn_new_material,
+    spawn_new_mesh, spawn_new_mesh_nouuid, TestRun,
+};
+use uuid::Uuid;
+
+#[derive(Asset, TypePath, Debug, PartialEq)]
+struct LevelData {
+    name: String,
+    tiles: Vec<u8>,
+}
+
+impl SyncAsset for LevelData {
+    fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let mut bytes = vec![self.name.len() as u8];
+        bytes.extend(self.name.as_bytes());
+        bytes.extend(&self.tiles);
+        Ok(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let (len, rest) = bytes.split_first().ok_or("empty level")?;
+        let (name, tiles) = rest
+            .split_at_checked(*len as usize)
+            .ok_or("truncated level")?;
+        Ok(LevelData {
+            name: String::from_utf8(name.to_vec())?,
+            tiles: tiles.to_vec(),
+        })
+    }
+}
+
+fn sample_level() -> LevelData {
+    LevelData {
+        name: "cave".to_string(),
+        tiles: vec![1, 0, 0, 2, 2, 1],
+    }
+}
+
+fn spawn_new_level(app: &mut App) -> AssetId<LevelData> {
+    let id = Uuid::new_v4();
+    app.world_mut()
+        .resource_mut::<Assets<LevelData>>()
+        .insert(id, sample_level());
+    id.into()
+}
+
+#[derive(Asset, AsBindGroup, Reflect, Clone, Debug)]
+struct GlowMaterial {
+    #[uniform(0)]
+    color: LinearRgba,
+    #[texture(1)]
+    #[sampler(2)]
+    texture: Option<Handle<Image>>,
+}
+
+impl Material for GlowMaterial {}
+
+fn spawn_new_glow_material(app: &mut App) -> AssetId<GlowMaterial> {
+    let image_id = AssetId::from(Uuid::new_v4());
+    let mut images = app.world_mut().resource_mut::<Assets<Image>>();
+    images.insert(image_id, sample_image());
+    let texture = images.get_strong_handle(image_id);
+    let id = Uuid::new_v4();
+    app.world_mut()
+        .resource_mut::<Assets<GlowMaterial>>()
+        .insert(
+            id,
+            GlowMaterial {
+                color: LinearRgba::GREEN,
+                texture,
+            },
+        );
+    id.into()
+}
+
+fn setup_glow_sync(app: &mut App) {
+    app.init_asset::<GlowMaterial>();
+    app.sync_material::<GlowMaterial>();
+}
+
+fn setup_level_sync(app: &mut App) {
+    app.init_asset::<LevelData>();
+    app.sync_asset::<LevelData>();
+}
+
+/// TextureAtlasLayout comes from bevy, it is sent as its size and texture rects.
+fn atlas_to_bytes(atlas: &TextureAtlasLayout) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let rects = atlas
+        .textures
+        .iter()
+        .flat_map(|rect| [rect.min.x, rect.min.y, rect.max.x, rect.max.y]);
+    Ok([atlas.size.x, atlas.size.y]
+        .into_iter()
+        .chain(rects)
+        .flat_map(u32::to_le_bytes)
+        .collect())
+}
+
+fn atlas_from_bytes(bytes: &[u8]) -> Result<TextureAtlasLayout, Box<dyn Error + Send + Sync>> {
+    let values: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    let (size, rects) = values.split_at_checked(2).ok_or("truncated atlas")?;
+    let mut atlas = TextureAtlasLayout::new_empty(UVec2::new(size[0], size[1]));
+    for rect in rects.chunks_exact(4) {
+        atlas.add_texture(URect::new(rect[0], rect[1], rect[2], rect[3]));
+    }
+    Ok(atlas)
+}
+
+fn setup_atlas_sync(app: &mut App) {
+    app.init_asset::<TextureAtlasLayout>();
+    app.sync_asset_with(atlas_to_bytes, atlas_from_bytes);
+}
+
+/// Loads the levels of the assets directory, stored as their SyncAsset bytes. The suffix is
+/// added to their name to tell where a level was loaded.
+struct LevelLoader {
+    name_suffix: &'static str,
+}
+
+impl AssetLoader for LevelLoader {
+    type Asset = LevelData;
+    type Settings = ();
+    type Error = Box<dyn Error + Send + Sync>;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _: &'a (),
+        _: &'a mut LoadContext<'_>,
+    ) -> Result<LevelData, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let mut level = LevelData::from_bytes(&bytes)?;
+        level.name.push_str(self.name_suffix);
+        Ok(level)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["lvl"]
+    }
+}
+
+fn load_level(app: &mut App) -> Handle<LevelData> {
+    app.world().resource::<AssetServer>().load("level.lvl")
+}
+
+fn level_handle_of_first_entity(app: &mut App) -> AssetId<LevelData> {
+    get_first_entity_component::<Handle<LevelData>>(app.world_mut())
+        .unwrap()
+        .id()
+}
 
 #[test]
 #[serial]
@@ -88,6 +252,118 @@ fn test_mesh_transferred_from_server() {
     );
 }
 
+#[serial]
+#[test]
+fn test_mesh_transferred_from_server_over_socket() {
+    TestRun::socket().run(
+        1,
+        |env| {
+            env.setup_registration::<Handle<Mesh>>();
+            env.server.sync_meshes(true);
+            env.clients[0].sync_meshes(true);
+        },
+        |env| {
+            let app = &mut env.server;
+            spawn_new_mesh(app)
+        },
+        |env, _, id| {
+            assets_has_sample_mesh(&mut env.clients[0], id);
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_mesh_transferred_from_server_over_connection() {
+    TestRun::socket_assets_over_connection().run(
+        1,
+        |env| {
+            env.setup_registration::<Handle<Mesh>>();
+            env.server.sync_meshes(true);
+            env.clients[0].sync_meshes(true);
+        },
+        |env| {
+            let app = &mut env.server;
+            spawn_new_mesh(app)
+        },
+        |env, _, id| {
+            assets_has_sample_mesh(&mut env.clients[0], id);
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_image_transferred_from_client_to_client_over_connection() {
+    TestRun::socket_assets_over_connection().run(
+        2,
+        |env| {
+            env.setup_registration::<Handle<StandardMaterial>>();
+            env.setup_registration::<Handle<Image>>();
+            env.server.sync_materials(true);
+            for client in env.clients.iter_mut() {
+                client.sync_materials(true);
+            }
+        },
+        |env| {
+            // both clients joined, the image goes through the host to the other one
+            env.update(20);
+            let app = &mut env.clients[0];
+            spawn_new_image(app)
+        },
+        |env, _, id| {
+            assets_has_sample_image(&mut env.server, id);
+            assets_has_sample_image(&mut env.clients[1], id);
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_mesh_transferred_from_server_over_websocket() {
+    TestRun::websocket().run(
+        1,
+        |env| {
+            env.setup_registration::<Handle<Mesh>>();
+            env.server.sync_meshes(true);
+            env.clients[0].sync_meshes(true);
+        },
+        |env| {
+            let app = &mut env.server;
+            spawn_new_mesh(app)
+        },
+        |env, _, id| {
+            assets_has_sample_mesh(&mut env.clients[0], id);
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_image_transferred_from_client_to_client_over_websocket() {
+    TestRun::websocket().run(
+        2,
+        |env| {
+            env.setup_registration::<Handle<StandardMaterial>>();
+            env.setup_registration::<Handle<Image>>();
+            env.server.sync_materials(true);
+            for client in env.clients.iter_mut() {
+                client.sync_materials(true);
+            }
+        },
+        |env| {
+            // both clients joined, the image goes through the host to the other one
+            env.update(20);
+            let app = &mut env.clients[0];
+            spawn_new_image(app)
+        },
+        |env, _, id| {
+            assets_has_sample_image(&mut env.server, id);
+            assets_has_sample_image(&mut env.clients[1], id);
+        },
+    );
+}
+
 #[serial]
 #[test]
 fn test_mesh_transferred_from_client() {
@@ -208,3 +484,398 @@ fn test_audio_transferred_initial_sync() {
         },
     );
 }
+
+#[serial]
+#[test]
+fn test_custom_asset_transferred_from_server() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            setup_level_sync(&mut env.server);
+            setup_level_sync(&mut env.clients[0]);
+        },
+        |env| {
+            env.update(20);
+            spawn_new_level(&mut env.server)
+        },
+        |env, _, id| {
+            let levels = env.clients[0].world().resource::<Assets<LevelData>>();
+            assert_eq!(levels.get(id), Some(&sample_level()));
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_asset_of_another_crate_transferred_with_its_codec() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            setup_atlas_sync(&mut env.server);
+            setup_atlas_sync(&mut env.clients[0]);
+        },
+        |env| {
+            env.update(20);
+            let mut atlas = TextureAtlasLayout::new_empty(UVec2::new(64, 32));
+            atlas.add_texture(URect::new(0, 0, 32, 32));
+            atlas.add_texture(URect::new(32, 0, 64, 32));
+            let id = Uuid::new_v4();
+            env.server
+                .world_mut()
+                .resource_mut::<Assets<TextureAtlasLayout>>()
+                .insert(id, atlas);
+            AssetId::<TextureAtlasLayout>::from(id)
+        },
+        |env, _, id| {
+            let atlases = env.clients[0]
+                .world()
+                .resource::<Assets<TextureAtlasLayout>>();
+            let atlas = atlases.get(id).unwrap();
+            assert_eq!(atlas.size, UVec2::new(64, 32));
+            assert_eq!(
+                atlas.textures,
+                vec![URect::new(0, 0, 32, 32), URect::new(32, 0, 64, 32)]
+            );
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_custom_asset_transferred_initial_sync() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            setup_level_sync(&mut env.server);
+            setup_level_sync(&mut env.clients[0]);
+            spawn_new_level(&mut env.server)
+        },
+        TestRun::no_setup,
+        |env, id, _| {
+            let levels = env.clients[0].world().resource::<Assets<LevelData>>();
+            assert_eq!(levels.get(id), Some(&sample_level()));
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_custom_material_transferred_from_server_with_its_texture() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            setup_glow_sync(&mut env.server);
+            setup_glow_sync(&mut env.clients[0]);
+        },
+        |env| {
+            env.update(20);
+            spawn_new_glow_material(&mut env.server)
+        },
+        |env, _, id| {
+            let client = env.clients[0].world();
+            let material = client.resource::<Assets<GlowMaterial>>().get(id).unwrap();
+            assert_eq!(material.color, LinearRgba::GREEN);
+            let texture = material.texture.as_ref().unwrap().id();
+            assert!(client.resource::<Assets<Image>>().contains(texture));
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_custom_material_keeps_its_texture_synched_without_meshes() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            setup_glow_sync(&mut env.server);
+            setup_glow_sync(&mut env.clients[0]);
+            env.server.sync_meshes(false);
+            env.clients[0].sync_meshes(false);
+        },
+        |env| {
+            env.update(20);
+            spawn_new_glow_material(&mut env.server)
+        },
+        |env, _, id| {
+            let client = env.clients[0].world();
+            let material = client.resource::<Assets<GlowMaterial>>().get(id).unwrap();
+            let texture = material.texture.as_ref().unwrap().id();
+            assert!(client.resource::<Assets<Image>>().contains(texture));
+        },
+    );
+}
+
+#[test]
+fn test_mesh_removed_from_server() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            env.setup_registration::<Handle<Mesh>>();
+            env.server.sync_meshes(true);
+            env.clients[0].sync_meshes(true);
+        },
+        |env| {
+            env.update(20);
+            let id = spawn_new_mesh(&mut env.server);
+            env.update(20);
+            assert!(env.clients[0]
+                .world()
+                .resource::<Assets<Mesh>>()
+                .contains(id));
+            env.server
+                .world_mut()
+                .resource_mut::<Assets<Mesh>>()
+                .remove(id);
+            id
+        },
+        |env, _, id| {
+            assert!(!env.clients[0]
+                .world()
+                .resource::<Assets<Mesh>>()
+                .contains(id));
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_material_removed_from_client_reaches_other_clients() {
+    TestRun::memory().run(
+        2,
+        |env| {
+            env.setup_registration::<Handle<StandardMaterial>>();
+            env.server.sync_materials(true);
+            for client in &mut env.clients {
+                client.sync_materials(true);
+            }
+        },
+        |env| {
+            env.update(20);
+            let id = spawn_new_material(&mut env.clients[0]);
+            env.update(20);
+            assert!(env.clients[1]
+                .world()
+                .resource::<Assets<StandardMaterial>>()
+                .contains(id));
+            env.clients[0]
+                .world_mut()
+                .resource_mut::<Assets<StandardMaterial>>()
+                .remove(id);
+            id
+        },
+        |env, _, id| {
+            for app in [&env.server, &env.clients[1]] {
+                assert!(!app
+                    .world()
+                    .resource::<Assets<StandardMaterial>>()
+                    .contains(id));
+            }
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_mesh_without_uuid_transferred_with_entity_from_client() {
+    TestRun::memory().run(
+        2,
+        |env| {
+            env.setup_registration::<Handle<Mesh>>();
+            env.server.sync_meshes(true);
+            for client in &mut env.clients {
+                client.sync_meshes(true);
+            }
+        },
+        |env| {
+            env.update(20);
+            let e_id = env.clients[0].world_mut().spawn(SyncMark).id();
+            env.update(4);
+            let handle = spawn_new_mesh_nouuid(&mut env.clients[0]);
+            env.clients[0].world_mut().entity_mut(e_id).insert(handle);
+        },
+        |env, _, _| {
+            for app in [&mut env.server, &mut env.clients[1]] {
+                let id = get_first_entity_component::<Handle<Mesh>>(app.world_mut())
+                    .unwrap()
+                    .id();
+                assert!(matches!(id, AssetId::Uuid { .. }));
+                assets_has_sample_mesh(app, id);
+            }
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_mesh_transferred_with_the_compression_of_its_sender() {
+    let run = TestRun::memory();
+    run.run(
+        1,
+        |env| {
+            env.setup_registration::<Handle<Mesh>>();
+            env.server.sync_meshes(true);
+            env.clients[0].sync_meshes(true);
+        },
+        |env| {
+            let mut joining = setup::new_app_with(SyncPlugin {
+                mesh_compression: AssetCompression::Zstd,
+                quantize_meshes: true,
+                ..default()
+            });
+            joining.sync_component::<Handle<Mesh>>();
+            joining.sync_meshes(true);
+            env.join_client(&run, joining);
+            env.update(20);
+            spawn_new_mesh(&mut env.clients[1])
+        },
+        |env, _, id| {
+            let sample = sample_mesh();
+            let Some(VertexAttributeValues::Float32x3(expected)) =
+                sample.attribute(Mesh::ATTRIBUTE_POSITION)
+            else {
+                panic!("sample mesh without positions");
+            };
+            for app in [&mut env.server, &mut env.clients[0]] {
+                let meshes = app.world().resource::<Assets<Mesh>>();
+                let mesh = meshes.get(id).unwrap();
+                let Some(VertexAttributeValues::Float32x3(positions)) =
+                    mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+                else {
+                    panic!("mesh without positions");
+                };
+                // quantized by the sender, close to the sample only
+                for (position, expected) in positions.iter().zip(expected) {
+                    let position = Vec3::from_array(*position);
+                    assert!(position.distance(Vec3::from_array(*expected)) < 0.001);
+                }
+                assert_eq!(mesh.indices().unwrap().len(), 3);
+            }
+        },
+    );
+}
+
+fn morph_targets_of_first_mesh(app: &mut App) -> Option<AssetId<Image>> {
+    let id = get_first_entity_component::<Handle<Mesh>>(app.world_mut())?.id();
+    let mesh = app.world().resource::<Assets<Mesh>>().get(id)?;
+    let morph_targets = mesh.field("morph_targets")?;
+    let morph_targets = morph_targets.downcast_ref::<Option<Handle<Image>>>()?;
+    morph_targets.as_ref().map(|handle| handle.id())
+}
+
+#[serial]
+#[test]
+fn test_mesh_transferred_with_its_morph_targets() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            env.setup_registration::<Handle<Mesh>>();
+            for app in [&mut env.server, &mut env.clients[0]] {
+                app.add_plugins((ScenePlugin, GltfPlugin::default()));
+                app.init_asset::<AnimationClip>();
+                // the glTF loader is registered when the plugin is finished
+                GltfPlugin::default().finish(app);
+                app.sync_meshes(true);
+                // the morph targets images stay synched without the materials
+                app.sync_materials(false);
+            }
+        },
+        |env| {
+            env.update(20);
+            let handle: Handle<Mesh> = env
+                .server
+                .world()
+                .resource::<AssetServer>()
+                .load("morph.gltf#Mesh0/Primitive0");
+            for _ in 0..200 {
+                let asset_server = env.server.world().resource::<AssetServer>();
+                if asset_server.is_loaded_with_dependencies(&handle) {
+                    break;
+                }
+                env.update(1);
+                thread::sleep(Duration::from_millis(5));
+            }
+            let e_id = env.server.world_mut().spawn(SyncMark).id();
+            env.update(4);
+            env.server.world_mut().entity_mut(e_id).insert(handle);
+        },
+        |env, _, _| {
+            let server_id = morph_targets_of_first_mesh(&mut env.server).unwrap();
+            let client_id = morph_targets_of_first_mesh(&mut env.clients[0]).unwrap();
+            let server_image = env
+                .server
+                .world()
+                .resource::<Assets<Image>>()
+                .get(server_id);
+            let client_image = env.clients[0]
+                .world()
+                .resource::<Assets<Image>>()
+                .get(client_id);
+            let (server_image, client_image) = (server_image.unwrap(), client_image.unwrap());
+            assert_eq!(
+                server_image.texture_descriptor.size,
+                client_image.texture_descriptor.size
+            );
+            assert_eq!(server_image.data, client_image.data);
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_asset_loaded_by_path_on_client() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            env.setup_registration::<Handle<LevelData>>();
+            for (app, name_suffix) in [(&mut env.server, ""), (&mut env.clients[0], " (local)")] {
+                setup_level_sync(app);
+                app.register_asset_loader(LevelLoader { name_suffix });
+                app.sync_assets_by_path(true);
+            }
+        },
+        |env| {
+            env.update(20);
+            let e_id = env.server.world_mut().spawn(SyncMark).id();
+            env.update(4);
+            let handle = load_level(&mut env.server);
+            env.server.world_mut().entity_mut(e_id).insert(handle);
+        },
+        |env, _, _| {
+            let id = level_handle_of_first_entity(&mut env.clients[0]);
+            let levels = env.clients[0].world().resource::<Assets<LevelData>>();
+            let level = levels.get(id).unwrap();
+            assert_eq!(level.name, "cave (local)");
+            assert_eq!(level.tiles, sample_level().tiles);
+        },
+    );
+}
+
+#[serial]
+#[test]
+fn test_asset_downloaded_when_its_path_cannot_be_loaded() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            env.setup_registration::<Handle<LevelData>>();
+            for app in [&mut env.server, &mut env.clients[0]] {
+                setup_level_sync(app);
+                app.sync_assets_by_path(true);
+            }
+            // the client has no loader for the level files
+            env.server
+                .register_asset_loader(LevelLoader { name_suffix: "" });
+        },
+        |env| {
+            env.update(20);
+            let e_id = env.server.world_mut().spawn(SyncMark).id();
+            env.update(4);
+            let handle = load_level(&mut env.server);
+            env.server.world_mut().entity_mut(e_id).insert(handle);
+        },
+        |env, _, _| {
+            let id = level_handle_of_first_entity(&mut env.clients[0]);
+            let levels = env.clients[0].world().resource::<Assets<LevelData>>();
+            assert_eq!(levels.get(id), Some(&sample_level()));
+        },
+    );
+}
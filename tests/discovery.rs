@@ -0,0 +1,174 @@
+mod setup;
+
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_sync::{
+    ClientPlugin, ClientState, ClientTransport, DiscoveredSessions, DiscoveryPlugin, ServerPlugin,
+    ServerTransport, SyncConnectionParameters, SyncNetworkConfig,
+};
+use portpicker::pick_unused_port;
+use serial_test::serial;
+use setup::{new_app, TestRun};
+
+fn loopback_discovery(port: u16) -> DiscoveryPlugin {
+    DiscoveryPlugin {
+        session_name: "lan party".to_string(),
+        port,
+        broadcast_ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+        interval: Duration::from_millis(20),
+        timeout: Duration::from_millis(300),
+    }
+}
+
+fn update_until(apps: &mut [&mut App], mut condition: impl FnMut(&[&mut App]) -> bool) {
+    for _ in 0..200 {
+        for app in apps.iter_mut() {
+            app.update();
+        }
+        if condition(apps) {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    panic!("condition not reached in time");
+}
+
+fn sessions(app: &App) -> &DiscoveredSessions {
+    app.world().resource::<DiscoveredSessions>()
+}
+
+fn hosting(discovery_port: u16) -> App {
+    let mut host = new_app();
+    host.add_plugins(loopback_discovery(discovery_port));
+    host.add_plugins(ServerPlugin {
+        parameters: TestRun::socket().params,
+    });
+    host
+}
+
+fn stop(app: &mut App) {
+    app.world_mut().remove_resource::<ServerTransport>();
+    app.world_mut().remove_resource::<ClientTransport>();
+    app.update();
+}
+
+#[test]
+#[serial]
+fn test_client_discovers_and_joins_session() {
+    let discovery_port = pick_unused_port().unwrap();
+    let mut host = hosting(discovery_port);
+    let mut client = new_app();
+    client.add_plugins(loopback_discovery(discovery_port));
+
+    update_until(&mut [&mut host, &mut client], |apps| {
+        !sessions(apps[1]).sessions.is_empty()
+    });
+    let SyncConnectionParameters::Socket { port, .. } =
+        host.world().resource::<SyncConnectionParameters>().clone()
+    else {
+        panic!("host is not on a socket");
+    };
+    let session = sessions(&client).sessions.values().next().unwrap().clone();
+    assert_eq!(session.name, "lan party");
+    assert_eq!(session.ip, IpAddr::V4(Ipv4Addr::LOCALHOST));
+    assert_eq!(session.port, port);
+    assert_eq!(session.players, 1);
+    assert!(!session.secure);
+
+    client.add_plugins(ClientPlugin {
+        parameters: session.client_parameters(100_000_000, None).unwrap(),
+        identity: None,
+    });
+    update_until(&mut [&mut host, &mut client], |apps| {
+        apps[1].world().resource::<State<ClientState>>().get() == &ClientState::Connected
+    });
+    stop(&mut client);
+    stop(&mut host);
+}
+
+#[test]
+#[serial]
+fn test_client_joins_secure_session_with_connect_token() {
+    let discovery_port = pick_unused_port().unwrap();
+    let run = TestRun::secure(60);
+    let SyncConnectionParameters::SecureSocket { connect_token, .. } = run.params.clone() else {
+        panic!("run is not secure");
+    };
+    let mut host = new_app();
+    host.add_plugins(loopback_discovery(discovery_port));
+    host.add_plugins(ServerPlugin {
+        parameters: run.params,
+    });
+    let mut client = new_app();
+    client.add_plugins(loopback_discovery(discovery_port));
+
+    update_until(&mut [&mut host, &mut client], |apps| {
+        !sessions(apps[1]).sessions.is_empty()
+    });
+    let session = sessions(&client).sessions.values().next().unwrap().clone();
+    assert!(session.secure);
+    assert!(session.client_parameters(100_000_000, None).is_none());
+
+    let parameters = session
+        .client_parameters(100_000_000, Some(connect_token))
+        .unwrap();
+    assert!(matches!(
+        parameters,
+        SyncConnectionParameters::SecureSocket { .. }
+    ));
+    client.add_plugins(ClientPlugin {
+        parameters,
+        identity: None,
+    });
+    update_until(&mut [&mut host, &mut client], |apps| {
+        apps[1].world().resource::<State<ClientState>>().get() == &ClientState::Connected
+    });
+    stop(&mut client);
+    stop(&mut host);
+}
+
+#[test]
+#[serial]
+fn test_session_expires_when_host_stops() {
+    let discovery_port = pick_unused_port().unwrap();
+    let mut host = hosting(discovery_port);
+    let mut client = new_app();
+    client.add_plugins(loopback_discovery(discovery_port));
+
+    update_until(&mut [&mut host, &mut client], |apps| {
+        !sessions(apps[1]).sessions.is_empty()
+    });
+    stop(&mut host);
+    update_until(&mut [&mut host, &mut client], |apps| {
+        sessions(apps[1]).sessions.is_empty()
+    });
+}
+
+#[test]
+#[serial]
+fn test_sessions_of_other_protocols_are_ignored() {
+    let discovery_port = pick_unused_port().unwrap();
+    let mut host = new_app();
+    host.insert_resource(SyncNetworkConfig {
+        protocol_id: 7,
+        ..Default::default()
+    });
+    host.add_plugins(loopback_discovery(discovery_port));
+    host.add_plugins(ServerPlugin {
+        parameters: TestRun::socket().params,
+    });
+    let mut client = new_app();
+    client.add_plugins(loopback_discovery(discovery_port));
+
+    for _ in 0..30 {
+        host.update();
+        client.update();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(sessions(&client).sessions.is_empty());
+    stop(&mut host);
+}
@@ -36,6 +36,34 @@ fn test_one_entity_spawned_from_client() {
     );
 }
 
+#[test]
+#[serial]
+fn test_one_entity_spawned_from_server_over_socket() {
+    TestRun::socket().run(
+        1,
+        TestRun::no_pre_setup,
+        |env| {
+            env.server.world_mut().spawn(SyncMark {});
+            1
+        },
+        assert::entities_in_sync,
+    );
+}
+
+#[test]
+#[serial]
+fn test_one_entity_spawned_from_server_over_websocket() {
+    TestRun::websocket().run(
+        1,
+        TestRun::no_pre_setup,
+        |env| {
+            env.server.world_mut().spawn(SyncMark {});
+            1
+        },
+        assert::entities_in_sync,
+    );
+}
+
 #[test]
 #[serial]
 fn test_more_entities_spawned_from_server() {
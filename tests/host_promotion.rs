@@ -1,9 +1,10 @@
-use bevy::{app::App, ecs::entity::Entity};
-use bevy_renet::renet::{
-    transport::{NetcodeClientTransport, NetcodeServerTransport},
-    RenetServer,
+use std::{error::Error, time::Duration};
+
+use bevy::{app::App, ecs::entity::Entity, state::state::State};
+use bevy_sync::{
+    ClientId, ClientTransport, PromoteToHostEvent, ServerState, ServerTransport,
+    SyncConnectionParameters, SyncMark, SyncTransport, TransportEvent,
 };
-use bevy_sync::{PromoteToHostEvent, SyncConnectionParameters, SyncMark};
 use serial_test::serial;
 use setup::{MySynched, TestEnv, TestRun};
 
@@ -23,7 +24,137 @@ fn test_host_promotion_with_one_client() {
     );
 }
 
-// It is currently difficult to run with this test.
+#[test]
+#[serial]
+fn test_host_promotion_with_more_clients() {
+    TestRun::memory().run(
+        3,
+        TestRun::no_pre_setup,
+        setup_host_promotion,
+        |env, _, _| assert_host_promotion(env),
+    );
+}
+
+#[test]
+#[serial]
+fn test_host_promotion_with_one_client_over_socket() {
+    TestRun::socket().run(
+        1,
+        TestRun::no_pre_setup,
+        setup_host_promotion,
+        |env, _, _| assert_host_promotion(env),
+    );
+}
+
+#[test]
+#[serial]
+fn test_host_promotion_with_one_client_over_secure_socket() {
+    // the token lists the ports the host and the client move to, see alter_connection_port
+    TestRun::secure_promotable(300, 2).run(
+        1,
+        TestRun::no_pre_setup,
+        setup_host_promotion,
+        |env, _, _| assert_host_promotion(env),
+    );
+}
+
+#[test]
+#[serial]
+fn test_host_promotion_without_private_key_fails() {
+    TestRun::secure_promotable(300, 2).run(
+        1,
+        TestRun::no_pre_setup,
+        |env| {
+            setup_and_check_sync(env);
+            alter_connection_port(env);
+            for c in env.clients.iter_mut() {
+                if let SyncConnectionParameters::SecureSocket {
+                    ref mut private_key,
+                    ..
+                } = c
+                    .world_mut()
+                    .resource_mut::<SyncConnectionParameters>()
+                    .as_mut()
+                {
+                    *private_key = None;
+                }
+            }
+            send_promotion_event(env);
+            env.update(10);
+        },
+        |env, _, _| {
+            assert_server_is_host(env);
+            let client = &env.clients[0];
+            assert!(is_client(client));
+            assert!(matches!(
+                client.world().resource::<State<ServerState>>().get(),
+                ServerState::Failed(_)
+            ));
+        },
+    );
+}
+
+/// Host transport without any peer, enough for a client to believe it became a host.
+struct NoPeers;
+
+impl SyncTransport for NoPeers {
+    fn update(&mut self, _: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Ok(())
+    }
+
+    fn send_packets(&mut self) {}
+
+    fn peers(&self) -> Vec<ClientId> {
+        vec![]
+    }
+
+    fn send(&mut self, _: ClientId, _: Vec<u8>) {}
+
+    fn receive(&mut self, _: ClientId) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn is_disconnected(&self) -> bool {
+        false
+    }
+
+    fn disconnect(&mut self, _: ClientId) {}
+
+    fn disconnect_all(&mut self) {}
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        None
+    }
+}
+
+#[test]
+#[serial]
+fn test_new_host_from_a_client_not_promoted_is_ignored() {
+    TestRun::memory().run(
+        2,
+        TestRun::no_pre_setup,
+        |env| {
+            setup_and_check_sync(env);
+            // the client hosting on its own tells the host it is the new one
+            env.clients[1].insert_resource(ServerTransport(Box::new(NoPeers)));
+            env.update(10);
+        },
+        |env, _, _| {
+            assert!(is_host(&env.server));
+            assert!(!is_client(&env.server));
+            let server = env.server.world().resource::<ServerTransport>();
+            assert_eq!(server.peers().len(), 2);
+            assert!(!is_host(&env.clients[0]));
+            assert!(is_client(&env.clients[0]));
+        },
+    );
+}
+
+// It is currently difficult to run with this test over sockets.
 //
 // When there is only one server and one client in the same machine, one will open the new server
 // while the other will connect as client.
@@ -36,8 +167,8 @@ fn test_host_promotion_with_one_client() {
 #[ignore = "Unfeasible to run on the same localhost ip for all instances?"]
 #[test]
 #[serial]
-fn test_host_promotion_with_more_clients() {
-    TestRun::default().run(
+fn test_host_promotion_with_more_clients_over_socket() {
+    TestRun::socket().run(
         3,
         TestRun::no_pre_setup,
         setup_host_promotion,
@@ -125,14 +256,21 @@ fn increment_port(app: &mut App, i: u16) {
             ref mut port,
             web_port: _,
             max_transfer: _,
+            asset_transport: _,
         } => *port += i,
+        SyncConnectionParameters::SecureSocket { ref mut port, .. } => *port += i,
+        SyncConnectionParameters::InMemory { ref mut name } => name.push_str(&i.to_string()),
+        SyncConnectionParameters::WebSocket { ref mut url } => {
+            let (base, port) = url.rsplit_once(':').unwrap();
+            *url = format!("{}:{}", base, port.parse::<u16>().unwrap() + i);
+        }
     }
 }
 
 fn send_promotion_event(env: &mut TestEnv) {
-    let server = env.server.world_mut().resource_mut::<RenetServer>();
+    let server = env.server.world_mut().resource_mut::<ServerTransport>();
     let event = PromoteToHostEvent {
-        id: server.clients_id().first().unwrap().to_owned(),
+        id: server.peers().first().unwrap().to_owned(),
     };
     env.server.world_mut().send_event(event);
 }
@@ -156,15 +294,11 @@ fn assert_only_one_client_is_host(env: &TestEnv) {
 }
 
 fn is_host(app: &App) -> bool {
-    app.world()
-        .get_resource::<NetcodeServerTransport>()
-        .is_some()
+    app.world().get_resource::<ServerTransport>().is_some()
 }
 
 fn is_client(app: &App) -> bool {
-    app.world()
-        .get_resource::<NetcodeClientTransport>()
-        .is_some()
+    app.world().get_resource::<ClientTransport>().is_some()
 }
 
 fn assert_all_are_connected(env: &TestEnv) {
@@ -4,7 +4,6 @@ mod setup;
 use assert::{assets_has_sample_image, assets_has_sample_mesh, material_has_color};
 use bevy::prelude::*;
 use bevy_sync::{SyncComponent, SyncExclude, SyncMark};
-use serial_test::serial;
 use setup::{
     spawn_new_image, spawn_new_material, spawn_new_material_nouuid, spawn_new_mesh,
     spawn_new_mesh_nouuid, MySynched, TestEnv, TestRun,
@@ -13,7 +12,6 @@ use setup::{
 use crate::{assert::count_entities_with_component, setup::MySynched2};
 
 #[test]
-#[serial]
 fn test_initial_world_sync_sent_from_server() {
     TestRun::default().run(
         1,
@@ -60,7 +58,6 @@ fn test_initial_world_sync_sent_from_server() {
 }
 
 #[test]
-#[serial]
 fn test_init_sync_multiple_clients() {
     TestRun::default().run(
         3,
@@ -96,7 +93,6 @@ fn test_init_sync_multiple_clients() {
 }
 
 #[test]
-#[serial]
 fn test_initial_world_sync_not_transfer_excluded_components() {
     TestRun::default().run(
         1,
@@ -124,7 +120,6 @@ fn test_initial_world_sync_not_transfer_excluded_components() {
 }
 
 #[test]
-#[serial]
 fn test_initial_with_parenting() {
     TestRun::default().run(
         1,
@@ -165,7 +160,6 @@ fn test_initial_with_parenting() {
 }
 
 #[test]
-#[serial]
 fn test_initial_world_sync_without_uuid() {
     TestRun::default().run(
         1,
@@ -197,3 +191,43 @@ fn test_initial_world_sync_without_uuid() {
         },
     );
 }
+
+#[test]
+fn test_initial_world_sync_without_uuid_transfers_the_assets() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            env.setup_registration::<Handle<StandardMaterial>>();
+            env.setup_registration::<Handle<Mesh>>();
+            env.server.sync_materials(true);
+            env.server.sync_meshes(true);
+            env.clients[0].sync_materials(true);
+            env.clients[0].sync_meshes(true);
+            let material_id = spawn_new_material_nouuid(&mut env.server);
+            let mesh_id = spawn_new_mesh_nouuid(&mut env.server);
+            env.server
+                .world_mut()
+                .spawn((SyncMark {}, material_id, mesh_id));
+        },
+        TestRun::no_setup,
+        |env, _, _| {
+            let mesh_id =
+                assert::get_first_entity_component::<Handle<Mesh>>(env.clients[0].world_mut())
+                    .unwrap()
+                    .id();
+            assert::assets_has_sample_mesh(&mut env.clients[0], mesh_id);
+            let material_id = assert::get_first_entity_component::<Handle<StandardMaterial>>(
+                env.clients[0].world_mut(),
+            )
+            .unwrap()
+            .id();
+            assert::material_has_color(
+                &mut env.clients[0],
+                material_id,
+                Color::srgb(1.0, 0.0, 0.0),
+            );
+            assert::no_messages_left_for_server(&mut env.server);
+            assert::no_messages_left_for_client(&mut env.clients[0]);
+        },
+    );
+}
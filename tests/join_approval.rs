@@ -0,0 +1,327 @@
+mod assert;
+mod setup;
+
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use bevy_sync::{
+    ClientId, ClientTransport, JoinApproval, JoinCredentials, JoinRejected, JoinSession,
+    PeerIdentity, PeerJoined, ServerTransport, StartHosting, SyncEntity, SyncMark,
+    SyncNetworkConfig, SyncPeers, SyncTransport, TransportEvent,
+};
+use serial_test::serial;
+use setup::{new_app, TestEnv, TestRun};
+
+#[derive(Resource, Default)]
+struct Rejections(Vec<String>);
+
+fn collect_rejections(mut events: EventReader<JoinRejected>, mut rejections: ResMut<Rejections>) {
+    for event in events.read() {
+        rejections.0.push(event.reason.clone());
+    }
+}
+
+fn setup_approval(env: &mut TestEnv, secret: &str) {
+    env.server
+        .insert_resource(JoinApproval::new(|_, credentials: &JoinCredentials| {
+            if credentials.secret == "open sesame" {
+                Ok(())
+            } else {
+                Err(format!("wrong secret for {}", credentials.user_name))
+            }
+        }));
+    let client = &mut env.clients[0];
+    client.insert_resource(JoinCredentials {
+        user_name: "alice".to_string(),
+        secret: secret.to_string(),
+    });
+    client.init_resource::<Rejections>();
+    client.add_systems(Update, collect_rejections);
+}
+
+#[test]
+#[serial]
+fn test_client_with_valid_credentials_joins() {
+    TestRun::memory().run(
+        1,
+        |env| setup_approval(env, "open sesame"),
+        |env| {
+            env.server.world_mut().spawn(SyncMark {});
+            1
+        },
+        |env, _, entity_count| {
+            assert::entities_in_sync(env, (), entity_count);
+            assert!(env.clients[0].world().resource::<Rejections>().0.is_empty());
+        },
+    );
+}
+
+#[test]
+#[serial]
+fn test_client_with_invalid_credentials_is_rejected() {
+    TestRun::memory().run(
+        1,
+        |env| setup_approval(env, "guess"),
+        |env| {
+            env.clients[0].world_mut().spawn(SyncMark {});
+        },
+        |env, _, _| {
+            assert_eq!(
+                env.clients[0].world().resource::<Rejections>().0,
+                vec!["wrong secret for alice".to_string()]
+            );
+            assert!(env.clients[0]
+                .world()
+                .get_resource::<ClientTransport>()
+                .is_none());
+            assert!(env
+                .server
+                .world()
+                .resource::<ServerTransport>()
+                .peers()
+                .is_empty());
+            assert_eq!(
+                assert::count_entities_with_component::<SyncEntity>(&mut env.server),
+                0
+            );
+        },
+    );
+}
+
+/// Client transport losing whatever the client sends, so that it never asks to join.
+struct Mute(Box<dyn SyncTransport>);
+
+impl SyncTransport for Mute {
+    fn update(&mut self, delta: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.0.update(delta)
+    }
+
+    fn send_packets(&mut self) {
+        self.0.send_packets();
+    }
+
+    fn peers(&self) -> Vec<ClientId> {
+        self.0.peers()
+    }
+
+    fn send(&mut self, _: ClientId, _: Vec<u8>) {}
+
+    fn receive(&mut self, peer: ClientId) -> Option<Vec<u8>> {
+        self.0.receive(peer)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.0.is_connected()
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.0.is_disconnected()
+    }
+
+    fn disconnect(&mut self, peer: ClientId) {
+        self.0.disconnect(peer);
+    }
+
+    fn disconnect_all(&mut self) {
+        self.0.disconnect_all();
+    }
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        self.0.poll_event()
+    }
+}
+
+#[test]
+fn test_client_not_joining_is_sent_nothing_and_disconnected() {
+    let params = TestRun::memory().params;
+    let mut server = new_app();
+    server.insert_resource(SyncNetworkConfig {
+        join_timeout: Duration::from_millis(300),
+        ..default()
+    });
+    server.insert_resource(JoinApproval::new(|_, _| Ok(())));
+    server.world_mut().send_event(StartHosting {
+        parameters: params.clone(),
+    });
+    server.update();
+    let mut client = new_app();
+    client
+        .world_mut()
+        .send_event(JoinSession { parameters: params });
+    client.update();
+    let transport = client
+        .world_mut()
+        .remove_resource::<ClientTransport>()
+        .unwrap();
+    client.insert_resource(ClientTransport(Box::new(Mute(transport.0))));
+
+    while server
+        .world()
+        .resource::<ServerTransport>()
+        .peers()
+        .is_empty()
+    {
+        server.update();
+        client.update();
+    }
+    server.world_mut().spawn(SyncMark);
+    for _ in 0..200 {
+        server.update();
+        client.update();
+        if server
+            .world()
+            .resource::<ServerTransport>()
+            .peers()
+            .is_empty()
+        {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert!(server
+        .world()
+        .resource::<ServerTransport>()
+        .peers()
+        .is_empty());
+    assert_eq!(
+        assert::count_entities_with_component::<SyncEntity>(&mut client),
+        0
+    );
+}
+
+/// Client transport sending its first message, the Join, twice.
+struct JoinTwice(Box<dyn SyncTransport>, bool);
+
+impl SyncTransport for JoinTwice {
+    fn update(&mut self, delta: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.0.update(delta)
+    }
+
+    fn send_packets(&mut self) {
+        self.0.send_packets();
+    }
+
+    fn peers(&self) -> Vec<ClientId> {
+        self.0.peers()
+    }
+
+    fn send(&mut self, peer: ClientId, message: Vec<u8>) {
+        if !self.1 {
+            self.1 = true;
+            self.0.send(peer, message.clone());
+        }
+        self.0.send(peer, message);
+    }
+
+    fn receive(&mut self, peer: ClientId) -> Option<Vec<u8>> {
+        self.0.receive(peer)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.0.is_connected()
+    }
+
+    fn is_disconnected(&self) -> bool {
+        self.0.is_disconnected()
+    }
+
+    fn disconnect(&mut self, peer: ClientId) {
+        self.0.disconnect(peer);
+    }
+
+    fn disconnect_all(&mut self) {
+        self.0.disconnect_all();
+    }
+
+    fn poll_event(&mut self) -> Option<TransportEvent> {
+        self.0.poll_event()
+    }
+}
+
+#[derive(Resource, Default)]
+struct Joins(usize);
+
+fn count_joins(mut events: EventReader<PeerJoined>, mut joins: ResMut<Joins>) {
+    joins.0 += events.read().count();
+}
+
+#[test]
+fn test_join_sent_again_is_ignored() {
+    let params = TestRun::memory().params;
+    let mut server = new_app();
+    let approvals = Arc::new(AtomicUsize::new(0));
+    let counted = approvals.clone();
+    // a second approval would reject the client
+    server.insert_resource(JoinApproval::new(move |_, _| {
+        match counted.fetch_add(1, Ordering::SeqCst) {
+            0 => Ok(()),
+            _ => Err("already joined".to_string()),
+        }
+    }));
+    server.init_resource::<Joins>();
+    server.add_systems(Update, count_joins);
+    server.world_mut().send_event(StartHosting {
+        parameters: params.clone(),
+    });
+    server.update();
+    let mut client = new_app();
+    client.init_resource::<Rejections>();
+    client.add_systems(Update, collect_rejections);
+    client
+        .world_mut()
+        .send_event(JoinSession { parameters: params });
+    client.update();
+    let transport = client
+        .world_mut()
+        .remove_resource::<ClientTransport>()
+        .unwrap();
+    client.insert_resource(ClientTransport(Box::new(JoinTwice(transport.0, false))));
+
+    server.world_mut().spawn(SyncMark);
+    for _ in 0..20 {
+        server.update();
+        client.update();
+    }
+    assert_eq!(approvals.load(Ordering::SeqCst), 1);
+    assert_eq!(server.world().resource::<Joins>().0, 1);
+    assert!(client.world().resource::<Rejections>().0.is_empty());
+    assert_eq!(
+        server.world().resource::<ServerTransport>().peers().len(),
+        1
+    );
+    assert_eq!(
+        assert::count_entities_with_component::<SyncEntity>(&mut client),
+        1
+    );
+}
+
+#[test]
+#[serial]
+fn test_client_with_an_identity_already_joined_is_rejected() {
+    TestRun::memory().run(
+        2,
+        |env| {
+            for client in env.clients.iter_mut() {
+                client.world_mut().resource_mut::<SyncPeers>().local = PeerIdentity::named("twin");
+                client.init_resource::<Rejections>();
+                client.add_systems(Update, collect_rejections);
+            }
+        },
+        TestRun::no_setup,
+        |env, _, _| {
+            let peers = &env.server.world().resource::<SyncPeers>().peers;
+            assert_eq!(peers.len(), 1);
+            assert!(env.clients[0].world().resource::<Rejections>().0.is_empty());
+            let rejections = &env.clients[1].world().resource::<Rejections>().0;
+            assert_eq!(rejections.len(), 1);
+            assert!(rejections[0].contains("already in the session"));
+        },
+    );
+}
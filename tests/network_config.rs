@@ -0,0 +1,97 @@
+mod assert;
+mod setup;
+
+use std::time::Duration;
+
+use bevy::{ecs::event::Events, prelude::*};
+use bevy_sync::{
+    ChannelConfig, SendType, ServerState, StartHosting, SyncMark, SyncNetworkConfig,
+    SyncStartupError,
+};
+use serial_test::serial;
+use setup::{new_app, TestEnv, TestRun};
+
+fn insert_config(env: &mut TestEnv, config: SyncNetworkConfig) {
+    env.server.insert_resource(config.clone());
+    for capp in &mut env.clients {
+        capp.insert_resource(config.clone());
+    }
+}
+
+#[test]
+#[serial]
+fn test_entities_sync_with_custom_network_config() {
+    TestRun::socket().run(
+        2,
+        |env| {
+            insert_config(
+                env,
+                SyncNetworkConfig {
+                    max_clients: 4,
+                    protocol_id: 42,
+                    available_bytes_per_tick: 250_000,
+                    channels: vec![ChannelConfig {
+                        channel_id: 0,
+                        max_memory_usage_bytes: 20 * 1024 * 1024,
+                        send_type: SendType::ReliableOrdered {
+                            resend_time: Duration::from_millis(100),
+                        },
+                    }],
+                    sync_channel: 0,
+                    ..Default::default()
+                },
+            )
+        },
+        |env| {
+            env.server.world_mut().spawn(SyncMark {});
+            env.clients[0].world_mut().spawn(SyncMark {});
+            2
+        },
+        |env, _, entity_count| {
+            assert::entities_in_sync(env, (), entity_count);
+        },
+    );
+}
+
+/// Hosts at runtime with the config, returning the reason hosting failed.
+fn hosting_failure(run: &TestRun, config: SyncNetworkConfig) -> String {
+    let mut app = new_app();
+    app.insert_resource(config);
+    app.world_mut().send_event(StartHosting {
+        parameters: run.params.clone(),
+    });
+    app.update();
+    app.update();
+    let events = app.world().resource::<Events<SyncStartupError>>();
+    assert_eq!(events.get_reader().read(events).count(), 1);
+    match app.world().resource::<State<ServerState>>().get() {
+        ServerState::Failed(reason) => reason.clone(),
+        state => panic!("hosting did not fail, state is {:?}", state),
+    }
+}
+
+#[test]
+#[serial]
+fn test_sync_channel_must_be_reliable_ordered() {
+    let reason = hosting_failure(
+        &TestRun::memory(),
+        SyncNetworkConfig {
+            sync_channel: 0,
+            ..Default::default()
+        },
+    );
+    assert!(reason.contains("sync_channel"), "{}", reason);
+}
+
+#[test]
+#[serial]
+fn test_asset_channel_must_be_reliable() {
+    let reason = hosting_failure(
+        &TestRun::socket_assets_over_connection(),
+        SyncNetworkConfig {
+            asset_channel: 0,
+            ..Default::default()
+        },
+    );
+    assert!(reason.contains("asset_channel"), "{}", reason);
+}
@@ -0,0 +1,204 @@
+mod setup;
+
+use bevy::prelude::*;
+use bevy_sync::{
+    ClientTransport, PeerIdentity, PeerJoined, PeerLeft, ServerTransport, SyncEntity, SyncMark,
+    SyncPeers, HOST_PEER,
+};
+use serial_test::serial;
+use setup::{TestEnv, TestRun};
+
+#[derive(Resource, Default)]
+struct PeerEvents {
+    joined: Vec<PeerIdentity>,
+    left: Vec<(PeerIdentity, String)>,
+}
+
+fn collect_peer_events(
+    mut joined: EventReader<PeerJoined>,
+    mut left: EventReader<PeerLeft>,
+    mut events: ResMut<PeerEvents>,
+) {
+    events
+        .joined
+        .extend(joined.read().map(|event| event.identity.clone()));
+    events.left.extend(
+        left.read()
+            .map(|event| (event.identity.clone(), event.reason.clone())),
+    );
+}
+
+fn setup_identities(env: &mut TestEnv) -> Vec<PeerIdentity> {
+    env.server.world_mut().resource_mut::<SyncPeers>().local = PeerIdentity::named("host");
+    env.server.init_resource::<PeerEvents>();
+    env.server.add_systems(Update, collect_peer_events);
+    let mut identities = vec![];
+    for (i, client) in env.clients.iter_mut().enumerate() {
+        client.init_resource::<PeerEvents>();
+        client.add_systems(Update, collect_peer_events);
+        let identity = PeerIdentity::named(format!("player{}", i));
+        client.world_mut().resource_mut::<SyncPeers>().local = identity.clone();
+        identities.push(identity);
+    }
+    identities
+}
+
+fn known_identities(app: &App) -> Vec<PeerIdentity> {
+    let mut identities: Vec<PeerIdentity> = app
+        .world()
+        .resource::<SyncPeers>()
+        .peers
+        .values()
+        .map(|peer| peer.identity.clone())
+        .collect();
+    identities.sort_by(|a, b| a.name.cmp(&b.name));
+    identities
+}
+
+/// Identities known by the clients, the host sorting first.
+fn with_host(identities: &[PeerIdentity]) -> Vec<PeerIdentity> {
+    let mut with_host = vec![PeerIdentity::named("host")];
+    with_host.extend(identities.iter().cloned());
+    with_host
+}
+
+#[test]
+fn test_named_identity_is_stable() {
+    assert_eq!(PeerIdentity::named("alice"), PeerIdentity::named("alice"));
+    assert_ne!(PeerIdentity::named("alice"), PeerIdentity::named("bob"));
+    assert_ne!(PeerIdentity::new("alice").id, PeerIdentity::new("alice").id);
+}
+
+#[test]
+#[serial]
+fn test_peers_known_on_host_and_clients() {
+    TestRun::memory().run(
+        2,
+        setup_identities,
+        TestRun::no_setup,
+        |env, identities, _| {
+            // the host is known by the clients, it is not a peer of its own
+            assert_eq!(known_identities(&env.server), identities);
+            let with_host = with_host(&identities);
+            for client in &env.clients {
+                assert_eq!(known_identities(client), with_host);
+                let host = &client.world().resource::<SyncPeers>().peers[&HOST_PEER];
+                assert_eq!(host.identity, with_host[0]);
+            }
+            let events = env.server.world().resource::<PeerEvents>();
+            assert_eq!(events.joined, identities);
+            assert!(events.left.is_empty());
+            let mut joined = env.clients[0]
+                .world()
+                .resource::<PeerEvents>()
+                .joined
+                .clone();
+            joined.sort_by(|a, b| a.name.cmp(&b.name));
+            assert_eq!(joined, with_host);
+        },
+    );
+}
+
+#[test]
+#[serial]
+fn test_peer_leaving_is_removed_everywhere() {
+    TestRun::memory().run(
+        2,
+        setup_identities,
+        TestRun::no_setup,
+        |env, identities, _| {
+            env.clients[1]
+                .world_mut()
+                .remove_resource::<ClientTransport>();
+            env.update(5);
+            assert_eq!(known_identities(&env.server), identities[..1]);
+            assert_eq!(
+                known_identities(&env.clients[0]),
+                with_host(&identities[..1])
+            );
+            let events = env.server.world().resource::<PeerEvents>();
+            assert_eq!(events.left.len(), 1);
+            assert_eq!(events.left[0].0, identities[1]);
+            let reason = events.left[0].1.clone();
+            let events = env.clients[0].world().resource::<PeerEvents>();
+            assert_eq!(events.left, vec![(identities[1].clone(), reason)]);
+        },
+    );
+}
+
+#[test]
+fn test_client_sending_a_malformed_message_is_disconnected() {
+    TestRun::memory().run(
+        2,
+        setup_identities,
+        TestRun::no_setup,
+        |env, identities, _| {
+            env.clients[1]
+                .world_mut()
+                .resource_mut::<ClientTransport>()
+                .send(HOST_PEER, vec![255, 255, 255]);
+            env.update(5);
+            assert_eq!(known_identities(&env.server), identities[..1]);
+            assert_eq!(
+                known_identities(&env.clients[0]),
+                with_host(&identities[..1])
+            );
+        },
+    );
+}
+
+#[test]
+fn test_malformed_message_from_host_is_dropped() {
+    TestRun::memory().run(1, TestRun::no_pre_setup, TestRun::no_setup, |env, _, _| {
+        let mut server = env.server.world_mut().resource_mut::<ServerTransport>();
+        for client_id in server.peers() {
+            server.send(client_id, vec![255, 255, 255]);
+        }
+        env.server.world_mut().spawn(SyncMark);
+        env.update(5);
+        let synched = env.clients[0]
+            .world_mut()
+            .query::<&SyncEntity>()
+            .iter(env.clients[0].world())
+            .count();
+        assert_eq!(synched, 1);
+    });
+}
+
+#[test]
+#[serial]
+fn test_socket_client_id_is_stable_across_reconnections() {
+    let mut client_ids = vec![];
+    for _ in 0..2 {
+        TestRun::socket().run(
+            1,
+            |env| {
+                env.clients[0].world_mut().resource_mut::<SyncPeers>().local =
+                    PeerIdentity::named("returning");
+            },
+            TestRun::no_setup,
+            |env, _, _| {
+                let peers = &env.server.world().resource::<SyncPeers>().peers;
+                client_ids.extend(peers.keys().copied());
+            },
+        );
+    }
+    assert_eq!(client_ids.len(), 2);
+    assert_eq!(client_ids[0], client_ids[1]);
+}
+
+#[test]
+#[serial]
+fn test_peer_network_info_from_transport() {
+    TestRun::socket().run(1, TestRun::no_pre_setup, TestRun::no_setup, |env, _, _| {
+        let server = env.server.world().resource::<ServerTransport>();
+        let peers = &env.server.world().resource::<SyncPeers>().peers;
+        assert_eq!(peers.len(), 1);
+        for (client_id, peer) in peers {
+            assert_eq!(Some(peer.network), server.network_info(*client_id));
+        }
+        let client = env.clients[0].world().resource::<ClientTransport>();
+        let host = &env.clients[0].world().resource::<SyncPeers>().peers[&HOST_PEER];
+        assert_eq!(Some(host.network), client.network_info(HOST_PEER));
+    });
+}
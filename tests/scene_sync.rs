@@ -0,0 +1,324 @@
+mod assert;
+mod setup;
+
+use bevy::{gltf::GltfPlugin, prelude::*, render::primitives::Aabb, scene::ScenePlugin};
+use bevy_sync::{AssetTransferFailed, SyncComponent, SyncEntity, SyncMark};
+use serial_test::serial;
+use setup::{TestEnv, TestRun};
+use std::{thread, time::Duration};
+use uuid::Uuid;
+
+fn setup_scene_sync(app: &mut App) {
+    app.add_plugins((ScenePlugin, GltfPlugin::default()));
+    app.init_asset::<AnimationClip>();
+    // the glTF loader is registered when the plugin is finished, which App::update does not do
+    GltfPlugin::default().finish(app);
+    // the types spawned by the glTF scenes, registered by the plugins missing in the tests
+    app.register_type::<Visibility>()
+        .register_type::<InheritedVisibility>()
+        .register_type::<ViewVisibility>()
+        .register_type::<GlobalTransform>()
+        .register_type::<Children>()
+        .register_type::<Parent>()
+        .register_type::<Aabb>();
+    app.sync_component::<Transform>();
+    app.sync_scenes(true);
+}
+
+fn spawn_cube_scene(env: &mut TestEnv) -> Entity {
+    let scene = load_scene(env, "cube.glb#Scene0".to_string());
+    spawn_scene(env, scene)
+}
+
+fn load_scene(env: &mut TestEnv, path: String) -> Handle<Scene> {
+    let scene = env.server.world().resource::<AssetServer>().load(path);
+    // the glTF file is read in the background, give it the time to load
+    for _ in 0..200 {
+        let asset_server = env.server.world().resource::<AssetServer>();
+        if asset_server.is_loaded_with_dependencies(&scene) {
+            break;
+        }
+        env.update(1);
+        thread::sleep(Duration::from_millis(5));
+    }
+    scene
+}
+
+fn spawn_scene(env: &mut TestEnv, scene: Handle<Scene>) -> Entity {
+    let e_id = env.server.world_mut().spawn(SyncMark).id();
+    env.update(4);
+    env.server
+        .world_mut()
+        .entity_mut(e_id)
+        .insert(SceneBundle { scene, ..default() });
+    e_id
+}
+
+/// Uuids of the entities spawned by the scenes, with their entity.
+fn scene_node_uuids(app: &mut App) -> Vec<(Uuid, Entity)> {
+    let mut uuids: Vec<(Uuid, Entity)> = app
+        .world_mut()
+        .query_filtered::<(Entity, &SyncEntity), Without<Handle<Scene>>>()
+        .iter(app.world())
+        .map(|(e_id, sup)| (sup.uuid, e_id))
+        .collect();
+    uuids.sort();
+    uuids
+}
+
+fn find_node_named(app: &mut App, name: &str) -> Option<(Entity, Uuid)> {
+    app.world_mut()
+        .query::<(Entity, &Name, &SyncEntity)>()
+        .iter(app.world())
+        .find(|(_, n, _)| n.as_str() == name)
+        .map(|(e_id, _, sup)| (e_id, sup.uuid))
+}
+
+#[test]
+#[serial]
+fn test_scene_instanced_on_client_with_same_entity_uuids() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            setup_scene_sync(&mut env.server);
+            setup_scene_sync(&mut env.clients[0]);
+        },
+        |env| {
+            env.update(20);
+            spawn_cube_scene(env);
+        },
+        |env, _, _| {
+            let server_nodes = scene_node_uuids(&mut env.server);
+            let client_nodes = scene_node_uuids(&mut env.clients[0]);
+            assert!(server_nodes.len() >= 2);
+            assert_eq!(
+                server_nodes
+                    .iter()
+                    .map(|(uuid, _)| uuid)
+                    .collect::<Vec<_>>(),
+                client_nodes
+                    .iter()
+                    .map(|(uuid, _)| uuid)
+                    .collect::<Vec<_>>()
+            );
+            // instanced by the client, the scene entities were not sent
+            let cube = find_node_named(&mut env.clients[0], "Cube").unwrap().0;
+            assert!(env.clients[0].world().get::<Children>(cube).is_some());
+        },
+    );
+}
+
+#[test]
+#[serial]
+fn test_scene_entity_change_reaches_the_same_entity_on_client() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            setup_scene_sync(&mut env.server);
+            setup_scene_sync(&mut env.clients[0]);
+        },
+        |env| {
+            env.update(20);
+            spawn_cube_scene(env);
+            env.update(20);
+            let (cube, uuid) = find_node_named(&mut env.server, "Cube").unwrap();
+            env.server
+                .world_mut()
+                .get_mut::<Transform>(cube)
+                .unwrap()
+                .translation = Vec3::new(1., 2., 3.);
+            uuid
+        },
+        |env, _, uuid| {
+            let (cube, client_uuid) = find_node_named(&mut env.clients[0], "Cube").unwrap();
+            assert_eq!(client_uuid, uuid);
+            let transform = env.clients[0].world().get::<Transform>(cube).unwrap();
+            assert_eq!(transform.translation, Vec3::new(1., 2., 3.));
+            assert_eq!(
+                scene_node_uuids(&mut env.server).len(),
+                scene_node_uuids(&mut env.clients[0]).len()
+            );
+        },
+    );
+}
+
+#[test]
+#[serial]
+fn test_scene_changed_before_client_joined_initial_sync() {
+    let run = TestRun::memory();
+    run.run(
+        1,
+        |env| {
+            setup_scene_sync(&mut env.server);
+            setup_scene_sync(&mut env.clients[0]);
+        },
+        |env| {
+            env.update(20);
+            spawn_cube_scene(env);
+            env.update(20);
+            let (cube, uuid) = find_node_named(&mut env.server, "Cube").unwrap();
+            env.server
+                .world_mut()
+                .get_mut::<Transform>(cube)
+                .unwrap()
+                .translation = Vec3::new(1., 2., 3.);
+            env.update(5);
+            let mut joining = setup::new_app();
+            setup_scene_sync(&mut joining);
+            env.join_client(&run, joining);
+            uuid
+        },
+        |env, _, uuid| {
+            let (cube, client_uuid) = find_node_named(&mut env.clients[1], "Cube").unwrap();
+            assert_eq!(client_uuid, uuid);
+            let transform = env.clients[1].world().get::<Transform>(cube).unwrap();
+            assert_eq!(transform.translation, Vec3::new(1., 2., 3.));
+            // the entities received before the scene was instanced were merged into it
+            assert_eq!(
+                scene_node_uuids(&mut env.server).len(),
+                scene_node_uuids(&mut env.clients[1]).len()
+            );
+        },
+    );
+}
+
+#[derive(Resource, Default)]
+struct Failures(Vec<AssetTransferFailed>);
+
+fn collect_failures(mut events: EventReader<AssetTransferFailed>, mut failures: ResMut<Failures>) {
+    failures.0.extend(events.read().cloned());
+}
+
+/// Mesh of the node with the given name, held by one of its children in glTF scenes.
+fn mesh_of_node(app: &mut App, name: &str) -> Option<AssetId<Mesh>> {
+    let node = find_node_named(app, name)?.0;
+    let children = app.world().get::<Children>(node)?;
+    children
+        .iter()
+        .find_map(|&child| app.world().get::<Handle<Mesh>>(child))
+        .map(|mesh| mesh.id())
+}
+
+fn update_until(env: &mut TestEnv, done: impl Fn(&mut TestEnv) -> bool) {
+    for _ in 0..200 {
+        env.update(1);
+        if done(env) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+#[serial]
+fn test_scene_missing_on_client_is_transferred() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            for app in [&mut env.server, &mut env.clients[0]] {
+                setup_scene_sync(app);
+                app.sync_assets_by_path(true);
+            }
+            env.clients[0].init_resource::<Failures>();
+            env.clients[0].add_systems(Update, collect_failures);
+        },
+        |env| {
+            env.update(20);
+            // a scene file only the server had when loading it
+            let file = format!("{}.glb", Uuid::new_v4());
+            let copy = format!("{}/assets/{}", env!("CARGO_MANIFEST_DIR"), file);
+            std::fs::copy(
+                format!("{}/assets/cube.glb", env!("CARGO_MANIFEST_DIR")),
+                &copy,
+            )
+            .unwrap();
+            let scene = load_scene(env, format!("{}#Scene0", file));
+            std::fs::remove_file(&copy).unwrap();
+            spawn_scene(env, scene);
+            update_until(env, |env| {
+                find_node_named(&mut env.clients[0], "Cube").is_some()
+            });
+        },
+        |env, _, _| {
+            assert!(env.clients[0].world().resource::<Failures>().0.is_empty());
+            let server_nodes = scene_node_uuids(&mut env.server);
+            let client_nodes = scene_node_uuids(&mut env.clients[0]);
+            assert!(server_nodes.len() >= 2);
+            assert_eq!(
+                server_nodes
+                    .iter()
+                    .map(|(uuid, _)| uuid)
+                    .collect::<Vec<_>>(),
+                client_nodes
+                    .iter()
+                    .map(|(uuid, _)| uuid)
+                    .collect::<Vec<_>>()
+            );
+        },
+    );
+}
+
+#[test]
+#[serial]
+fn test_transferred_scene_brings_its_meshes() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            for app in [&mut env.server, &mut env.clients[0]] {
+                setup_scene_sync(app);
+                app.sync_meshes(true);
+            }
+        },
+        |env| {
+            env.update(20);
+            spawn_cube_scene(env);
+            update_until(env, |env| {
+                mesh_of_node(&mut env.clients[0], "Cube").is_some_and(|mesh| {
+                    env.clients[0]
+                        .world()
+                        .resource::<Assets<Mesh>>()
+                        .contains(mesh)
+                })
+            });
+        },
+        |env, _, _| {
+            let server_mesh = mesh_of_node(&mut env.server, "Cube").unwrap();
+            let client_mesh = mesh_of_node(&mut env.clients[0], "Cube").unwrap();
+            let server_meshes = env.server.world().resource::<Assets<Mesh>>();
+            let client_meshes = env.clients[0].world().resource::<Assets<Mesh>>();
+            assert_eq!(
+                client_meshes.get(client_mesh).unwrap().count_vertices(),
+                server_meshes.get(server_mesh).unwrap().count_vertices()
+            );
+        },
+    );
+}
+
+#[test]
+#[serial]
+fn test_scene_loaded_from_client_files_when_synched_by_path() {
+    TestRun::memory().run(
+        1,
+        |env| {
+            for app in [&mut env.server, &mut env.clients[0]] {
+                setup_scene_sync(app);
+                app.sync_assets_by_path(true);
+            }
+        },
+        |env| {
+            env.update(20);
+            spawn_cube_scene(env);
+            update_until(env, |env| {
+                find_node_named(&mut env.clients[0], "Cube").is_some()
+            });
+        },
+        |env, _, _| {
+            // meshes are not synched, the client has them from its own glTF file
+            let mesh = mesh_of_node(&mut env.clients[0], "Cube").unwrap();
+            assert!(env.clients[0]
+                .world()
+                .resource::<Assets<Mesh>>()
+                .contains(mesh));
+        },
+    );
+}
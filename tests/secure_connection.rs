@@ -0,0 +1,75 @@
+mod assert;
+mod setup;
+
+use bevy_sync::{ClientState, SyncConnectionParameters, SyncMark};
+use serial_test::serial;
+use setup::TestRun;
+
+#[test]
+#[serial]
+fn test_client_with_valid_token_joins() {
+    TestRun::secure(300).run(
+        1,
+        TestRun::no_pre_setup,
+        |env| {
+            env.server.world_mut().spawn(SyncMark {});
+            1
+        },
+        assert::entities_in_sync,
+    );
+}
+
+#[test]
+#[serial]
+fn test_client_with_expired_token_fails() {
+    let state = TestRun::secure(0).run_refused();
+    assert_eq!(
+        state,
+        ClientState::Failed("connection token has expired".to_string())
+    );
+}
+
+#[test]
+#[serial]
+fn test_client_with_invalid_token_fails() {
+    let mut run = TestRun::secure(300);
+    if let SyncConnectionParameters::SecureSocket {
+        ref mut connect_token,
+        ..
+    } = run.params
+    {
+        connect_token.truncate(10);
+    }
+    let state = run.run_refused();
+    assert!(
+        matches!(state, ClientState::Failed(reason) if reason.starts_with("invalid connect token"))
+    );
+}
+
+#[test]
+fn test_secrets_are_not_printed_nor_serialized() {
+    let params = TestRun::secure(300).params;
+    let SyncConnectionParameters::SecureSocket {
+        private_key,
+        connect_token,
+        ..
+    } = &params
+    else {
+        panic!("not a secure session");
+    };
+    let printed = format!("{:?}", params);
+    assert!(!printed.contains(&format!("{:?}", private_key)));
+    assert!(!printed.contains(&format!("{:?}", connect_token)));
+
+    let bytes = bincode::serialize(&params).unwrap();
+    let SyncConnectionParameters::SecureSocket {
+        private_key,
+        connect_token,
+        ..
+    } = bincode::deserialize(&bytes).unwrap()
+    else {
+        panic!("not a secure session");
+    };
+    assert_eq!(private_key, None);
+    assert!(connect_token.is_empty());
+}
@@ -0,0 +1,130 @@
+mod setup;
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_sync::{
+    ClientState, ClientTransport, JoinSession, LeaveSession, ServerState, ServerTransport,
+    StartHosting, SyncConnectionParameters, SyncEntity, SyncMark, SyncPeers,
+};
+use serial_test::serial;
+use setup::{new_app, TestRun};
+
+fn update_until(apps: &mut [&mut App], mut condition: impl FnMut(&mut [&mut App]) -> bool) {
+    for _ in 0..200 {
+        for app in apps.iter_mut() {
+            app.update();
+        }
+        if condition(apps) {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    panic!("condition not reached in time");
+}
+
+fn server_state(app: &App) -> &ServerState {
+    app.world().resource::<State<ServerState>>().get()
+}
+
+fn client_state(app: &App) -> &ClientState {
+    app.world().resource::<State<ClientState>>().get()
+}
+
+fn peer_count(app: &App) -> usize {
+    app.world().resource::<SyncPeers>().peers.len()
+}
+
+fn synched_entities(app: &mut App) -> usize {
+    app.world_mut()
+        .query::<&SyncEntity>()
+        .iter(app.world())
+        .count()
+}
+
+fn host(app: &mut App, parameters: SyncConnectionParameters) {
+    app.world_mut().send_event(StartHosting { parameters });
+}
+
+fn join(app: &mut App, parameters: SyncConnectionParameters) {
+    app.world_mut().send_event(JoinSession { parameters });
+}
+
+fn leave(app: &mut App) {
+    app.world_mut().send_event(LeaveSession);
+}
+
+#[test]
+#[serial]
+fn test_host_and_join_at_runtime() {
+    let params = TestRun::memory().params;
+    let mut server = new_app();
+    let mut client = new_app();
+    host(&mut server, params.clone());
+    join(&mut client, params);
+    update_until(&mut [&mut server, &mut client], |apps| {
+        client_state(apps[1]) == &ClientState::Connected && peer_count(apps[0]) == 1
+    });
+
+    server.world_mut().spawn(SyncMark);
+    update_until(&mut [&mut server, &mut client], |apps| {
+        synched_entities(apps[1]) == 1
+    });
+
+    leave(&mut client);
+    update_until(&mut [&mut server, &mut client], |apps| {
+        client_state(apps[1]) == &ClientState::Disconnected && peer_count(apps[0]) == 0
+    });
+    assert!(client.world().get_resource::<ClientTransport>().is_none());
+    assert_eq!(synched_entities(&mut client), 1);
+
+    leave(&mut server);
+    update_until(&mut [&mut server], |apps| {
+        server_state(apps[0]) == &ServerState::Disconnected
+    });
+    assert!(server.world().get_resource::<ServerTransport>().is_none());
+}
+
+#[test]
+#[serial]
+fn test_host_again_on_the_same_ports_after_leaving() {
+    let params = TestRun::socket().params;
+    let mut server = new_app();
+    for _ in 0..2 {
+        host(&mut server, params.clone());
+        update_until(&mut [&mut server], |apps| {
+            server_state(apps[0]) == &ServerState::Connected
+        });
+        leave(&mut server);
+        update_until(&mut [&mut server], |apps| {
+            server_state(apps[0]) == &ServerState::Disconnected
+        });
+    }
+}
+
+#[test]
+#[serial]
+fn test_client_moves_between_sessions() {
+    let first = TestRun::memory().params;
+    let second = TestRun::memory().params;
+    let mut first_host = new_app();
+    let mut second_host = new_app();
+    let mut client = new_app();
+    host(&mut first_host, first.clone());
+    host(&mut second_host, second.clone());
+    join(&mut client, first);
+    update_until(
+        &mut [&mut first_host, &mut second_host, &mut client],
+        |apps| client_state(apps[2]) == &ClientState::Connected && peer_count(apps[0]) == 1,
+    );
+
+    join(&mut client, second);
+    update_until(
+        &mut [&mut first_host, &mut second_host, &mut client],
+        |apps| {
+            peer_count(apps[0]) == 0
+                && peer_count(apps[1]) == 1
+                && client_state(apps[2]) == &ClientState::Connected
+        },
+    );
+}
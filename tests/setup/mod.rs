@@ -1,8 +1,11 @@
+// each test binary uses only part of the helpers
+#![allow(dead_code)]
+
 use std::{
     env,
     error::Error,
     fmt::Display,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
 };
 
 use bevy::{
@@ -18,11 +21,11 @@ use bevy::{
     state::app::StatesPlugin,
     MinimalPlugins,
 };
-use bevy_renet::renet::{
-    transport::{NetcodeClientTransport, NetcodeServerTransport},
-    RenetClient,
+use bevy_sync::{
+    generate_connect_token, generate_private_key, AssetTransport, ClientPlugin, ClientState,
+    ClientTransport, ServerPlugin, ServerTransport, SyncComponent, SyncConnectionParameters,
+    SyncNetworkConfig, SyncPlugin,
 };
-use bevy_sync::{ClientPlugin, ServerPlugin, SyncComponent, SyncConnectionParameters, SyncPlugin};
 use portpicker::pick_unused_port;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -89,6 +92,16 @@ impl TestEnv {
             c.sync_component::<T>();
         }
     }
+
+    /// Connects one more client to the session of run, joining after the others.
+    pub(crate) fn join_client(&mut self, run: &TestRun, mut capp: App) {
+        capp.add_plugins(ClientPlugin {
+            parameters: client_params(&run.params),
+            identity: None,
+        });
+        wait_until_connected(&mut self.server, &mut capp, run.startup_max_wait_updates).unwrap();
+        self.clients.push(capp);
+    }
 }
 
 impl Default for TestRun {
@@ -99,6 +112,7 @@ impl Default for TestRun {
                 ip: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
                 web_port: pick_unused_port().unwrap(),
                 max_transfer: 100_000_000,
+                asset_transport: AssetTransport::Http,
             },
             startup_max_wait_updates: 20,
             updates_per_run: 20,
@@ -107,6 +121,116 @@ impl Default for TestRun {
 }
 
 impl TestRun {
+    /// Runs over real UDP and HTTP sockets on localhost, as the default run does.
+    pub(crate) fn socket() -> Self {
+        Self::default()
+    }
+
+    /// Runs in memory, without binding any socket.
+    pub(crate) fn memory() -> Self {
+        Self {
+            params: SyncConnectionParameters::InMemory {
+                name: Uuid::new_v4().to_string(),
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Runs over UDP on localhost, sending the assets over the same connection.
+    pub(crate) fn socket_assets_over_connection() -> Self {
+        let mut run = Self::socket();
+        if let SyncConnectionParameters::Socket {
+            asset_transport, ..
+        } = &mut run.params
+        {
+            *asset_transport = AssetTransport::Connection;
+        }
+        run
+    }
+
+    /// Runs over a WebSocket on localhost.
+    pub(crate) fn websocket() -> Self {
+        Self {
+            params: SyncConnectionParameters::WebSocket {
+                url: format!(
+                    "ws://127.0.0.1:{}",
+                    pick_unused_port().expect("No ports free")
+                ),
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Runs over UDP with secure netcode, the client holds a token valid for expire_seconds.
+    pub(crate) fn secure(expire_seconds: u64) -> Self {
+        Self::secure_promotable(expire_seconds, 0)
+    }
+
+    /// Same as secure, the token being valid as well for the next promotable_ports ports that
+    /// promoted clients host on.
+    pub(crate) fn secure_promotable(expire_seconds: u64, promotable_ports: u16) -> Self {
+        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let port = pick_unused_port().expect("No ports free");
+        let private_key = generate_private_key();
+        let connect_token = generate_connect_token(
+            &private_key,
+            SyncNetworkConfig::default().protocol_id,
+            1,
+            (port..=port + promotable_ports)
+                .map(|port| SocketAddr::new(ip, port))
+                .collect(),
+            expire_seconds,
+        )
+        .unwrap();
+        Self {
+            params: SyncConnectionParameters::SecureSocket {
+                ip,
+                port,
+                web_port: pick_unused_port().unwrap(),
+                max_transfer: 100_000_000,
+                asset_transport: AssetTransport::Http,
+                private_key: Some(private_key),
+                connect_token,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Connects a single client that is expected to be refused, returns its final state.
+    pub(crate) fn run_refused(&self) -> ClientState {
+        let mut sapp = create_server().unwrap();
+        let mut capp = create_client().unwrap();
+        sapp.add_plugins(ServerPlugin {
+            parameters: self.params.clone(),
+        });
+        capp.add_plugins(ClientPlugin {
+            parameters: client_params(&self.params),
+            identity: None,
+        });
+        let mut state = ClientState::Disconnected;
+        for _ in 0..self.startup_max_wait_updates {
+            sapp.update();
+            capp.update();
+            state = capp.world().resource::<State<ClientState>>().get().clone();
+            if matches!(state, ClientState::Failed(_) | ClientState::Connected) {
+                break;
+            }
+        }
+        disconnect(&mut sapp);
+        disconnect(&mut capp);
+        state
+    }
+
+    /// Starts only the host, returning its app after the first update.
+    pub(crate) fn host_only(&self) -> App {
+        let mut sapp = create_server().unwrap();
+        sapp.add_plugins(ServerPlugin {
+            parameters: self.params.clone(),
+        });
+        sapp.update();
+        sapp
+    }
+
     #[allow(dead_code)]
     pub(crate) fn no_pre_setup(_: &mut TestEnv) {}
 
@@ -156,8 +280,8 @@ impl TestRun {
 }
 
 fn disconnect(app: &mut App) {
-    app.world_mut().remove_resource::<NetcodeServerTransport>();
-    app.world_mut().remove_resource::<NetcodeClientTransport>();
+    app.world_mut().remove_resource::<ServerTransport>();
+    app.world_mut().remove_resource::<ClientTransport>();
     for _ in 0..10 {
         app.update();
     }
@@ -165,19 +289,31 @@ fn disconnect(app: &mut App) {
 
 fn create_server() -> Result<App, Box<dyn Error>> {
     let mut sapp = App::new();
-    add_plugins(&mut sapp);
+    add_plugins(&mut sapp, SyncPlugin::default());
     // Start a non synched entity only on server so the id is intentionally offseted between server and client
     sapp.world_mut().spawn(TransformBundle::default());
     Ok(sapp)
 }
 
+/// App with the test plugins and SyncPlugin, before any host or client plugin.
+pub(crate) fn new_app() -> App {
+    create_client().unwrap()
+}
+
+/// App with the test plugins and the given SyncPlugin, before any host or client plugin.
+pub(crate) fn new_app_with(plugin: SyncPlugin) -> App {
+    let mut app = App::new();
+    add_plugins(&mut app, plugin);
+    app
+}
+
 fn create_client() -> Result<App, Box<dyn Error>> {
     let mut capp = App::new();
-    add_plugins(&mut capp);
+    add_plugins(&mut capp, SyncPlugin::default());
     Ok(capp)
 }
 
-fn add_plugins(app: &mut App) {
+fn add_plugins(app: &mut App, plugin: SyncPlugin) {
     app.add_plugins(MinimalPlugins);
     app.add_plugins(StatesPlugin);
     app.add_plugins(AssetPlugin::default());
@@ -195,7 +331,7 @@ fn add_plugins(app: &mut App) {
         });
     }
 
-    app.add_plugins(SyncPlugin);
+    app.add_plugins(plugin);
 }
 
 fn connect_envs(env: &TestRun, sapp: &mut App, capps: &mut [App]) -> Result<(), Box<dyn Error>> {
@@ -204,18 +340,11 @@ fn connect_envs(env: &TestRun, sapp: &mut App, capps: &mut [App]) -> Result<(),
     });
 
     for capp in capps {
-        let mut newenv = env.params.clone();
-        match newenv {
-            SyncConnectionParameters::Socket {
-                ip: _,
-                port: _,
-                ref mut web_port,
-                max_transfer: _,
-            } => {
-                *web_port = pick_unused_port().unwrap();
-            }
-        }
-        capp.add_plugins(ClientPlugin { parameters: newenv });
+        let newenv = client_params(&env.params);
+        capp.add_plugins(ClientPlugin {
+            parameters: newenv,
+            identity: None,
+        });
 
         wait_until_connected(sapp, capp, env.startup_max_wait_updates)?;
     }
@@ -223,6 +352,29 @@ fn connect_envs(env: &TestRun, sapp: &mut App, capps: &mut [App]) -> Result<(),
     Ok(())
 }
 
+fn client_params(params: &SyncConnectionParameters) -> SyncConnectionParameters {
+    let mut params = params.clone();
+    match params {
+        SyncConnectionParameters::Socket {
+            ip: _,
+            port: _,
+            ref mut web_port,
+            max_transfer: _,
+            asset_transport: _,
+        } => {
+            *web_port = 0;
+        }
+        SyncConnectionParameters::SecureSocket {
+            ref mut web_port, ..
+        } => {
+            *web_port = 0;
+        }
+        SyncConnectionParameters::InMemory { name: _ } => {}
+        SyncConnectionParameters::WebSocket { url: _ } => {}
+    }
+    params
+}
+
 fn wait_until_connected(
     sapp: &mut App,
     capp: &mut App,
@@ -235,7 +387,7 @@ fn wait_until_connected(
     while count < updates {
         sapp.update();
         capp.update();
-        if !capp.world().resource::<RenetClient>().is_disconnected() {
+        if !capp.world().resource::<ClientTransport>().is_disconnected() {
             return Ok(());
         }
         count += 1;
@@ -0,0 +1,106 @@
+mod setup;
+
+use std::net::{TcpListener, UdpSocket};
+
+use bevy::{ecs::event::Events, prelude::*};
+use bevy_sync::{ServerState, ServerTransport, SyncConnectionParameters, SyncStartupError};
+use serial_test::serial;
+use setup::TestRun;
+
+fn startup_errors(app: &App) -> Vec<String> {
+    let events = app.world().resource::<Events<SyncStartupError>>();
+    events
+        .get_reader()
+        .read(events)
+        .map(|e| e.reason.clone())
+        .collect()
+}
+
+fn assert_hosting_failed(app: &App) {
+    assert!(matches!(
+        app.world().resource::<State<ServerState>>().get(),
+        ServerState::Failed(_)
+    ));
+    assert!(app.world().get_resource::<ServerTransport>().is_none());
+    assert_eq!(startup_errors(app).len(), 1);
+}
+
+#[test]
+#[serial]
+fn test_hosting_on_busy_port_fails() {
+    let busy = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let mut run = TestRun::socket();
+    let mut web_port = 0;
+    if let SyncConnectionParameters::Socket {
+        port,
+        web_port: run_web_port,
+        ..
+    } = &mut run.params
+    {
+        *port = busy.local_addr().unwrap().port();
+        web_port = *run_web_port;
+    }
+    let app = run.host_only();
+    assert_hosting_failed(&app);
+    // the asset server started before the session failed is closed as well
+    TcpListener::bind(("127.0.0.1", web_port)).unwrap();
+}
+
+#[test]
+#[serial]
+fn test_hosting_on_busy_web_port_fails() {
+    let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut run = TestRun::socket();
+    if let SyncConnectionParameters::Socket { web_port, .. } = &mut run.params {
+        *web_port = busy.local_addr().unwrap().port();
+    }
+    assert_hosting_failed(&run.host_only());
+}
+
+#[test]
+#[serial]
+fn test_hosting_on_port_zero_publishes_bound_ports() {
+    let mut run = TestRun::socket();
+    if let SyncConnectionParameters::Socket { port, web_port, .. } = &mut run.params {
+        *port = 0;
+        *web_port = 0;
+    }
+    let mut app = run.host_only();
+    app.update();
+    assert_eq!(
+        app.world().resource::<State<ServerState>>().get(),
+        &ServerState::Connected
+    );
+    let SyncConnectionParameters::Socket { port, web_port, .. } =
+        app.world().resource::<SyncConnectionParameters>()
+    else {
+        panic!("parameters changed kind");
+    };
+    assert_ne!(*port, 0);
+    assert_ne!(*web_port, 0);
+    assert!(UdpSocket::bind(("127.0.0.1", *port)).is_err());
+    assert!(TcpListener::bind(("127.0.0.1", *web_port)).is_err());
+    assert!(startup_errors(&app).is_empty());
+}
+
+#[test]
+#[serial]
+fn test_websocket_hosting_on_port_zero_publishes_url() {
+    let mut run = TestRun::websocket();
+    run.params = SyncConnectionParameters::WebSocket {
+        url: "ws://127.0.0.1:0".to_string(),
+    };
+    let app = run.host_only();
+    let SyncConnectionParameters::WebSocket { url } =
+        app.world().resource::<SyncConnectionParameters>()
+    else {
+        panic!("parameters changed kind");
+    };
+    let port: u16 = url
+        .trim_start_matches("ws://127.0.0.1:")
+        .trim_end_matches('/')
+        .parse()
+        .unwrap();
+    assert_ne!(port, 0);
+    assert!(TcpListener::bind(("127.0.0.1", port)).is_err());
+}